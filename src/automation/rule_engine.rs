@@ -0,0 +1,328 @@
+use crate::commands::executor::{CommandExecutor, OpenClose};
+use crate::config::alias::AliasManager;
+use crate::config::rules::{Comparison, Condition, Rule, RulesFile, TimeWindow};
+use crate::events::fetch_device_events::DeviceEvent;
+use crate::events::recording::RecordedEvent;
+use crate::utils::poller::PollerConfig;
+use anyhow::Context;
+use chrono::{DateTime, Local, NaiveTime};
+use log::{error, info, warn};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use tokio::time::sleep;
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct RuleMatch {
+    pub timestamp: DateTime<Local>,
+    pub rule: String,
+    pub event: String,
+    pub device: Option<String>,
+    pub action: OpenClose,
+    pub devices: Vec<String>,
+}
+
+pub(crate) type RuleMatches = Vec<RuleMatch>;
+
+/// Evaluates rules against device events. Aliases are resolved once on creation, unknown tags fail it.
+pub(crate) struct RuleEngine {
+    rules: Vec<Rule>,
+    last_fired: HashMap<String, DateTime<Local>>,
+}
+
+impl RuleEngine {
+    pub(crate) fn new(rules_file: RulesFile, alias_manager: &AliasManager) -> anyhow::Result<Self> {
+        let rules = rules_file
+            .rules
+            .into_iter()
+            .map(|mut rule| {
+                rule.device = rule.device.map(|d| alias_manager.resolve_alias(&d));
+                rule.devices = rule
+                    .devices
+                    .iter()
+                    .map(|d| alias_manager.resolve_aliases(d))
+                    .collect::<anyhow::Result<Vec<_>>>()
+                    .context(format!("Invalid devices of rule '{}'", rule.name))?
+                    .concat();
+                Ok(rule)
+            })
+            .collect::<anyhow::Result<_>>()?;
+
+        Ok(Self {
+            rules,
+            last_fired: HashMap::new(),
+        })
+    }
+
+    /// Returns all rules triggered by the event, honoring time windows and debouncing
+    pub(crate) fn evaluate(&mut self, event: &DeviceEvent, at: DateTime<Local>) -> RuleMatches {
+        let mut matches = Vec::new();
+        for rule in &self.rules {
+            if !rule_matches(rule, event, at.time()) {
+                continue;
+            }
+
+            if let Some(last) = self.last_fired.get(&rule.name) {
+                if (at - *last).num_seconds() < rule.debounce_seconds as i64 {
+                    continue;
+                }
+            }
+
+            self.last_fired.insert(rule.name.clone(), at);
+            matches.push(RuleMatch {
+                timestamp: at,
+                rule: rule.name.clone(),
+                event: event.name.clone(),
+                device: event.device_url.clone(),
+                action: rule.action,
+                devices: rule.devices.clone(),
+            });
+        }
+        matches
+    }
+
+    /// Evaluates a recorded event stream without executing any action
+    pub(crate) fn replay(&mut self, recording: &[RecordedEvent]) -> RuleMatches {
+        recording
+            .iter()
+            .flat_map(|r| self.evaluate(&r.event, r.timestamp.with_timezone(&Local)))
+            .collect()
+    }
+}
+
+fn rule_matches(rule: &Rule, event: &DeviceEvent, time: NaiveTime) -> bool {
+    if rule.event != event.name {
+        return false;
+    }
+    if rule.device.is_some() && rule.device != event.device_url {
+        return false;
+    }
+    if let Some(window) = &rule.time_window {
+        if !in_window(window, time) {
+            return false;
+        }
+    }
+    rule.conditions.iter().all(|c| condition_matches(c, event))
+}
+
+fn in_window(window: &TimeWindow, time: NaiveTime) -> bool {
+    if window.from <= window.to {
+        window.from <= time && time <= window.to
+    } else {
+        time >= window.from || time <= window.to
+    }
+}
+
+fn condition_matches(condition: &Condition, event: &DeviceEvent) -> bool {
+    let Some(value) = event.state(&condition.state) else {
+        return false;
+    };
+
+    match &condition.comparison {
+        Comparison::Eq(expected) => values_equal(value, expected),
+        Comparison::Ne(expected) => !values_equal(value, expected),
+        Comparison::Gt(n) => as_number(value).is_some_and(|v| v > *n),
+        Comparison::Gte(n) => as_number(value).is_some_and(|v| v >= *n),
+        Comparison::Lt(n) => as_number(value).is_some_and(|v| v < *n),
+        Comparison::Lte(n) => as_number(value).is_some_and(|v| v <= *n),
+    }
+}
+
+/// Event states are usually transmitted as strings, so numbers are parsed leniently
+fn as_number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => s.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+fn as_plain_string(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+fn values_equal(actual: &Value, expected: &Value) -> bool {
+    match (as_number(actual), as_number(expected)) {
+        (Some(a), Some(b)) => a == b,
+        _ => as_plain_string(actual) == as_plain_string(expected),
+    }
+}
+
+async fn fire(rule_match: &RuleMatch, executor: &CommandExecutor) {
+    match executor
+        .apply(rule_match.devices.clone(), rule_match.action)
+        .await
+    {
+        Ok(resp) => info!(
+            "Rule '{}' fired ({}): exec {}",
+            rule_match.rule, rule_match.action, resp.exec_id
+        ),
+        Err(e) => error!("Rule '{}' failed: {e}", rule_match.rule),
+    }
+}
+
+/// Listens for device events and executes matching rules until the process is stopped.
+/// An expired or lost event listener is registered again.
+pub(crate) async fn run(engine: &mut RuleEngine, executor: &CommandExecutor) -> anyhow::Result<()> {
+    let poller_config = PollerConfig::EVENT_LISTENER;
+    let mut listener_id = executor.register_event_listener().await?;
+    info!("Evaluating rules against events of listener {listener_id}");
+
+    loop {
+        sleep(poller_config.refresh_interval).await;
+        match executor.fetch_device_events(&listener_id).await {
+            Ok(events) => {
+                for event in events {
+                    for rule_match in engine.evaluate(&event, Local::now()) {
+                        fire(&rule_match, executor).await;
+                    }
+                }
+            }
+            Err(e) => {
+                warn!("Fetching events failed: {e}. Registering a new event listener");
+                match executor.register_event_listener().await {
+                    Ok(id) => listener_id = id,
+                    Err(e) => warn!("Registering event listener failed: {e}"),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::rules::Condition;
+    use chrono::TimeZone;
+    use serde_json::json;
+
+    fn engine(rules: Vec<Rule>) -> RuleEngine {
+        RuleEngine {
+            rules,
+            last_fired: HashMap::new(),
+        }
+    }
+
+    fn wind_rule() -> Rule {
+        Rule {
+            name: "wind".to_string(),
+            event: "DeviceStateChangedEvent".to_string(),
+            device: Some("io://1/wind".to_string()),
+            conditions: vec![Condition {
+                state: "core:WindSpeedState".to_string(),
+                comparison: Comparison::Gt(40.0),
+            }],
+            time_window: None,
+            debounce_seconds: 60,
+            action: OpenClose::Open,
+            devices: vec!["io://1/awning".to_string()],
+        }
+    }
+
+    fn wind_event(speed: &str) -> DeviceEvent {
+        serde_json::from_value(json!({
+            "name": "DeviceStateChangedEvent",
+            "deviceURL": "io://1/wind",
+            "deviceStates": [{"name": "core:WindSpeedState", "type": 2, "value": speed}]
+        }))
+        .unwrap()
+    }
+
+    fn at(h: u32, m: u32, s: u32) -> DateTime<Local> {
+        Local.with_ymd_and_hms(2024, 6, 21, h, m, s).unwrap()
+    }
+
+    #[test]
+    fn test_condition_on_string_encoded_number() {
+        let mut engine = engine(vec![wind_rule()]);
+
+        assert!(engine
+            .evaluate(&wind_event("12.5"), at(12, 0, 0))
+            .is_empty());
+        let matches = engine.evaluate(&wind_event("41.3"), at(12, 0, 1));
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].devices, vec!["io://1/awning".to_string()]);
+    }
+
+    #[test]
+    fn test_debounce_suppresses_repeated_firing() {
+        let mut engine = engine(vec![wind_rule()]);
+
+        assert_eq!(engine.evaluate(&wind_event("50"), at(12, 0, 0)).len(), 1);
+        assert!(engine.evaluate(&wind_event("55"), at(12, 0, 30)).is_empty());
+        assert_eq!(engine.evaluate(&wind_event("60"), at(12, 1, 0)).len(), 1);
+    }
+
+    #[test]
+    fn test_other_device_does_not_match() {
+        let mut engine = engine(vec![wind_rule()]);
+        let mut event = wind_event("80");
+        event.device_url = Some("io://1/other".to_string());
+
+        assert!(engine.evaluate(&event, at(12, 0, 0)).is_empty());
+    }
+
+    #[test]
+    fn test_time_window_spanning_midnight() {
+        let window = TimeWindow {
+            from: NaiveTime::from_hms_opt(22, 0, 0).unwrap(),
+            to: NaiveTime::from_hms_opt(6, 0, 0).unwrap(),
+        };
+
+        assert!(in_window(
+            &window,
+            NaiveTime::from_hms_opt(23, 30, 0).unwrap()
+        ));
+        assert!(in_window(
+            &window,
+            NaiveTime::from_hms_opt(5, 0, 0).unwrap()
+        ));
+        assert!(!in_window(
+            &window,
+            NaiveTime::from_hms_opt(12, 0, 0).unwrap()
+        ));
+    }
+
+    #[test]
+    fn test_eq_condition_on_string_state() {
+        let condition = Condition {
+            state: "core:ContactState".to_string(),
+            comparison: Comparison::Eq(Value::from("open")),
+        };
+        let event: DeviceEvent = serde_json::from_value(json!({
+            "name": "DeviceStateChangedEvent",
+            "deviceURL": "io://1/door",
+            "deviceStates": [{"name": "core:ContactState", "type": 3, "value": "open"}]
+        }))
+        .unwrap();
+
+        assert!(condition_matches(&condition, &event));
+    }
+
+    #[test]
+    fn test_replay_uses_recorded_timestamps() {
+        let mut engine = engine(vec![wind_rule()]);
+        let recording = vec![
+            RecordedEvent {
+                timestamp: at(12, 0, 0).to_utc(),
+                event: wind_event("50"),
+            },
+            RecordedEvent {
+                timestamp: at(12, 0, 10).to_utc(),
+                event: wind_event("50"),
+            },
+            RecordedEvent {
+                timestamp: at(12, 5, 0).to_utc(),
+                event: wind_event("50"),
+            },
+        ];
+
+        let matches = engine.replay(&recording);
+
+        assert_eq!(matches.len(), 2);
+        assert_eq!(matches[1].timestamp, at(12, 5, 0));
+    }
+}
@@ -0,0 +1,257 @@
+use crate::automation::sun::sun_times;
+use crate::commands::executor::CommandExecutor;
+use crate::config::alias::AliasManager;
+use crate::config::schedule::{Location, ScheduleEntry, ScheduleFile, SunEvent, Trigger};
+use chrono::{DateTime, Duration, Local};
+use log::{error, info, warn};
+use serde::Serialize;
+use std::time::Duration as StdDuration;
+use tokio::time::sleep;
+
+/// Upper bound for a single sleep, so that suspend/resume and clock changes are picked up
+const MAX_SLEEP: StdDuration = StdDuration::from_secs(60);
+/// How many days to look ahead for a sunrise/sunset, covers polar day and night
+const SUN_LOOKAHEAD_DAYS: i64 = 366;
+/// Fire times missed by more than this, e.g. while suspended, are skipped instead of caught up
+const MISSED_FIRE_GRACE: Duration = Duration::minutes(5);
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct ScheduleOverviewEntry {
+    pub name: String,
+    pub trigger: String,
+    pub action: String,
+    pub devices: Vec<String>,
+    pub next_fire: Option<DateTime<Local>>,
+}
+
+pub(crate) type ScheduleOverview = Vec<ScheduleOverviewEntry>;
+
+/// Returns the first fire time of the entry strictly after `after`
+pub(crate) fn next_fire(
+    entry: &ScheduleEntry,
+    location: Option<&Location>,
+    after: DateTime<Local>,
+) -> anyhow::Result<Option<DateTime<Local>>> {
+    match &entry.trigger {
+        Trigger::Cron(expr) => Ok(Trigger::cron_schedule(expr)?.after(&after).next()),
+        Trigger::Sun {
+            event,
+            offset_minutes,
+        } => {
+            let location = location.ok_or(anyhow::anyhow!(
+                "Schedule entry '{}' requires a location",
+                entry.name
+            ))?;
+            let start = after.date_naive() - Duration::days(1);
+            for day in 0..SUN_LOOKAHEAD_DAYS {
+                let date = start + Duration::days(day);
+                let Some(times) = sun_times(date, location.latitude, location.longitude) else {
+                    continue;
+                };
+                let base = match event {
+                    SunEvent::Sunrise => times.sunrise,
+                    SunEvent::Sunset => times.sunset,
+                };
+                let fire = (base + Duration::minutes(*offset_minutes)).with_timezone(&Local);
+                if fire > after {
+                    return Ok(Some(fire));
+                }
+            }
+            Ok(None)
+        }
+    }
+}
+
+pub(crate) fn overview(
+    schedule: &ScheduleFile,
+    now: DateTime<Local>,
+) -> anyhow::Result<ScheduleOverview> {
+    schedule
+        .entries
+        .iter()
+        .map(|entry| {
+            Ok(ScheduleOverviewEntry {
+                name: entry.name.clone(),
+                trigger: entry.trigger.to_string(),
+                action: entry.action.to_string(),
+                devices: entry.targets(),
+                next_fire: next_fire(entry, schedule.location.as_ref(), now)?,
+            })
+        })
+        .collect()
+}
+
+async fn fire(entry: &ScheduleEntry, executor: &CommandExecutor, alias_manager: &AliasManager) {
+    let device_urls = entry
+        .targets()
+        .iter()
+        .map(|d| alias_manager.resolve_aliases(d))
+        .collect::<anyhow::Result<Vec<_>>>();
+    let device_urls = match device_urls {
+        Ok(device_urls) => device_urls.concat(),
+        Err(e) => {
+            error!("Failed to fire '{}': {e:#}", entry.name);
+            return;
+        }
+    };
+
+    match executor.apply(device_urls, entry.action).await {
+        Ok(resp) => info!(
+            "Fired '{}' ({}): exec {}",
+            entry.name, entry.action, resp.exec_id
+        ),
+        Err(e) => error!("Failed to fire '{}': {e}", entry.name),
+    }
+}
+
+/// Runs the scheduler in the foreground until the process is stopped.
+/// Failing executions are logged and do not stop the scheduler.
+pub(crate) async fn run(
+    schedule: &ScheduleFile,
+    executor: &CommandExecutor,
+    alias_manager: &AliasManager,
+) -> anyhow::Result<()> {
+    let mut after = Local::now();
+    loop {
+        let mut upcoming = Vec::new();
+        for entry in &schedule.entries {
+            if let Some(at) = next_fire(entry, schedule.location.as_ref(), after)? {
+                upcoming.push((at, entry));
+            }
+        }
+
+        let Some(next) = upcoming.iter().map(|(at, _)| *at).min() else {
+            anyhow::bail!("No schedule entry has an upcoming fire time");
+        };
+        info!("Next schedule fire at {next}");
+
+        while Local::now() < next {
+            let remaining = (next - Local::now()).to_std().unwrap_or_default();
+            sleep(remaining.min(MAX_SLEEP)).await;
+        }
+
+        for (_, entry) in upcoming.iter().filter(|(at, _)| *at == next) {
+            if is_missed(next, Local::now()) {
+                warn!("Skipping '{}', its fire time {next} was missed", entry.name);
+            } else {
+                fire(entry, executor, alias_manager).await;
+            }
+        }
+        after = resume_after(next, Local::now());
+    }
+}
+
+fn is_missed(at: DateTime<Local>, now: DateTime<Local>) -> bool {
+    now - at > MISSED_FIRE_GRACE
+}
+
+/// Where to continue after firing `fired`. Fire times that passed meanwhile are only kept
+/// within the grace window, so that a suspend or a long fire does not cause a burst of catch-ups.
+fn resume_after(fired: DateTime<Local>, now: DateTime<Local>) -> DateTime<Local> {
+    fired.max(now - MISSED_FIRE_GRACE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::executor::OpenClose;
+    use chrono::{TimeZone, Timelike};
+
+    fn entry(trigger: Trigger) -> ScheduleEntry {
+        ScheduleEntry {
+            name: "test".to_string(),
+            trigger,
+            action: OpenClose::Open,
+            devices: vec![],
+            groups: vec![],
+        }
+    }
+
+    #[test]
+    fn test_missed_fire_times_are_skipped() {
+        let fired = Local.with_ymd_and_hms(2024, 3, 4, 8, 0, 0).unwrap();
+        let woke_up = fired + Duration::hours(3);
+        let every_minute = entry(Trigger::Cron("* * * * *".to_string()));
+
+        let after = resume_after(fired, woke_up);
+        let next = next_fire(&every_minute, None, after).unwrap().unwrap();
+
+        assert!(woke_up - next <= MISSED_FIRE_GRACE);
+        assert!(is_missed(fired, woke_up));
+        assert!(!is_missed(next, woke_up));
+        assert_eq!(resume_after(fired, fired + Duration::seconds(10)), fired);
+    }
+
+    #[test]
+    fn test_next_fire_cron() {
+        let after = Local.with_ymd_and_hms(2024, 3, 4, 8, 0, 0).unwrap();
+        let next = next_fire(&entry(Trigger::Cron("30 7 * * *".to_string())), None, after)
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(next.date_naive(), after.date_naive().succ_opt().unwrap());
+        assert_eq!((next.hour(), next.minute()), (7, 30));
+    }
+
+    #[test]
+    fn test_next_fire_sun_applies_offset() {
+        let location = Location {
+            latitude: 48.137,
+            longitude: 11.575,
+        };
+        let after = Local.with_ymd_and_hms(2024, 6, 21, 0, 0, 0).unwrap();
+        let sunset = next_fire(
+            &entry(Trigger::Sun {
+                event: SunEvent::Sunset,
+                offset_minutes: 0,
+            }),
+            Some(&location),
+            after,
+        )
+        .unwrap()
+        .unwrap();
+        let before_sunset = next_fire(
+            &entry(Trigger::Sun {
+                event: SunEvent::Sunset,
+                offset_minutes: -30,
+            }),
+            Some(&location),
+            after,
+        )
+        .unwrap()
+        .unwrap();
+
+        assert!(sunset > after);
+        assert_eq!(sunset - before_sunset, Duration::minutes(30));
+    }
+
+    #[test]
+    fn test_next_fire_sun_skips_past_events() {
+        let location = Location {
+            latitude: 48.137,
+            longitude: 11.575,
+        };
+        let trigger = Trigger::Sun {
+            event: SunEvent::Sunrise,
+            offset_minutes: 0,
+        };
+        let first = next_fire(&entry(trigger.clone()), Some(&location), Local::now())
+            .unwrap()
+            .unwrap();
+        let second = next_fire(&entry(trigger), Some(&location), first)
+            .unwrap()
+            .unwrap();
+
+        assert!(second > first);
+        assert!(second - first > Duration::hours(23));
+    }
+
+    #[test]
+    fn test_next_fire_sun_without_location_errors() {
+        let trigger = Trigger::Sun {
+            event: SunEvent::Sunrise,
+            offset_minutes: 0,
+        };
+        assert!(next_fire(&entry(trigger), None, Local::now()).is_err());
+    }
+}
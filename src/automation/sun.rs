@@ -0,0 +1,104 @@
+use chrono::{DateTime, NaiveDate, Utc};
+
+/// Julian date of 2000-01-01 12:00 UTC (J2000 epoch)
+const J2000: f64 = 2451545.0;
+/// Julian date of the unix epoch
+const UNIX_EPOCH_JD: f64 = 2440587.5;
+/// Sun altitude at sunrise/sunset, corrected for refraction and the solar disc
+const HORIZON_ALTITUDE_DEG: f64 = -0.833;
+/// Axial tilt of the earth
+const EARTH_OBLIQUITY_DEG: f64 = 23.4397;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct SunTimes {
+    pub(crate) sunrise: DateTime<Utc>,
+    pub(crate) sunset: DateTime<Utc>,
+}
+
+/// Computes sunrise and sunset for the given date and location using the sunrise equation.
+/// Accuracy is within a minute or two, which is plenty for shutter automation.
+///
+/// returns: None during polar day/night, when the sun does not cross the horizon
+pub(crate) fn sun_times(date: NaiveDate, latitude: f64, longitude: f64) -> Option<SunTimes> {
+    let j2000 = NaiveDate::from_ymd_opt(2000, 1, 1)?;
+    let n = (date - j2000).num_days() as f64 + 0.0008;
+
+    let mean_solar_time = n - longitude / 360.0;
+    let mean_anomaly = (357.5291 + 0.98560028 * mean_solar_time).rem_euclid(360.0);
+    let m = mean_anomaly.to_radians();
+    let center = 1.9148 * m.sin() + 0.02 * (2.0 * m).sin() + 0.0003 * (3.0 * m).sin();
+    let ecliptic_longitude = (mean_anomaly + center + 180.0 + 102.9372)
+        .rem_euclid(360.0)
+        .to_radians();
+    let transit =
+        J2000 + mean_solar_time + 0.0053 * m.sin() - 0.0069 * (2.0 * ecliptic_longitude).sin();
+
+    let declination_sin = ecliptic_longitude.sin() * EARTH_OBLIQUITY_DEG.to_radians().sin();
+    let declination_cos = declination_sin.asin().cos();
+    let lat = latitude.to_radians();
+    let hour_angle_cos = (HORIZON_ALTITUDE_DEG.to_radians().sin() - lat.sin() * declination_sin)
+        / (lat.cos() * declination_cos);
+
+    if !(-1.0..=1.0).contains(&hour_angle_cos) {
+        return None;
+    }
+
+    let hour_angle = hour_angle_cos.acos().to_degrees();
+
+    Some(SunTimes {
+        sunrise: julian_to_utc(transit - hour_angle / 360.0)?,
+        sunset: julian_to_utc(transit + hour_angle / 360.0)?,
+    })
+}
+
+fn julian_to_utc(julian_date: f64) -> Option<DateTime<Utc>> {
+    let millis = ((julian_date - UNIX_EPOCH_JD) * 86_400_000.0).round() as i64;
+    DateTime::from_timestamp_millis(millis)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn assert_close(actual: DateTime<Utc>, expected: DateTime<Utc>) {
+        let diff = (actual - expected).num_minutes().abs();
+        assert!(diff <= 3, "expected {expected}, got {actual}");
+    }
+
+    #[test]
+    fn test_sun_times_munich_summer_solstice() {
+        let date = NaiveDate::from_ymd_opt(2024, 6, 21).unwrap();
+        let times = sun_times(date, 48.137, 11.575).unwrap();
+
+        assert_close(
+            times.sunrise,
+            Utc.with_ymd_and_hms(2024, 6, 21, 3, 12, 0).unwrap(),
+        );
+        assert_close(
+            times.sunset,
+            Utc.with_ymd_and_hms(2024, 6, 21, 19, 17, 0).unwrap(),
+        );
+    }
+
+    #[test]
+    fn test_sun_times_new_york_winter() {
+        let date = NaiveDate::from_ymd_opt(2024, 12, 1).unwrap();
+        let times = sun_times(date, 40.7128, -74.006).unwrap();
+
+        assert_close(
+            times.sunrise,
+            Utc.with_ymd_and_hms(2024, 12, 1, 12, 3, 0).unwrap(),
+        );
+        assert_close(
+            times.sunset,
+            Utc.with_ymd_and_hms(2024, 12, 1, 21, 29, 0).unwrap(),
+        );
+    }
+
+    #[test]
+    fn test_sun_times_polar_night_returns_none() {
+        let date = NaiveDate::from_ymd_opt(2024, 12, 21).unwrap();
+        assert_eq!(sun_times(date, 78.22, 15.65), None);
+    }
+}
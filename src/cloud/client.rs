@@ -0,0 +1,172 @@
+use crate::commands::error::{cli_error, ErrorKind};
+use reqwest::header::{COOKIE, SET_COOKIE};
+use reqwest::{Client, RequestBuilder, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::time::Duration;
+use urlencoding::encode;
+
+/// Cloud API of Somfy TaHoma in Europe, other regions use different servers
+pub(crate) const DEFAULT_CLOUD_URL: &str =
+    "https://ha101-1.overkiz.com/enduser-mobile-web/enduserAPI";
+pub(crate) const SESSION_COOKIE: &str = "JSESSIONID";
+/// Tokens with this scope are accepted by the local API of the gateway
+pub(crate) const DEVMODE_SCOPE: &str = "devmode";
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct GeneratedToken {
+    pub token: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ActivatedToken {
+    pub label: String,
+    pub request_id: String,
+}
+
+/// An activated token as listed by the cloud, the token itself is never returned again
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct LocalToken {
+    pub label: String,
+    pub uuid: String,
+    pub scope: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gateway_creation_time: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expiration_time: Option<i64>,
+}
+
+pub(crate) type LocalTokens = Vec<LocalToken>;
+
+fn session_of(response: &Response) -> Option<String> {
+    response
+        .headers()
+        .get_all(SET_COOKIE)
+        .iter()
+        .filter_map(|h| h.to_str().ok())
+        .filter_map(|h| h.split(';').next())
+        .find(|cookie| cookie.starts_with(&format!("{SESSION_COOKIE}=")))
+        .map(str::to_string)
+}
+
+async fn check_status(response: Response, action: &str) -> anyhow::Result<Response> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    let body = response.text().await.unwrap_or_default();
+    let kind = match status {
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => ErrorKind::Auth,
+        _ => ErrorKind::General,
+    };
+    Err(cli_error(
+        kind,
+        anyhow::anyhow!("Failed to {action}, the cloud responded with {status}: {body}"),
+    ))
+}
+
+async fn send(request: RequestBuilder, action: &str) -> anyhow::Result<Response> {
+    let response = request.send().await.map_err(|e| {
+        let kind = if e.is_timeout() {
+            ErrorKind::Timeout
+        } else {
+            ErrorKind::GatewayUnreachable
+        };
+        cli_error(
+            kind,
+            anyhow::Error::new(e).context(format!("Failed to {action}")),
+        )
+    })?;
+    check_status(response, action).await
+}
+
+/// Session with the Somfy cloud, which manages the tokens of the local API
+pub(crate) struct CloudClient {
+    client: Client,
+    base_url: String,
+    session: String,
+}
+
+impl CloudClient {
+    pub(crate) async fn login(base_url: &str, user: &str, password: &str) -> anyhow::Result<Self> {
+        let client = Client::builder()
+            .connect_timeout(CONNECT_TIMEOUT)
+            .timeout(REQUEST_TIMEOUT)
+            .build()?;
+        let base_url = base_url.trim_end_matches('/').to_string();
+        let response = send(
+            client
+                .post(format!("{base_url}/login"))
+                .form(&[("userId", user), ("userPassword", password)]),
+            "log into the Somfy cloud",
+        )
+        .await?;
+        let session = session_of(&response).ok_or(cli_error(
+            ErrorKind::Auth,
+            anyhow::anyhow!("The Somfy cloud did not return a session for '{user}'"),
+        ))?;
+
+        Ok(Self {
+            client,
+            base_url,
+            session,
+        })
+    }
+
+    fn tokens_url(&self, gateway_id: &str) -> String {
+        format!(
+            "{}/config/{}/local/tokens",
+            self.base_url,
+            encode(gateway_id)
+        )
+    }
+
+    fn request(&self, request: RequestBuilder) -> RequestBuilder {
+        request.header(COOKIE, &self.session)
+    }
+
+    /// A new token, which the gateway only accepts after it has been activated
+    pub(crate) async fn generate_token(&self, gateway_id: &str) -> anyhow::Result<GeneratedToken> {
+        let url = format!("{}/generate", self.tokens_url(gateway_id));
+        let response = send(self.request(self.client.get(url)), "generate a token").await?;
+        Ok(response.json().await?)
+    }
+
+    pub(crate) async fn activate_token(
+        &self,
+        gateway_id: &str,
+        token: &str,
+        label: &str,
+    ) -> anyhow::Result<ActivatedToken> {
+        let body = json!({"label": label, "token": token, "scope": DEVMODE_SCOPE});
+        let request = self.client.post(self.tokens_url(gateway_id)).json(&body);
+        let response = send(self.request(request), "activate the token").await?;
+
+        #[derive(Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct ActivationResponse {
+            request_id: String,
+        }
+        let activation: ActivationResponse = response.json().await?;
+        Ok(ActivatedToken {
+            label: label.to_string(),
+            request_id: activation.request_id,
+        })
+    }
+
+    pub(crate) async fn list_tokens(&self, gateway_id: &str) -> anyhow::Result<LocalTokens> {
+        let url = format!("{}/{DEVMODE_SCOPE}", self.tokens_url(gateway_id));
+        let response = send(self.request(self.client.get(url)), "list the tokens").await?;
+        Ok(response.json().await?)
+    }
+
+    pub(crate) async fn revoke_token(&self, gateway_id: &str, uuid: &str) -> anyhow::Result<()> {
+        let url = format!("{}/{}", self.tokens_url(gateway_id), encode(uuid));
+        send(self.request(self.client.delete(url)), "revoke the token").await?;
+        Ok(())
+    }
+}
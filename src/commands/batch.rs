@@ -0,0 +1,258 @@
+use crate::commands::cli::{Command, RulesCommands, ScheduleCommands};
+use crate::utils::duration::parse_duration;
+use anyhow::Context;
+use clap::error::{ContextKind, ErrorKind as ClapErrorKind};
+use clap::Parser;
+use serde::Serialize;
+use serde_json::Value;
+use std::error::Error as _;
+use std::fmt::{Display, Formatter};
+use std::io::Read;
+use std::path::Path;
+use std::time::Duration;
+
+const STDIN_PATH: &str = "-";
+const BINARY_NAME: &str = "somfy";
+
+/// A single line of a batch script, parsed with the same definitions as the command line
+#[derive(Parser)]
+#[command(no_binary_name = true)]
+struct BatchLine {
+    #[command(subcommand)]
+    command: Command,
+}
+
+pub(crate) enum BatchDirective {
+    /// `wait 2s` pauses the batch
+    Wait(Duration),
+    /// `wait idle` waits until the gateway has no running executions
+    WaitIdle,
+    Command(Box<Command>),
+}
+
+pub(crate) struct BatchStep {
+    /// 1-based line number in the script
+    pub line: usize,
+    pub source: String,
+    pub directive: BatchDirective,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum BatchStatus {
+    Ok,
+    Failed,
+    Skipped,
+}
+
+impl Display for BatchStatus {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BatchStatus::Ok => write!(f, "ok"),
+            BatchStatus::Failed => write!(f, "failed"),
+            BatchStatus::Skipped => write!(f, "skipped"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct BatchStepResult {
+    pub line: usize,
+    pub command: String,
+    pub status: BatchStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+pub(crate) type BatchReport = Vec<BatchStepResult>;
+
+/// Commands that run until interrupted, need a terminal or write directly to stdout
+/// cannot be part of a batch
+fn check_batchable(command: &Command) -> anyhow::Result<()> {
+    let name = match command {
+        Command::Batch(_) => "batch",
+        Command::Tui => "tui",
+        Command::Shell => "shell",
+        Command::Completions(_) => "completions",
+        Command::MockGateway(_) => "mock-gateway",
+        Command::Listen(_) => "listen",
+        Command::Schedule(s) if matches!(s.schedule_cmd, ScheduleCommands::Run) => "schedule run",
+        Command::Rules(r) if matches!(r.rules_cmd, RulesCommands::Run) => "rules run",
+        Command::ListDevices(l) if l.watch.watch => "--watch",
+        Command::GetCurrentExecutions(w) if w.watch => "--watch",
+        Command::State(s) if s.watch.watch => "--watch",
+        _ => return Ok(()),
+    };
+    anyhow::bail!("'{name}' cannot be used in a batch")
+}
+
+/// Short description of a parse error, requests for help keep their full text
+fn describe_parse_error(error: &clap::Error) -> String {
+    let context = |kind| error.get(kind).map(ToString::to_string).unwrap_or_default();
+    let reason = error
+        .source()
+        .map(|source| format!(": {source}"))
+        .unwrap_or_default();
+    match error.kind() {
+        ClapErrorKind::DisplayHelp
+        | ClapErrorKind::DisplayVersion
+        | ClapErrorKind::DisplayHelpOnMissingArgumentOrSubcommand => {
+            error.render().to_string().trim_end().to_string()
+        }
+        ClapErrorKind::InvalidSubcommand => format!(
+            "unrecognized subcommand '{}'",
+            context(ContextKind::InvalidSubcommand)
+        ),
+        ClapErrorKind::UnknownArgument => {
+            format!("unexpected argument '{}'", context(ContextKind::InvalidArg))
+        }
+        ClapErrorKind::InvalidValue | ClapErrorKind::ValueValidation => format!(
+            "invalid value '{}' for '{}'{reason}",
+            context(ContextKind::InvalidValue),
+            context(ContextKind::InvalidArg)
+        ),
+        ClapErrorKind::MissingRequiredArgument => format!(
+            "missing required argument {}",
+            context(ContextKind::InvalidArg)
+        ),
+        kind => match error.get(ContextKind::InvalidArg) {
+            Some(arg) => format!("{}: {arg}", kind.as_str().unwrap_or("invalid command")),
+            None => format!("{}{reason}", kind.as_str().unwrap_or("invalid command")),
+        },
+    }
+}
+
+/// Parses the words of a single line with the same definitions as the command line, a leading `somfy` is optional
+pub(crate) fn parse_command(mut words: Vec<String>) -> anyhow::Result<Command> {
+    if words.first().is_some_and(|w| w == BINARY_NAME) {
+        words.remove(0);
+    }
+
+    BatchLine::try_parse_from(words)
+        .map(|line| line.command)
+        .map_err(|e| anyhow::anyhow!("{}", describe_parse_error(&e)))
+}
+
+fn parse_line(source: &str) -> anyhow::Result<BatchDirective> {
+    let words = shlex::split(source).context("Unbalanced quotes")?;
+
+    if words.first().is_some_and(|w| w == "wait") {
+        return match words.get(1..) {
+            Some([arg]) if arg == "idle" => Ok(BatchDirective::WaitIdle),
+            Some([arg]) => Ok(BatchDirective::Wait(
+                parse_duration(arg).map_err(anyhow::Error::msg)?,
+            )),
+            _ => anyhow::bail!("Expected 'wait <duration>' or 'wait idle'"),
+        };
+    }
+
+    let command = parse_command(words)?;
+    check_batchable(&command)?;
+    Ok(BatchDirective::Command(Box::new(command)))
+}
+
+/// Parses the whole script up front, so that syntax errors are reported before anything is executed.
+/// Empty lines and lines starting with `#` are ignored.
+pub(crate) fn parse_script(script: &str) -> anyhow::Result<Vec<BatchStep>> {
+    script
+        .lines()
+        .enumerate()
+        .map(|(idx, line)| (idx + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(line, source)| {
+            let directive = parse_line(source).with_context(|| format!("Line {line}: {source}"))?;
+            Ok(BatchStep {
+                line,
+                source: source.to_string(),
+                directive,
+            })
+        })
+        .collect()
+}
+
+/// Reads the script from a file, or from stdin for `-`
+pub(crate) fn read_script(path: &Path) -> anyhow::Result<String> {
+    if path == Path::new(STDIN_PATH) {
+        let mut script = String::new();
+        std::io::stdin()
+            .read_to_string(&mut script)
+            .context("Failed to read batch script from stdin")?;
+        return Ok(script);
+    }
+    std::fs::read_to_string(path).context(format!("Failed to read batch script {path:?}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::cli::PositionChange;
+
+    #[test]
+    fn test_parse_script() {
+        let script = r#"
+            # Morning routine
+            somfy open living-room
+            position "kitchen blind" -20 -N
+
+            wait 2s
+            wait idle
+        "#;
+
+        let steps = parse_script(script).unwrap();
+
+        assert_eq!(steps.len(), 4);
+        assert_eq!(steps[0].line, 3);
+        assert!(
+            matches!(&steps[0].directive, BatchDirective::Command(c) if matches!(**c, Command::Open(_)))
+        );
+        match &steps[1].directive {
+            BatchDirective::Command(c) => match c.as_ref() {
+                Command::Position(args) => {
+                    assert_eq!(args.device_url, "kitchen blind");
+                    assert_eq!(args.percentage, Some(PositionChange::Relative(-20)));
+                }
+                _ => panic!("Expected position command"),
+            },
+            _ => panic!("Expected command"),
+        }
+        assert!(
+            matches!(steps[2].directive, BatchDirective::Wait(d) if d == Duration::from_secs(2))
+        );
+        assert!(matches!(steps[3].directive, BatchDirective::WaitIdle));
+    }
+
+    #[test]
+    fn test_invalid_lines_are_reported_with_line_number() {
+        let err = parse_script("open a\nfly kitchen").err().unwrap();
+        assert_eq!(
+            format!("{err:#}"),
+            "Line 2: fly kitchen: unrecognized subcommand 'fly'"
+        );
+
+        let err = parse_script("open a --poll").err().unwrap();
+        assert_eq!(
+            format!("{err:#}"),
+            "Line 1: open a --poll: unexpected argument '--poll'"
+        );
+
+        let err = parse_script("history --since soon").err().unwrap();
+        assert_eq!(
+            format!("{err:#}"),
+            "Line 1: history --since soon: invalid value 'soon' for '--since <SINCE>': 'soon' is neither a duration like 2h nor a date like 2024-05-01"
+        );
+
+        let err = parse_script("ls --watch").err().unwrap();
+        assert!(format!("{err:#}").contains("'--watch' cannot be used in a batch"));
+
+        let err = parse_script("open a\nshell").err().unwrap();
+        assert_eq!(
+            format!("{err:#}"),
+            "Line 2: shell: 'shell' cannot be used in a batch"
+        );
+
+        let err = parse_script("completions bash").err().unwrap();
+        assert!(format!("{err:#}").contains("'completions' cannot be used in a batch"));
+    }
+}
@@ -0,0 +1,145 @@
+use reqwest::header::HeaderMap;
+use reqwest::Body;
+use serde::{Deserialize, Serialize};
+use somfy_sdk::commands::traits::{
+    HttpMethod, RequestData, SomfyApiRequestCommand, SomfyApiRequestResponse,
+};
+use somfy_sdk::commands::types::{Command, DeviceDefinitionCommand};
+use somfy_sdk::err::http::RequestError;
+use std::collections::HashMap;
+use urlencoding::encode;
+
+/// Same endpoint as the SDK's `GetDeviceCommand`, but keeps the definition of the device,
+/// which lists the commands it supports.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct GetDeviceCapabilitiesCommand<'a> {
+    pub device_url: &'a str,
+}
+
+impl SomfyApiRequestCommand for GetDeviceCapabilitiesCommand<'_> {
+    type Response = DeviceCapabilities;
+    fn to_request(&self) -> Result<RequestData, RequestError> {
+        let encoded_device_url = encode(self.device_url);
+        Ok(RequestData {
+            path: format!("/enduser-mobile-web/1/enduserAPI/setup/devices/{encoded_device_url}"),
+            method: HttpMethod::GET,
+            body: Body::default(),
+            query_params: HashMap::default(),
+            header_map: HeaderMap::default(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct CapabilityDefinition {
+    #[serde(default)]
+    pub ui_class: String,
+    #[serde(default)]
+    pub commands: Vec<DeviceDefinitionCommand>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct DeviceCapabilities {
+    #[serde(rename = "deviceURL")]
+    pub device_url: String,
+    pub label: String,
+    #[serde(default)]
+    pub definition: CapabilityDefinition,
+}
+
+impl SomfyApiRequestResponse for DeviceCapabilities {}
+
+impl DeviceCapabilities {
+    fn supported_commands(&self) -> String {
+        match self.definition.commands.as_slice() {
+            [] => "none".to_string(),
+            commands => commands
+                .iter()
+                .map(|c| c.command_name.as_str())
+                .collect::<Vec<_>>()
+                .join(", "),
+        }
+    }
+
+    /// Fails if the device does not support the command, or with another number of parameters
+    pub(crate) fn validate(&self, command: &Command) -> anyhow::Result<()> {
+        let supported = self
+            .definition
+            .commands
+            .iter()
+            .find(|c| c.command_name == command.name)
+            .ok_or(anyhow::anyhow!(
+                "'{}' ({}) does not support {}, supported commands: {}",
+                self.label,
+                self.device_url,
+                command.name,
+                self.supported_commands()
+            ))?;
+        if command.parameters.len() as i64 != supported.nparams {
+            anyhow::bail!(
+                "{} of '{}' expects {} parameter(s), got {}",
+                command.name,
+                self.label,
+                supported.nparams,
+                command.parameters.len()
+            );
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn capabilities() -> DeviceCapabilities {
+        DeviceCapabilities::from_body(
+            r#"{
+              "deviceURL": "io://1234-5678-9012/5",
+              "label": "Hallway Light",
+              "states": [],
+              "definition": {
+                "uiClass": "Light",
+                "commands": [{"commandName": "on", "nparams": 0}, {"commandName": "off", "nparams": 0}]
+              }
+            }"#,
+        )
+        .unwrap()
+    }
+
+    fn command(name: &str, parameters: &[&str]) -> Command {
+        Command {
+            name: name.to_string(),
+            parameters: parameters.iter().map(|p| p.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_generates_correct_request_path() {
+        let command = GetDeviceCapabilitiesCommand {
+            device_url: "io://1234-5678-9012/5",
+        };
+        let request_data = command.to_request().unwrap();
+        assert_eq!(
+            request_data.path,
+            "/enduser-mobile-web/1/enduserAPI/setup/devices/io%3A%2F%2F1234-5678-9012%2F5"
+        );
+    }
+
+    #[test]
+    fn test_validates_command_and_parameter_count() {
+        let capabilities = capabilities();
+
+        assert!(capabilities.validate(&command("on", &[])).is_ok());
+        let unsupported = capabilities
+            .validate(&command("setClosure", &["50"]))
+            .unwrap_err();
+        assert_eq!(
+            unsupported.to_string(),
+            "'Hallway Light' (io://1234-5678-9012/5) does not support setClosure, supported commands: on, off"
+        );
+        assert!(capabilities.validate(&command("on", &["1"])).is_err());
+    }
+}
@@ -1,5 +1,20 @@
-use crate::output::formatter::OutputStyle;
+use crate::commands::completions::{
+    complete_alias, complete_device, complete_device_url, CompletionShell,
+};
+use crate::config::alias::{AliasFileFormat, ImportMode};
+use crate::config::certificate::GATEWAY_CA_URL;
+use crate::config::dotenv::HttpProtocol;
+use crate::events::stream::parse_replay_speed;
+use crate::history::store::{parse_since, HistoryKind};
+use crate::mock::server::parse_speed;
+use crate::output::formatter::{OutputFormat, OutputStyle};
+use crate::output::template::TemplateRenderer;
+use crate::utils::duration::parse_duration;
 use clap::{Args, Parser, Subcommand};
+use clap_complete::engine::ArgValueCompleter;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
 
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
@@ -35,10 +50,26 @@ pub(crate) struct Cli {
     )]
     pub(crate) gateway_port: Option<usize>,
 
+    #[arg(
+        long,
+        value_enum,
+        env = "SOMFY_GATEWAY_PROTOCOL",
+        help = "Gateway protocol (or set SOMFY_GATEWAY_PROTOCOL), defaults to https"
+    )]
+    pub(crate) gateway_protocol: Option<HttpProtocol>,
+
     /// The format of the output
     #[arg(long, short = 'S', value_enum, default_value = "json", global = true)]
     pub(crate) output_style: OutputStyle,
 
+    #[arg(
+        long,
+        global = true,
+        conflicts_with = "output_style",
+        help = "Format the output with a handlebars template, rendered once per element, e.g. '{{label}}: {{state \"core:ClosureState\"}}%'"
+    )]
+    pub(crate) format: Option<String>,
+
     #[arg(
         long,
         short = 'C',
@@ -46,10 +77,36 @@ pub(crate) struct Cli {
         help = "Use the certificate at the provided file path"
     )]
     pub(crate) cert: Option<String>,
+
+    #[arg(
+        long,
+        short = 'P',
+        global = true,
+        env = "SOMFY_PROFILE",
+        help = "Use the settings of this profile in env.json (or set SOMFY_PROFILE), defaults to active_profile"
+    )]
+    pub(crate) profile: Option<String>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Folder with env.json, aliases and the other files, defaults to SOMFY_CONFIG_DIR or $XDG_CONFIG_HOME/somfy-cli"
+    )]
+    pub(crate) config_dir: Option<PathBuf>,
+}
+
+impl Cli {
+    pub(crate) fn output_format(&self) -> anyhow::Result<OutputFormat> {
+        Ok(match &self.format {
+            Some(template) => OutputFormat::Template(Box::new(TemplateRenderer::new(template)?)),
+            None => OutputFormat::Style(self.output_style),
+        })
+    }
 }
 
 #[derive(Args)]
 pub(crate) struct OpenArgs {
+    #[arg(add = ArgValueCompleter::new(complete_device))]
     pub(crate) device_url: String,
     #[arg(
         long = "no-poll",
@@ -61,6 +118,7 @@ pub(crate) struct OpenArgs {
 
 #[derive(Args, Debug)]
 pub(crate) struct CloseArgs {
+    #[arg(add = ArgValueCompleter::new(complete_device))]
     pub(crate) device_url: String,
     #[arg(
         long = "no-poll",
@@ -70,10 +128,65 @@ pub(crate) struct CloseArgs {
     pub(crate) no_poll: bool,
 }
 
+/// Absolute closure in percent, or a change relative to the current closure like `+20` or `-10`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum PositionChange {
+    Absolute(u8),
+    Relative(i16),
+}
+
+impl FromStr for PositionChange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let value: i16 = s
+            .parse()
+            .map_err(|_| format!("'{s}' is neither a percentage nor a relative change"))?;
+        if !(-100..=100).contains(&value) {
+            return Err(format!("{s} is out of range, expected 0-100"));
+        }
+
+        if s.starts_with(['+', '-']) {
+            Ok(PositionChange::Relative(value))
+        } else {
+            Ok(PositionChange::Absolute(value as u8))
+        }
+    }
+}
+
+impl PositionChange {
+    /// Closure to send to the device. `current` is only needed for relative changes.
+    /// With `invert`, values are percent open instead of percent closed.
+    pub(crate) fn target_closure(self, current: Option<u8>, invert: bool) -> anyhow::Result<u8> {
+        match self {
+            PositionChange::Absolute(p) if invert => Ok(100 - p),
+            PositionChange::Absolute(p) => Ok(p),
+            PositionChange::Relative(delta) => {
+                let current = current.ok_or(anyhow::anyhow!(
+                    "The current closure is required for relative position changes"
+                ))?;
+                let delta = if invert { -delta } else { delta };
+                Ok((i16::from(current) + delta).clamp(0, 100) as u8)
+            }
+        }
+    }
+}
+
 #[derive(Args, Debug)]
 pub(crate) struct PositionArgs {
+    #[arg(add = ArgValueCompleter::new(complete_device))]
     pub(crate) device_url: String,
-    pub(crate) percentage: u8,
+    #[arg(
+        value_name = "PERCENTAGE",
+        help = "Closure in percent (0-100), or a change relative to the current closure like +20 or -10. Defaults to the default position of the alias, or of all aliases with the tag"
+    )]
+    pub(crate) percentage: Option<PositionChange>,
+    #[arg(
+        long,
+        requires = "percentage",
+        help = "Percentages are percent open instead of percent closed, e.g. 100 is fully open"
+    )]
+    pub(crate) invert: bool,
     #[arg(
         long = "no-poll",
         short = 'N',
@@ -82,24 +195,241 @@ pub(crate) struct PositionArgs {
     pub(crate) no_poll: bool,
 }
 
+#[derive(Args, Debug, Default)]
+pub(crate) struct WatchArgs {
+    #[arg(
+        long,
+        short = 'w',
+        help = "Keep running and redraw the output whenever the gateway reports changes"
+    )]
+    pub(crate) watch: bool,
+}
+
+#[derive(Args, Debug, Default)]
+pub(crate) struct ListArgs {
+    #[command(flatten)]
+    pub(crate) watch: WatchArgs,
+    #[arg(
+        long,
+        conflicts_with_all = ["watch", "max_age"],
+        help = "List the devices from the local cache without contacting the gateway"
+    )]
+    pub(crate) cached: bool,
+    #[arg(
+        long,
+        value_parser = parse_duration,
+        conflicts_with = "watch",
+        help = "Use the local cache if it is younger than this, e.g. 10m, otherwise fetch from the gateway"
+    )]
+    pub(crate) max_age: Option<Duration>,
+    #[arg(
+        long,
+        conflicts_with = "watch",
+        help = "Only list the devices of aliases with this tag"
+    )]
+    pub(crate) tag: Option<String>,
+}
+
+#[derive(Args, Debug)]
+pub(crate) struct CommandsArgs {
+    #[arg(add = ArgValueCompleter::new(complete_device))]
+    pub(crate) device_url: String,
+}
+
+#[derive(Args, Debug)]
+pub(crate) struct StateArgs {
+    #[arg(add = ArgValueCompleter::new(complete_device))]
+    pub(crate) device_url: String,
+    #[command(flatten)]
+    pub(crate) watch: WatchArgs,
+}
+
+#[derive(Args, Debug)]
+pub(crate) struct BatchArgs {
+    #[arg(help = "Script with one command per line, or - to read from stdin")]
+    pub(crate) file: PathBuf,
+    #[arg(
+        long,
+        help = "Keep going after a failed command instead of skipping the remaining ones"
+    )]
+    pub(crate) continue_on_error: bool,
+    #[arg(
+        long,
+        value_parser = parse_duration,
+        help = "Pause between commands, e.g. 500ms or 2s"
+    )]
+    pub(crate) delay: Option<Duration>,
+}
+
+#[derive(Args, Debug)]
+pub(crate) struct HistoryArgs {
+    #[arg(
+        add = ArgValueCompleter::new(complete_device),
+        help = "Device URL, alias or label, all devices if omitted"
+    )]
+    pub(crate) device_url: Option<String>,
+    #[arg(
+        long,
+        value_parser = parse_since,
+        help = "Only entries after this, e.g. 2h, 7d, 2024-05-01 or '2024-05-01 18:00'"
+    )]
+    pub(crate) since: Option<chrono::DateTime<chrono::Utc>>,
+    #[arg(
+        long,
+        help = "Only entries of this state or command, e.g. core:ClosureState or close"
+    )]
+    pub(crate) name: Option<String>,
+    #[arg(long, value_enum, help = "Only state changes or only executions")]
+    pub(crate) kind: Option<HistoryKind>,
+    #[arg(
+        long,
+        default_value_t = 50,
+        help = "Maximum number of entries, newest first"
+    )]
+    pub(crate) limit: usize,
+}
+
+#[derive(Args, Debug)]
+pub(crate) struct CompletionsArgs {
+    #[arg(value_enum)]
+    pub(crate) shell: CompletionShell,
+}
+
+#[derive(Args, Debug)]
+pub(crate) struct ListenArgs {
+    #[arg(
+        long,
+        help = "Append all received events with timestamps to the file (newline delimited JSON)"
+    )]
+    pub(crate) record: Option<PathBuf>,
+    #[arg(
+        long,
+        conflicts_with = "record",
+        help = "Replay a recorded event stream instead of listening to the gateway"
+    )]
+    pub(crate) replay: Option<PathBuf>,
+    #[arg(
+        long,
+        default_value_t = 1.0,
+        value_parser = parse_replay_speed,
+        requires = "replay",
+        help = "Replay speed factor, e.g. 10 replays ten times faster. 0 replays without delays"
+    )]
+    pub(crate) speed: f64,
+    #[arg(
+        long = "event",
+        help = "Only show events with this name, can be repeated"
+    )]
+    pub(crate) events: Vec<String>,
+    #[arg(
+        long = "device",
+        add = ArgValueCompleter::new(complete_device),
+        help = "Only show events of this device URL or alias, can be repeated"
+    )]
+    pub(crate) devices: Vec<String>,
+}
+
 #[derive(Args, Debug)]
 pub(crate) struct AliasAddArgs {
     pub(crate) alias_name: String,
+    #[arg(add = ArgValueCompleter::new(complete_device_url))]
     pub(crate) device_url: String,
     #[arg(long, short = 'O', help = "Overwrites an existing alias")]
     pub(crate) overwrite: bool,
+    #[arg(long)]
+    pub(crate) description: Option<String>,
+    #[arg(long)]
+    pub(crate) room: Option<String>,
+    #[arg(
+        long = "tag",
+        value_name = "TAG",
+        help = "Tag of the alias, can be given multiple times. Address all tagged devices with tag:<TAG>"
+    )]
+    pub(crate) tags: Vec<String>,
+    #[arg(
+        long,
+        value_parser = clap::value_parser!(u8).range(0..=100),
+        help = "Closure in percent used by 'position' without a percentage"
+    )]
+    pub(crate) default_position: Option<u8>,
+}
+
+#[derive(Args, Debug)]
+pub(crate) struct AliasLsArgs {
+    #[arg(long, help = "Only list aliases with this tag")]
+    pub(crate) tag: Option<String>,
+    #[arg(long, help = "Only list aliases in this room")]
+    pub(crate) room: Option<String>,
 }
 
 #[derive(Args, Debug)]
 pub(crate) struct AliasRmArgs {
+    #[arg(add = ArgValueCompleter::new(complete_alias))]
     pub(crate) alias_name: String,
 }
 
+#[derive(Args, Debug)]
+pub(crate) struct AliasRenameArgs {
+    #[arg(add = ArgValueCompleter::new(complete_alias))]
+    pub(crate) alias_name: String,
+    pub(crate) new_name: String,
+    #[arg(
+        long,
+        short = 'O',
+        help = "Overwrites an existing alias with the new name"
+    )]
+    pub(crate) overwrite: bool,
+}
+
+#[derive(Args, Debug)]
+pub(crate) struct AliasExportArgs {
+    #[arg(help = "File to write, printed in the output style if omitted")]
+    pub(crate) file: Option<PathBuf>,
+    #[arg(
+        long,
+        value_enum,
+        help = "Format of the file, derived from its extension by default"
+    )]
+    pub(crate) file_format: Option<AliasFileFormat>,
+}
+
+#[derive(Args, Debug)]
+pub(crate) struct AliasImportArgs {
+    #[arg(help = "JSON, YAML or CSV file written by 'alias export'")]
+    pub(crate) file: PathBuf,
+    #[arg(
+        long,
+        value_enum,
+        help = "Format of the file, derived from its extension by default"
+    )]
+    pub(crate) file_format: Option<AliasFileFormat>,
+    #[arg(long, value_enum, default_value = "merge")]
+    pub(crate) mode: ImportMode,
+    #[arg(
+        long,
+        short = 'O',
+        help = "Overwrites existing aliases pointing to other devices, they are reported as conflicts otherwise"
+    )]
+    pub(crate) overwrite: bool,
+    #[arg(long, help = "Only report the changes")]
+    pub(crate) dry_run: bool,
+}
+
 #[derive(Subcommand, Debug)]
 pub(crate) enum AliasCommands {
     Add(AliasAddArgs),
     Rm(AliasRmArgs),
-    Ls,
+    Ls(AliasLsArgs),
+    #[command(long_about = "Rename an alias, keeping its device URL")]
+    Rename(AliasRenameArgs),
+    #[command(
+        long_about = "Write all aliases to a JSON, YAML or CSV file, e.g. to keep them in version control"
+    )]
+    Export(AliasExportArgs),
+    #[command(
+        long_about = "Import aliases from a file written by 'alias export' and report what changed"
+    )]
+    Import(AliasImportArgs),
 }
 
 #[derive(Args, Debug)]
@@ -108,21 +438,384 @@ pub(crate) struct AliasArgs {
     pub(crate) alias_cmd: AliasCommands,
 }
 
+#[derive(Args, Debug)]
+pub(crate) struct KeyringAccountArgs {
+    #[arg(help = "Account name, referenced by api_key_keyring in env.json")]
+    pub(crate) account: String,
+}
+
+#[derive(Subcommand, Debug)]
+pub(crate) enum KeyringCommands {
+    #[command(long_about = "Store an API key read from stdin in the system keyring")]
+    Set(KeyringAccountArgs),
+    #[command(long_about = "Remove an API key from the system keyring")]
+    Delete(KeyringAccountArgs),
+}
+
+#[derive(Args, Debug)]
+pub(crate) struct KeyringArgs {
+    #[command(subcommand)]
+    pub(crate) keyring_cmd: KeyringCommands,
+}
+
+#[derive(Args, Debug)]
+pub(crate) struct TokenActivateArgs {
+    #[arg(help = "Token printed by 'somfy token generate', a new one is generated if omitted")]
+    pub(crate) token: Option<String>,
+    #[arg(
+        long,
+        default_value = "somfy-cli",
+        help = "Label of the token, shown by 'somfy token ls'"
+    )]
+    pub(crate) label: String,
+}
+
+#[derive(Args, Debug)]
+pub(crate) struct TokenRevokeArgs {
+    #[arg(help = "UUID of the token, see 'somfy token ls'")]
+    pub(crate) uuid: String,
+}
+
+#[derive(Subcommand, Debug)]
+pub(crate) enum TokenCommands {
+    #[command(
+        long_about = "Generate a new token, the gateway only accepts it after it has been activated"
+    )]
+    Generate,
+    #[command(
+        long_about = "Activate a token for the local API and store it as API key of the profile in env.json, or in the keyring if the profile uses api_key_keyring"
+    )]
+    Activate(TokenActivateArgs),
+    #[command(
+        visible_alias = "list",
+        long_about = "List the activated tokens of the gateway"
+    )]
+    Ls,
+    #[command(long_about = "Revoke an activated token, the gateway rejects it afterwards")]
+    Revoke(TokenRevokeArgs),
+}
+
+#[derive(Args, Debug)]
+pub(crate) struct TokenArgs {
+    #[command(subcommand)]
+    pub(crate) token_cmd: TokenCommands,
+    #[arg(
+        long,
+        global = true,
+        help = "Somfy account (or set SOMFY_CLOUD_USER), defaults to cloud_user in env.json. The password is read from SOMFY_CLOUD_PASSWORD or stdin"
+    )]
+    pub(crate) user: Option<String>,
+    #[arg(
+        long,
+        global = true,
+        env = "SOMFY_GATEWAY_ID",
+        help = "Gateway PIN like 1234-5678-9012 (or set SOMFY_GATEWAY_ID), defaults to gateway_id in env.json"
+    )]
+    pub(crate) gateway_id: Option<String>,
+    #[arg(
+        long,
+        global = true,
+        help = "Cloud API endpoint (or set SOMFY_CLOUD_URL), defaults to cloud_url in env.json or the Somfy server for Europe"
+    )]
+    pub(crate) cloud_url: Option<String>,
+}
+
+#[derive(Args, Debug)]
+pub(crate) struct CertFetchArgs {
+    #[arg(long, default_value = GATEWAY_CA_URL, help = "Where to download the CA from")]
+    pub(crate) url: String,
+}
+
+#[derive(Args, Debug)]
+pub(crate) struct CertShowArgs {
+    #[arg(
+        help = "PEM file to inspect, the certificates presented by the gateway are shown if omitted"
+    )]
+    pub(crate) path: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+pub(crate) struct CertPinArgs {
+    #[arg(
+        help = "SHA-256 fingerprint to pin, defaults to the fingerprint of the certificate the gateway presents now"
+    )]
+    pub(crate) fingerprint: Option<String>,
+}
+
+#[derive(Subcommand, Debug)]
+pub(crate) enum CertCommands {
+    #[command(
+        long_about = "Download the CA of the gateways into the config folder and use it as cert_path of the profile"
+    )]
+    Fetch(CertFetchArgs),
+    #[command(
+        long_about = "Show subject, issuer, SHA-256 fingerprint and validity of certificates"
+    )]
+    Show(CertShowArgs),
+    #[command(
+        long_about = "Pin the certificate fingerprint of the gateway in the profile, connections fail if the gateway presents another certificate"
+    )]
+    Pin(CertPinArgs),
+}
+
+#[derive(Args, Debug)]
+pub(crate) struct CertArgs {
+    #[command(subcommand)]
+    pub(crate) cert_cmd: CertCommands,
+}
+
+#[derive(Subcommand, Debug)]
+pub(crate) enum ConfigCommands {
+    #[command(
+        long_about = "Show the config folder, where it was configured and which files exist"
+    )]
+    Path,
+    #[command(
+        long_about = "Merge env.json and alias.json into config.toml. The JSON files are kept as env.json.bak and alias.json.bak"
+    )]
+    Convert(ConfigConvertArgs),
+}
+
+#[derive(Args, Debug)]
+pub(crate) struct ConfigConvertArgs {
+    #[arg(long, help = "Overwrite an existing config.toml")]
+    pub(crate) force: bool,
+}
+
+#[derive(Args, Debug)]
+pub(crate) struct ConfigArgs {
+    #[command(subcommand)]
+    pub(crate) config_cmd: ConfigCommands,
+}
+
+#[derive(Subcommand, Debug)]
+pub(crate) enum ScheduleCommands {
+    #[command(
+        long_about = "Run the scheduler in the foreground and execute entries at their fire times"
+    )]
+    Run,
+    #[command(long_about = "List all schedule entries with their next fire time")]
+    Ls,
+}
+
+#[derive(Args, Debug)]
+pub(crate) struct ScheduleArgs {
+    #[command(subcommand)]
+    pub(crate) schedule_cmd: ScheduleCommands,
+}
+
+#[derive(Args, Debug)]
+pub(crate) struct RulesTestArgs {
+    #[arg(help = "Recorded event stream (newline delimited JSON) to evaluate the rules against")]
+    pub(crate) recording: PathBuf,
+}
+
+#[derive(Subcommand, Debug)]
+pub(crate) enum RulesCommands {
+    #[command(long_about = "Listen for device events and execute matching rules")]
+    Run,
+    #[command(
+        long_about = "Replay recorded events against the rules and list which rules would fire, without executing anything"
+    )]
+    Test(RulesTestArgs),
+}
+
+#[derive(Args, Debug)]
+pub(crate) struct RulesArgs {
+    #[command(subcommand)]
+    pub(crate) rules_cmd: RulesCommands,
+}
+
+#[derive(Args, Debug)]
+pub(crate) struct MockGatewayArgs {
+    #[arg(long, default_value = "127.0.0.1", help = "Address to listen on")]
+    pub(crate) bind: String,
+    #[arg(
+        long,
+        default_value_t = 8443,
+        help = "Port to listen on, 0 picks a free port"
+    )]
+    pub(crate) port: u16,
+    #[arg(
+        long,
+        help = "Only accept requests with this API key, any key is accepted if omitted"
+    )]
+    pub(crate) token: Option<String>,
+    #[arg(
+        long,
+        default_value_t = 10.0,
+        value_parser = parse_speed,
+        help = "Simulated shutter speed in percent per second"
+    )]
+    pub(crate) speed: f64,
+}
+
 #[derive(Subcommand)]
 pub(crate) enum Command {
     #[command(long_about = "Open the device")]
     Open(OpenArgs),
     #[command(long_about = "Close the device")]
     Close(CloseArgs),
-    #[command(long_about = "Move the device into a specific position")]
+    #[command(
+        long_about = "Move the device into a specific position, or relative to its current position",
+        allow_negative_numbers = true
+    )]
     Position(PositionArgs),
     #[command(name = "ls", long_about = "List all devices")]
-    ListDevices,
+    ListDevices(ListArgs),
+    #[command(long_about = "Show the states of a device")]
+    State(StateArgs),
+    #[command(
+        long_about = "List the commands a device supports with their number of parameters. Other commands are rejected before they are sent"
+    )]
+    Commands(CommandsArgs),
     #[command(name = "current-execs", long_about = "List all running executions")]
-    GetCurrentExecutions,
-    #[command(long_about = "Listen for device events")]
-    Listen,
+    GetCurrentExecutions(WatchArgs),
+    #[command(long_about = "Listen for device events, or replay a recorded event stream")]
+    Listen(ListenArgs),
+    #[command(
+        long_about = "Run commands from a script over a single gateway connection and report a combined result"
+    )]
+    Batch(BatchArgs),
+    #[command(
+        long_about = "Interactive prompt keeping one gateway connection open, with history and tab completion"
+    )]
+    Shell,
+    #[command(
+        long_about = "Interactive terminal dashboard to monitor and control devices and groups of devices"
+    )]
+    Tui,
+    #[command(
+        long_about = "Show recorded state changes and executions of devices, requires the history to be enabled in env.json"
+    )]
+    History(HistoryArgs),
+    #[command(
+        long_about = "Print the shell completion script, e.g. 'source <(somfy completions bash)'. Device arguments complete aliases"
+    )]
+    Completions(CompletionsArgs),
     // Scenario,
     #[command(name = "alias", long_about = "Manage aliases for devices")]
     Alias(AliasArgs),
+    #[command(
+        long_about = "Manage API keys in the system keyring (Secret Service on Linux, Keychain on macOS)"
+    )]
+    Keyring(KeyringArgs),
+    #[command(
+        long_about = "Generate, activate, list and revoke tokens for the local API of the gateway through the Somfy cloud"
+    )]
+    Token(TokenArgs),
+    #[command(long_about = "Fetch, inspect and pin the TLS certificate of the gateway")]
+    Cert(CertArgs),
+    #[command(long_about = "Inspect the configuration of the CLI")]
+    Config(ConfigArgs),
+    #[command(
+        name = "schedule",
+        long_about = "Run time-based automations from the schedule file"
+    )]
+    Schedule(ScheduleArgs),
+    #[command(
+        name = "rules",
+        long_about = "Run automations reacting to device events from the rules file"
+    )]
+    Rules(RulesArgs),
+    #[command(
+        name = "mock-gateway",
+        long_about = "Run a local HTTP server emulating the gateway API with simulated devices, for development and tests"
+    )]
+    MockGateway(MockGatewayArgs),
+}
+
+impl Command {
+    /// Commands working on local files only, which can run without gateway credentials
+    pub(crate) fn requires_gateway(&self) -> bool {
+        match self {
+            Command::Alias(_)
+            | Command::MockGateway(_)
+            | Command::Completions(_)
+            | Command::History(_)
+            | Command::Keyring(_)
+            | Command::Token(_)
+            | Command::Cert(_)
+            | Command::Config(_) => false,
+            Command::ListDevices(l) => !l.cached,
+            Command::Schedule(s) => !matches!(s.schedule_cmd, ScheduleCommands::Ls),
+            Command::Rules(r) => !matches!(r.rules_cmd, RulesCommands::Test(_)),
+            Command::Listen(l) => l.replay.is_none(),
+            _ => true,
+        }
+    }
+
+    /// Commands connecting to the gateway without the API client, e.g. to inspect its certificate
+    pub(crate) fn requires_gateway_address(&self) -> bool {
+        match self {
+            Command::Cert(c) => match &c.cert_cmd {
+                CertCommands::Fetch(_) => false,
+                CertCommands::Show(s) => s.path.is_none(),
+                CertCommands::Pin(p) => p.fingerprint.is_none(),
+            },
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_position_change() {
+        assert_eq!("40".parse(), Ok(PositionChange::Absolute(40)));
+        assert_eq!("+20".parse(), Ok(PositionChange::Relative(20)));
+        assert_eq!("-10".parse(), Ok(PositionChange::Relative(-10)));
+        assert!("150".parse::<PositionChange>().is_err());
+        assert!("half".parse::<PositionChange>().is_err());
+    }
+
+    #[test]
+    fn test_relative_changes_are_clamped() {
+        let up = PositionChange::Relative(20);
+
+        assert_eq!(up.target_closure(Some(90), false).unwrap(), 100);
+        assert_eq!(up.target_closure(Some(10), true).unwrap(), 0);
+        assert!(up.target_closure(None, false).is_err());
+    }
+
+    #[test]
+    fn test_inverted_absolute_position() {
+        assert_eq!(
+            PositionChange::Absolute(30)
+                .target_closure(None, true)
+                .unwrap(),
+            70
+        );
+    }
+
+    #[test]
+    fn test_invert_requires_a_percentage() {
+        assert!(Cli::try_parse_from(["somfy", "position", "terrace", "--invert"]).is_err());
+        assert!(Cli::try_parse_from(["somfy", "position", "terrace", "30", "--invert"]).is_ok());
+    }
+
+    #[test]
+    fn test_negative_relative_position_is_accepted() {
+        let cli = Cli::try_parse_from(["somfy", "position", "kitchen", "-10", "-N"]).unwrap();
+        let Command::Position(args) = cli.command else {
+            panic!("Expected position command");
+        };
+
+        assert_eq!(args.percentage, Some(PositionChange::Relative(-10)));
+        assert!(args.no_poll);
+    }
+
+    #[test]
+    fn test_mock_gateway_speed_has_to_be_positive() {
+        for speed in ["0", "-5", "NaN"] {
+            assert!(Cli::try_parse_from(["somfy", "mock-gateway", "--speed", speed]).is_err());
+        }
+        let cli = Cli::try_parse_from(["somfy", "mock-gateway", "--speed", "2.5"]).unwrap();
+        let Command::MockGateway(args) = cli.command else {
+            panic!("Expected mock-gateway command");
+        };
+        assert_eq!(args.speed, 2.5);
+    }
 }
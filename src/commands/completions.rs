@@ -0,0 +1,188 @@
+use crate::config::alias::{AliasManager, Aliases, TAG_PREFIX};
+use crate::config::device_cache::DeviceCache;
+use clap::ValueEnum;
+use clap_complete::engine::CompletionCandidate;
+use clap_complete::env::{Bash, EnvCompleter, Fish, Zsh};
+use somfy_sdk::commands::get_devices::GetDevicesResponse;
+use std::collections::BTreeSet;
+use std::ffi::OsStr;
+use std::io::Write;
+
+/// Environment variable the shell sets when asking the binary for completions
+pub(crate) const COMPLETE_VAR: &str = "COMPLETE";
+pub(crate) const BIN_NAME: &str = "somfy";
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub(crate) enum CompletionShell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+impl CompletionShell {
+    fn completer(self) -> &'static dyn EnvCompleter {
+        match self {
+            CompletionShell::Bash => &Bash,
+            CompletionShell::Zsh => &Zsh,
+            CompletionShell::Fish => &Fish,
+        }
+    }
+}
+
+/// Writes the script registering `somfy` for completion. The script calls back into the binary,
+/// so aliases added later are completed without regenerating it.
+pub(crate) fn write_registration(
+    shell: CompletionShell,
+    name: &str,
+    out: &mut dyn Write,
+) -> anyhow::Result<()> {
+    shell
+        .completer()
+        .write_registration(COMPLETE_VAR, name, BIN_NAME, BIN_NAME, out)?;
+    Ok(())
+}
+
+/// Aliases first, then device labels of the cache. Labels with spaces are left out, shells would split them.
+/// After `tag:` the tags of the aliases are completed.
+fn device_candidates(
+    aliases: &Aliases,
+    devices: &GetDevicesResponse,
+    prefix: &str,
+) -> Vec<CompletionCandidate> {
+    if let Some(tag_prefix) = prefix.strip_prefix(TAG_PREFIX) {
+        let tags: BTreeSet<&String> = aliases
+            .values()
+            .flat_map(|entry| &entry.metadata.tags)
+            .filter(|tag| tag.starts_with(tag_prefix))
+            .collect();
+        return tags
+            .into_iter()
+            .map(|tag| CompletionCandidate::new(format!("{TAG_PREFIX}{tag}")))
+            .collect();
+    }
+    let matching = aliases
+        .iter()
+        .filter(|(alias, _)| alias.starts_with(prefix))
+        .map(|(alias, entry)| (alias, &entry.device_url));
+    let labels = devices
+        .iter()
+        .filter(|d| d.label.starts_with(prefix) && !d.label.contains(char::is_whitespace))
+        .filter(|d| !aliases.contains_key(&d.label))
+        .map(|d| (&d.label, &d.device_url));
+
+    matching
+        .chain(labels)
+        .map(|(name, device_url)| {
+            CompletionCandidate::new(name).help(Some(device_url.clone().into()))
+        })
+        .collect()
+}
+
+fn cached_devices() -> GetDevicesResponse {
+    DeviceCache::default()
+        .load()
+        .ok()
+        .flatten()
+        .map(|cached| cached.devices)
+        .unwrap_or_default()
+}
+
+/// Completes device arguments with the aliases from the alias file and the labels of the device cache
+pub(crate) fn complete_device(current: &OsStr) -> Vec<CompletionCandidate> {
+    let Some(prefix) = current.to_str() else {
+        return vec![];
+    };
+    let aliases = AliasManager::default().load_aliases().unwrap_or_default();
+    device_candidates(&aliases, &cached_devices(), prefix)
+}
+
+fn alias_candidates(aliases: &Aliases, prefix: &str) -> Vec<CompletionCandidate> {
+    aliases
+        .iter()
+        .filter(|(alias, _)| alias.starts_with(prefix))
+        .map(|(alias, entry)| {
+            CompletionCandidate::new(alias).help(Some(entry.device_url.clone().into()))
+        })
+        .collect()
+}
+
+/// Completes existing aliases only, e.g. for removing or renaming them
+pub(crate) fn complete_alias(current: &OsStr) -> Vec<CompletionCandidate> {
+    let Some(prefix) = current.to_str() else {
+        return vec![];
+    };
+    let aliases = AliasManager::default().load_aliases().unwrap_or_default();
+    alias_candidates(&aliases, prefix)
+}
+
+/// Completes device URLs from the device cache
+pub(crate) fn complete_device_url(current: &OsStr) -> Vec<CompletionCandidate> {
+    let Some(prefix) = current.to_str() else {
+        return vec![];
+    };
+    cached_devices()
+        .into_iter()
+        .filter(|d| d.device_url.starts_with(prefix))
+        .map(|d| CompletionCandidate::new(d.device_url).help(Some(d.label.into())))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::alias::AliasEntry;
+    use serde_json::json;
+
+    #[test]
+    fn test_device_candidates_match_alias_and_label_prefix() {
+        let aliases = Aliases::from([
+            ("living-room".to_string(), "io://1/1".to_string().into()),
+            ("library".to_string(), "io://1/2".to_string().into()),
+            ("kitchen".to_string(), "io://1/3".to_string().into()),
+        ]);
+        let devices = serde_json::from_value(json!([
+            {"deviceURL": "io://1/4", "label": "light", "controllableName": "io:Light",
+             "subsystemId": 0, "type": 1, "available": true, "synced": true, "enabled": true,
+             "states": [], "attributes": []},
+            {"deviceURL": "io://1/5", "label": "lift gate", "controllableName": "io:Gate",
+             "subsystemId": 0, "type": 1, "available": true, "synced": true, "enabled": true,
+             "states": [], "attributes": []}
+        ]))
+        .unwrap();
+
+        let candidates: Vec<String> = device_candidates(&aliases, &devices, "li")
+            .iter()
+            .map(|c| c.get_value().to_string_lossy().into_owned())
+            .collect();
+
+        assert_eq!(candidates, vec!["library", "living-room", "light"]);
+    }
+
+    #[test]
+    fn test_alias_candidates_leave_out_labels_and_groups() {
+        let mut kitchen: AliasEntry = "io://1/3".to_string().into();
+        kitchen.metadata.tags = vec!["kids".to_string()];
+        let aliases = Aliases::from([
+            ("kitchen".to_string(), kitchen),
+            ("living-room".to_string(), "io://1/1".to_string().into()),
+        ]);
+
+        let candidates: Vec<String> = alias_candidates(&aliases, "k")
+            .iter()
+            .map(|c| c.get_value().to_string_lossy().into_owned())
+            .collect();
+
+        assert_eq!(candidates, vec!["kitchen"]);
+        assert!(alias_candidates(&aliases, "tag:").is_empty());
+    }
+
+    #[test]
+    fn test_registration_calls_back_into_binary() {
+        let mut script = vec![];
+        write_registration(CompletionShell::Bash, "somfy-cli", &mut script).unwrap();
+
+        let script = String::from_utf8(script).unwrap();
+        assert!(script.contains("COMPLETE=\"bash\""));
+        assert!(script.contains("somfy"));
+    }
+}
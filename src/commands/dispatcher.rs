@@ -1,21 +1,381 @@
-use crate::commands::cli::{AliasCommands, Command};
-use crate::commands::executor::CommandExecutor;
-use crate::config::alias::AliasManager;
-use crate::output::formatter::{print_to_console, OutputStyle};
+use crate::automation::rule_engine::RuleEngine;
+use crate::automation::{rule_engine, scheduler};
+use crate::cloud::client::{CloudClient, DEFAULT_CLOUD_URL};
+use crate::commands::batch::{
+    parse_script, read_script, BatchDirective, BatchReport, BatchStatus, BatchStepResult,
+};
+use crate::commands::cli::{
+    AliasCommands, BatchArgs, CertArgs, CertCommands, Cli, Command, ConfigCommands, HistoryArgs,
+    KeyringCommands, ListArgs, ListenArgs, PositionChange, RulesCommands, ScheduleCommands,
+    TokenArgs, TokenCommands,
+};
+use crate::commands::completions::write_registration;
+use crate::commands::error::{cli_error, error_kind_of, ErrorKind, WithErrorKind};
+use crate::commands::executor::{CommandExecutor, OpenClose};
+use crate::commands::gateway_client::GatewayClient;
+use crate::commands::shell;
+use crate::config::alias::{
+    json_alias_location, parse_aliases, AliasChangeKind, AliasEntry, AliasFileFormat, AliasManager,
+    AliasMetadata, TAG_PREFIX,
+};
+use crate::config::certificate::{
+    certificate_info, fetch_gateway_ca, gateway_ca_location, gateway_certificates,
+    normalize_fingerprint, read_pem_certificates, CertificateInfos,
+};
+use crate::config::common::ConfigPaths;
+use crate::config::device_cache::DeviceCache;
+use crate::config::dotenv::{load_profile_settings, store_profile_values, CliApiClientConfig};
+use crate::config::loader::GatewayAddress;
+use crate::config::rules::load_rules_file;
+use crate::config::schedule::load_schedule_file;
+use crate::config::secrets::{delete_from_keyring, store_in_keyring};
+use crate::config::toml_file::{convert_to_toml, toml_file_location};
+use crate::config::{dotenv, rules, schedule};
+use crate::events::recording::{read_recording, EventRecorder};
+use crate::events::stream;
+use crate::events::stream::{EventFilter, EventSource};
+use crate::events::watch::{watch, WatchTarget};
+use crate::history::store::{HistoryEntries, HistoryQuery, HistoryStore};
+use crate::mock;
+use crate::mock::server::MockServerConfig;
+use crate::output::formatter::{AnyOutput, CliOutput, OutputFormat, OutputStyle};
+use crate::tui;
 use crate::utils::poller::PollerConfig;
-use log::debug;
-use somfy_sdk::api_client::ApiClient;
+use anyhow::Context;
+use chrono::{Local, Utc};
+use clap::CommandFactory;
+use log::{debug, info, warn};
+use serde_json::Map;
 use somfy_sdk::commands::execute_action_group::ExecuteActionGroupResponse;
+use somfy_sdk::commands::get_devices::GetDevicesResponse;
 use somfy_sdk::commands::get_execution::GetExecutionResponse;
+use std::io::IsTerminal;
+use std::sync::Mutex;
+use tokio::time::sleep;
+
+const EXECUTION_FAILED_STATE: &str = "FAILED";
+/// Password of the Somfy account, read from stdin if unset
+const CLOUD_PASSWORD_VAR: &str = "SOMFY_CLOUD_PASSWORD";
+/// Account and endpoint from the environment are used for the login, but never stored in the profile
+const CLOUD_USER_VAR: &str = "SOMFY_CLOUD_USER";
+const CLOUD_URL_VAR: &str = "SOMFY_CLOUD_URL";
+
+/// Resolves an alias or a device label from the device cache, anything else has to be a device URL
+fn resolve_device(alias_manager: &AliasManager, device: &str) -> anyhow::Result<String> {
+    let device_url = alias_manager.resolve_alias(device);
+    if device_url.contains("://") {
+        return Ok(device_url);
+    }
+    DeviceCache::default()
+        .device_url_of(device)
+        .ok_or(cli_error(
+            ErrorKind::DeviceNotFound,
+            anyhow::anyhow!("'{device}' is neither a known alias, device label nor device URL"),
+        ))
+}
+
+/// Like `resolve_device`, `tag:<tag>` resolves to the devices of all aliases with that tag
+fn resolve_devices(alias_manager: &AliasManager, device: &str) -> anyhow::Result<Vec<String>> {
+    if device.starts_with(TAG_PREFIX) {
+        return alias_manager.resolve_aliases(device);
+    }
+    Ok(vec![resolve_device(alias_manager, device)?])
+}
+
+/// Reads a secret from stdin, so that it neither shows up in the shell history nor in the process list
+fn read_secret(prompt: &str, name: &str) -> anyhow::Result<String> {
+    if std::io::stdin().is_terminal() {
+        eprint!("{prompt}: ");
+    }
+    let mut secret = String::new();
+    std::io::stdin()
+        .read_line(&mut secret)
+        .context(format!("Failed to read the {name} from stdin"))?;
+    let secret = secret.trim();
+    if secret.is_empty() {
+        anyhow::bail!("The provided {name} is empty");
+    }
+    Ok(secret.to_string())
+}
+
+fn missing_setting(message: &str) -> anyhow::Error {
+    cli_error(ErrorKind::Config, anyhow::anyhow!("{message}"))
+}
+
+/// Logs into the cloud with the account of the arguments or the profile
+async fn cloud_login(
+    args: &TokenArgs,
+    settings: &CliApiClientConfig,
+) -> anyhow::Result<CloudClient> {
+    let user = args
+        .user
+        .clone()
+        .or(std::env::var(CLOUD_USER_VAR).ok())
+        .or(settings.cloud_user.clone())
+        .ok_or(missing_setting(
+            "No Somfy account given, pass --user or set cloud_user in env.json",
+        ))?;
+    let cloud_url = args
+        .cloud_url
+        .clone()
+        .or(std::env::var(CLOUD_URL_VAR).ok())
+        .or(settings.cloud_url.clone())
+        .unwrap_or(DEFAULT_CLOUD_URL.to_string());
+    let password = match std::env::var(CLOUD_PASSWORD_VAR) {
+        Ok(password) => password,
+        Err(_) => read_secret(&format!("Password for '{user}'"), "password")?,
+    };
+    CloudClient::login(&cloud_url, &user, &password).await
+}
+
+fn query_history(
+    alias_manager: &AliasManager,
+    args: HistoryArgs,
+) -> anyhow::Result<HistoryEntries> {
+    let location = HistoryStore::location();
+    if !location.exists() {
+        return Err(cli_error(
+            ErrorKind::Config,
+            anyhow::anyhow!(
+                "No history recorded yet, enable it with \"history\": {{\"retention_days\": 90}} in env.json"
+            ),
+        ));
+    }
+    let device_url = args
+        .device_url
+        .map(|device| resolve_device(alias_manager, &device))
+        .transpose()?;
+
+    HistoryStore::open(&location)?.query(&HistoryQuery {
+        device_url,
+        since: args.since,
+        name: args.name,
+        kind: args.kind,
+        limit: args.limit,
+    })
+}
+
+/// What a command hands back instead of printing it, so that a batch can collect it.
+/// The failure is reported after the output, e.g. for failed executions.
+#[derive(Default)]
+pub(crate) struct CommandOutcome {
+    output: Option<Box<dyn AnyOutput>>,
+    failure: Option<anyhow::Error>,
+}
+
+impl CommandOutcome {
+    fn of<T: CliOutput + 'static>(response: T) -> Self {
+        Self {
+            output: Some(Box::new(response)),
+            failure: None,
+        }
+    }
+}
 
 pub struct CommandDispatcher {
-    cmd_executor: CommandExecutor,
+    cmd_executor: Option<CommandExecutor>,
+    /// Profile of env.json selected with `--profile`
+    profile: Option<String>,
+    gateway_address: Option<GatewayAddress>,
+    /// Polling of execution results, configured with `poller`
+    poller: PollerConfig,
 }
 
 impl CommandDispatcher {
-    pub(crate) fn from(api_client: ApiClient) -> Self {
-        let cmd_executor = CommandExecutor { api_client };
-        Self { cmd_executor }
+    pub(crate) fn from(api_client: GatewayClient, history: Option<HistoryStore>) -> Self {
+        let cmd_executor = CommandExecutor {
+            api_client,
+            history,
+            capabilities: Mutex::default(),
+        };
+        Self {
+            cmd_executor: Some(cmd_executor),
+            profile: None,
+            gateway_address: None,
+            poller: PollerConfig::default(),
+        }
+    }
+
+    /// Dispatcher for commands that do not talk to the gateway
+    pub(crate) fn offline() -> Self {
+        Self {
+            cmd_executor: None,
+            profile: None,
+            gateway_address: None,
+            poller: PollerConfig::default(),
+        }
+    }
+
+    pub(crate) fn with_poller(mut self, poller: PollerConfig) -> Self {
+        self.poller = poller;
+        self
+    }
+
+    pub(crate) fn with_profile(mut self, profile: Option<String>) -> Self {
+        self.profile = profile;
+        self
+    }
+
+    pub(crate) fn with_gateway_address(mut self, gateway_address: Option<GatewayAddress>) -> Self {
+        self.gateway_address = gateway_address;
+        self
+    }
+
+    pub(crate) fn executor(&self) -> anyhow::Result<&CommandExecutor> {
+        self.cmd_executor.as_ref().ok_or(anyhow::anyhow!(
+            "This command requires a gateway connection"
+        ))
+    }
+
+    /// Runs a single step of a batch script
+    async fn run_step(
+        &self,
+        directive: BatchDirective,
+        format: &OutputFormat,
+    ) -> anyhow::Result<CommandOutcome> {
+        match directive {
+            BatchDirective::Wait(duration) => sleep(duration).await,
+            BatchDirective::WaitIdle => {
+                self.executor()?
+                    .wait_until_idle(PollerConfig::EVENT_LISTENER)
+                    .await?
+            }
+            BatchDirective::Command(command) => return Box::pin(self.run(*command, format)).await,
+        }
+        Ok(CommandOutcome::default())
+    }
+
+    /// Runs all steps of the script and reports them combined.
+    /// Without `--continue-on-error` the steps after the first failure are skipped.
+    async fn run_batch(
+        &self,
+        args: BatchArgs,
+        format: &OutputFormat,
+    ) -> anyhow::Result<CommandOutcome> {
+        let steps = parse_script(&read_script(&args.file)?)?;
+        let total = steps.len();
+        let mut report = BatchReport::new();
+        let mut first_error: Option<anyhow::Error> = None;
+
+        for (idx, step) in steps.into_iter().enumerate() {
+            let mut result = BatchStepResult {
+                line: step.line,
+                command: step.source,
+                status: BatchStatus::Skipped,
+                output: None,
+                error: None,
+            };
+            if first_error.is_some() && !args.continue_on_error {
+                report.push(result);
+                continue;
+            }
+            if let Some(delay) = args.delay.filter(|_| idx > 0) {
+                sleep(delay).await;
+            }
+
+            let outcome = self
+                .run_step(step.directive, format)
+                .await
+                .and_then(|outcome| {
+                    result.output = outcome.output.map(|o| o.value()).transpose()?;
+                    outcome.failure.map_or(Ok(()), Err)
+                });
+            match outcome {
+                Ok(()) => result.status = BatchStatus::Ok,
+                Err(e) => {
+                    result.status = BatchStatus::Failed;
+                    result.error = Some(format!("{e:#}"));
+                    first_error.get_or_insert(e);
+                }
+            }
+            report.push(result);
+        }
+
+        let failed = report
+            .iter()
+            .filter(|r| r.status == BatchStatus::Failed)
+            .count();
+        let failure = first_error.map(|e| {
+            cli_error(
+                error_kind_of(&e),
+                anyhow::anyhow!("{failed} of {total} batch commands failed"),
+            )
+        });
+        Ok(CommandOutcome {
+            failure,
+            ..CommandOutcome::of(report)
+        })
+    }
+
+    /// Runs the listen pipeline, every formatted event is handed to `emit`
+    pub(crate) async fn listen(
+        &self,
+        args: ListenArgs,
+        format: &OutputFormat,
+        emit: impl FnMut(String) -> anyhow::Result<()>,
+    ) -> anyhow::Result<()> {
+        let alias_manager = AliasManager::default();
+        let source = match &args.replay {
+            Some(path) => EventSource::replay(read_recording(path)?, args.speed),
+            None => EventSource::live(self.executor()?).await?,
+        };
+        let recorder = args
+            .record
+            .as_deref()
+            .map(EventRecorder::create)
+            .transpose()?;
+        let filter = EventFilter {
+            event_names: args.events,
+            device_urls: args
+                .devices
+                .iter()
+                .map(|d| alias_manager.resolve_aliases(d))
+                .collect::<anyhow::Result<Vec<_>>>()?
+                .concat(),
+        };
+        stream::listen(source, &filter, recorder, format, emit).await
+    }
+
+    /// Devices from the cache with `--cached` or a fresh enough cache, from the gateway otherwise
+    async fn list_devices(&self, args: &ListArgs) -> anyhow::Result<GetDevicesResponse> {
+        let cache = DeviceCache::default();
+        if args.cached {
+            let cached = cache.load()?.ok_or(cli_error(
+                ErrorKind::Config,
+                anyhow::anyhow!(
+                    "No devices cached yet, run 'somfy ls' while the gateway is reachable"
+                ),
+            ))?;
+            debug!("Using devices cached at {}", cached.updated);
+            return Ok(cached.devices);
+        }
+        if let Some(max_age) = args.max_age {
+            let fresh = cache.load()?.filter(|c| c.is_fresh(max_age, Utc::now()));
+            if let Some(cached) = fresh {
+                debug!("Using devices cached at {}", cached.updated);
+                return Ok(cached.devices);
+            }
+        }
+        self.executor()?.list_devices().await
+    }
+
+    /// Devices as listed by `ls`, only the tagged ones with `--tag`
+    async fn list_tagged_devices(
+        &self,
+        args: &ListArgs,
+        alias_manager: &AliasManager,
+    ) -> anyhow::Result<GetDevicesResponse> {
+        let devices = self.list_devices(args).await?;
+        let Some(tag) = &args.tag else {
+            return Ok(devices);
+        };
+        let device_urls = alias_manager
+            .tagged_devices(tag)
+            .error_kind(ErrorKind::Config)?;
+        Ok(devices
+            .into_iter()
+            .filter(|device| device_urls.contains(&device.device_url))
+            .collect())
     }
 
     async fn try_poll(
@@ -24,84 +384,448 @@ impl CommandDispatcher {
         pc: PollerConfig,
     ) -> anyhow::Result<GetExecutionResponse> {
         let res = self
-            .cmd_executor
+            .executor()?
             .get_execution_with_full_response(eagr.exec_id.as_str(), pc)
             .await?;
 
         Ok(res)
     }
 
+    /// The immediate response, or the polled execution result. Failed executions are reported as failure.
+    async fn execution_outcome(
+        &self,
+        exec_resp: ExecuteActionGroupResponse,
+        no_poll: bool,
+    ) -> anyhow::Result<CommandOutcome> {
+        if no_poll {
+            return Ok(CommandOutcome::of(exec_resp));
+        }
+
+        let detailed_resp = self.try_poll(exec_resp, self.poller).await?;
+        let failure = (detailed_resp.state == EXECUTION_FAILED_STATE).then(|| {
+            cli_error(
+                ErrorKind::ExecutionFailed,
+                anyhow::anyhow!("Execution {} failed", detailed_resp.id),
+            )
+        });
+        Ok(CommandOutcome {
+            failure,
+            ..CommandOutcome::of(detailed_resp)
+        })
+    }
+
+    async fn manage_tokens(&self, args: TokenArgs) -> anyhow::Result<CommandOutcome> {
+        let profile = self.profile.as_deref();
+        let settings = load_profile_settings(profile).error_kind(ErrorKind::Config)?;
+        let gateway_id = args
+            .gateway_id
+            .clone()
+            .or(settings.gateway_id.clone())
+            .ok_or(missing_setting(
+                "No gateway given, pass --gateway-id or set gateway_id in env.json",
+            ))?;
+        let cloud = cloud_login(&args, &settings).await?;
+
+        let outcome = match args.token_cmd {
+            TokenCommands::Generate => CommandOutcome::of(cloud.generate_token(&gateway_id).await?),
+            TokenCommands::Activate(a) => {
+                let token = match a.token {
+                    Some(token) => token,
+                    None => cloud.generate_token(&gateway_id).await?.token,
+                };
+                let activated = cloud.activate_token(&gateway_id, &token, &a.label).await?;
+
+                let mut values = Map::new();
+                values.insert("gateway_id".to_string(), gateway_id.into());
+                if let Some(user) = args.user {
+                    values.insert("cloud_user".to_string(), user.into());
+                }
+                if let Some(cloud_url) = args.cloud_url {
+                    values.insert("cloud_url".to_string(), cloud_url.into());
+                }
+                if let Some(account) = &settings.api_key_keyring {
+                    store_in_keyring(account, &token).error_kind(ErrorKind::Config)?;
+                } else {
+                    values.insert("api_key".to_string(), token.into());
+                }
+                store_profile_values(profile, values)
+                    .context("The token was activated but could not be stored")
+                    .error_kind(ErrorKind::Config)?;
+                info!("Stored the token as API key of the profile");
+                CommandOutcome::of(activated)
+            }
+            TokenCommands::Ls => CommandOutcome::of(cloud.list_tokens(&gateway_id).await?),
+            TokenCommands::Revoke(r) => {
+                cloud.revoke_token(&gateway_id, &r.uuid).await?;
+                info!("Revoked token {}", r.uuid);
+                CommandOutcome::default()
+            }
+        };
+        Ok(outcome)
+    }
+
+    /// The certificate chain the gateway presents
+    async fn presented_certificates(&self) -> anyhow::Result<CertificateInfos> {
+        let address = self
+            .gateway_address
+            .as_ref()
+            .ok_or(anyhow::anyhow!("This command requires the gateway address"))?;
+        gateway_certificates(&address.hostname, address.port)
+            .await?
+            .iter()
+            .map(|certificate| certificate_info(certificate))
+            .collect()
+    }
+
+    async fn manage_certificates(&self, args: CertArgs) -> anyhow::Result<CommandOutcome> {
+        let profile = self.profile.as_deref();
+        let outcome = match args.cert_cmd {
+            CertCommands::Fetch(f) => {
+                let (location, certificates) = fetch_gateway_ca(&f.url).await?;
+                let mut values = Map::new();
+                values.insert(
+                    "cert_path".to_string(),
+                    location.to_string_lossy().into_owned().into(),
+                );
+                store_profile_values(profile, values).error_kind(ErrorKind::Config)?;
+                info!("Stored the gateway CA at {location:?} as cert_path of the profile");
+                CommandOutcome::of(certificates)
+            }
+            CertCommands::Show(s) => {
+                let certificates = match s.path {
+                    Some(path) => {
+                        let pem = std::fs::read(&path)
+                            .context(format!("Failed to read {path:?}"))
+                            .error_kind(ErrorKind::Config)?;
+                        read_pem_certificates(&pem).context(format!("Failed to read {path:?}"))?
+                    }
+                    None => self.presented_certificates().await?,
+                };
+                for expired in certificates.iter().filter(|c| c.not_after < Utc::now()) {
+                    warn!("'{}' expired on {}", expired.subject, expired.not_after);
+                }
+                CommandOutcome::of(certificates)
+            }
+            CertCommands::Pin(p) => {
+                let fingerprint = match p.fingerprint {
+                    Some(fingerprint) => {
+                        normalize_fingerprint(&fingerprint).error_kind(ErrorKind::Config)?
+                    }
+                    None => {
+                        let presented = self.presented_certificates().await?;
+                        info!("The gateway presents '{}'", presented[0].subject);
+                        presented[0].fingerprint.clone()
+                    }
+                };
+                let mut values = Map::new();
+                values.insert("cert_fingerprint".to_string(), fingerprint.clone().into());
+                store_profile_values(profile, values).error_kind(ErrorKind::Config)?;
+                info!("Pinned the certificate fingerprint {fingerprint}");
+                CommandOutcome::default()
+            }
+        };
+        Ok(outcome)
+    }
+
+    /// Runs the command and prints its response
     pub(crate) async fn dispatch(
         &self,
         command: Command,
-        style: OutputStyle,
+        format: &OutputFormat,
     ) -> anyhow::Result<()> {
+        let outcome = self.run(command, format).await?;
+        if let Some(output) = outcome.output {
+            println!("{}", output.output(format)?);
+        }
+        debug!("Command succeeded");
+        outcome.failure.map_or(Ok(()), Err)
+    }
+
+    /// Runs the command and hands back its response instead of printing it
+    async fn run(&self, command: Command, format: &OutputFormat) -> anyhow::Result<CommandOutcome> {
         let alias_manager = AliasManager::default();
-        match command {
+        let outcome = match command {
             Command::Open(args) => {
-                let device_url = alias_manager.resolve_alias(&args.device_url);
-                let exec_resp = self.cmd_executor.open(device_url).await?;
-
-                if args.no_poll {
-                    print_to_console(exec_resp, style)
-                } else {
-                    let detailed_resp = self.try_poll(exec_resp, PollerConfig::default()).await?;
-                    print_to_console(detailed_resp, style)
-                };
+                let device_urls = resolve_devices(&alias_manager, &args.device_url)?;
+                let exec_resp = self.executor()?.apply(device_urls, OpenClose::Open).await?;
+                self.execution_outcome(exec_resp, args.no_poll).await?
             }
             Command::Close(args) => {
-                let device_url = alias_manager.resolve_alias(&args.device_url);
-                let exec_resp = self.cmd_executor.close(device_url).await?;
-                if args.no_poll {
-                    print_to_console(exec_resp, style)
+                let device_urls = resolve_devices(&alias_manager, &args.device_url)?;
+                let exec_resp = self
+                    .executor()?
+                    .apply(device_urls, OpenClose::Close)
+                    .await?;
+                self.execution_outcome(exec_resp, args.no_poll).await?
+            }
+            Command::ListDevices(args) if args.watch.watch => {
+                watch(self.executor()?, WatchTarget::Devices, format).await?;
+                CommandOutcome::default()
+            }
+            Command::ListDevices(args) => {
+                let devices_resp = self.list_tagged_devices(&args, &alias_manager).await?;
+                CommandOutcome::of(devices_resp)
+            }
+            Command::State(args) => {
+                let device_url = resolve_device(&alias_manager, &args.device_url)?;
+                if args.watch.watch {
+                    let target = WatchTarget::DeviceStates(device_url);
+                    watch(self.executor()?, target, format).await?;
+                    CommandOutcome::default()
                 } else {
-                    let detailed_resp = self.try_poll(exec_resp, PollerConfig::default()).await?;
-                    print_to_console(detailed_resp, style)
-                };
+                    let states_resp = self.executor()?.get_device_states(&device_url).await?;
+                    CommandOutcome::of(states_resp)
+                }
             }
-            Command::ListDevices => {
-                let devices_resp = self.cmd_executor.list_devices().await?;
-                print_to_console(devices_resp, style);
+            Command::Commands(args) => {
+                let device_url = resolve_device(&alias_manager, &args.device_url)?;
+                let capabilities = self.executor()?.get_capabilities(&device_url).await?;
+                CommandOutcome::of(capabilities)
             }
-            Command::GetCurrentExecutions => {
-                let execs_resp = self.cmd_executor.get_current_executions().await?;
-                print_to_console(execs_resp, style);
+            Command::GetCurrentExecutions(args) if args.watch => {
+                watch(self.executor()?, WatchTarget::Executions, format).await?;
+                CommandOutcome::default()
+            }
+            Command::GetCurrentExecutions(_) => {
+                let execs_resp = self.executor()?.get_current_executions().await?;
+                CommandOutcome::of(execs_resp)
             }
             Command::Position(args) => {
-                let device_url = alias_manager.resolve_alias(&args.device_url);
+                let device_urls = resolve_devices(&alias_manager, &args.device_url)?;
+                let (percentage, invert) = match args.percentage {
+                    Some(percentage) => (percentage, args.invert),
+                    // Default positions are closures, --invert requires a percentage
+                    None => (
+                        PositionChange::Absolute(alias_manager.default_position(&args.device_url)?),
+                        false,
+                    ),
+                };
+                let current = match percentage {
+                    PositionChange::Relative(_) if device_urls.len() > 1 => {
+                        return Err(cli_error(
+                            ErrorKind::Usage,
+                            anyhow::anyhow!(
+                                "Relative positions can only be applied to a single device"
+                            ),
+                        ));
+                    }
+                    PositionChange::Relative(_) => {
+                        Some(self.executor()?.get_closure(&device_urls[0]).await?)
+                    }
+                    PositionChange::Absolute(_) => None,
+                };
+                let closure = percentage.target_closure(current, invert)?;
                 let exec_resp = self
-                    .cmd_executor
-                    .closure(device_url, args.percentage)
+                    .executor()?
+                    .apply(device_urls, OpenClose::Closure(closure))
                     .await?;
-                if args.no_poll {
-                    print_to_console(exec_resp, style)
-                } else {
-                    let detailed_resp = self.try_poll(exec_resp, PollerConfig::default()).await?;
-                    print_to_console(detailed_resp, style)
-                };
+                self.execution_outcome(exec_resp, args.no_poll).await?
+            }
+            Command::Batch(args) => self.run_batch(args, format).await?,
+            Command::History(args) => {
+                let entries = query_history(&alias_manager, args)?;
+                CommandOutcome::of(entries)
+            }
+            Command::Completions(args) => {
+                let name = Cli::command().get_name().to_string();
+                write_registration(args.shell, &name, &mut std::io::stdout())?;
+                CommandOutcome::default()
+            }
+            Command::Shell => {
+                shell::run(self, format).await?;
+                CommandOutcome::default()
             }
-            Command::Listen => {
+            Command::Tui => {
+                tui::dashboard::run(self.executor()?, &alias_manager).await?;
+                CommandOutcome::default()
+            }
+            Command::Listen(args) => {
                 debug!("Listening for events");
-                let _ = self.cmd_executor.listen().await;
+                self.listen(args, format, |line| {
+                    println!("{line}");
+                    Ok(())
+                })
+                .await?;
+                CommandOutcome::default()
             }
             Command::Alias(a) => match a.alias_cmd {
                 AliasCommands::Add(a) => {
-                    let aliases =
-                        alias_manager.add_alias(a.alias_name, a.device_url, a.overwrite)?;
+                    let entry = AliasEntry {
+                        device_url: a.device_url,
+                        metadata: AliasMetadata {
+                            description: a.description,
+                            room: a.room,
+                            tags: a.tags,
+                            default_position: a.default_position,
+                        },
+                    };
+                    let aliases = alias_manager
+                        .add_alias(a.alias_name, entry, a.overwrite)
+                        .error_kind(ErrorKind::Config)?;
 
-                    print_to_console(aliases, style);
+                    CommandOutcome::of(aliases)
                 }
                 AliasCommands::Rm(r) => {
-                    let aliases = alias_manager.delete_alias(r.alias_name)?;
-                    print_to_console(aliases, style);
+                    let aliases = alias_manager
+                        .delete_alias(r.alias_name)
+                        .error_kind(ErrorKind::Config)?;
+                    CommandOutcome::of(aliases)
+                }
+                AliasCommands::Rename(r) => {
+                    let aliases = alias_manager
+                        .rename_alias(&r.alias_name, r.new_name, r.overwrite)
+                        .error_kind(ErrorKind::Config)?;
+                    CommandOutcome::of(aliases)
+                }
+                AliasCommands::Export(e) => {
+                    let aliases = alias_manager.load_aliases().error_kind(ErrorKind::Config)?;
+                    match e.file {
+                        None => CommandOutcome::of(aliases),
+                        Some(path) => {
+                            let style = match AliasFileFormat::of(&path, e.file_format)
+                                .error_kind(ErrorKind::Config)?
+                            {
+                                AliasFileFormat::Json => OutputStyle::Json,
+                                AliasFileFormat::Yaml => OutputStyle::Yaml,
+                                AliasFileFormat::Csv => OutputStyle::Csv,
+                            };
+                            let contents = aliases.to_cli_output(style)?;
+                            std::fs::write(&path, format!("{contents}\n"))
+                                .context(format!("Failed to write {path:?}"))?;
+                            info!("Exported {} aliases to {path:?}", aliases.len());
+                            CommandOutcome::default()
+                        }
+                    }
+                }
+                AliasCommands::Import(i) => {
+                    let file_format = AliasFileFormat::of(&i.file, i.file_format)
+                        .error_kind(ErrorKind::Config)?;
+                    let imported = std::fs::read_to_string(&i.file)
+                        .map_err(anyhow::Error::from)
+                        .and_then(|contents| parse_aliases(&contents, file_format))
+                        .context(format!("Failed to read aliases from {:?}", i.file))
+                        .error_kind(ErrorKind::Config)?;
+                    let changes = alias_manager
+                        .import_aliases(imported, i.mode, i.overwrite, i.dry_run)
+                        .error_kind(ErrorKind::Config)?;
+                    let conflicts = changes
+                        .iter()
+                        .filter(|c| c.change == AliasChangeKind::Conflict)
+                        .count();
+                    if conflicts > 0 {
+                        warn!("Kept {conflicts} aliases that differ from the imported ones, pass --overwrite to replace them");
+                    }
+                    if i.dry_run {
+                        info!("Dry run, no aliases were changed");
+                    }
+                    CommandOutcome::of(changes)
                 }
-                AliasCommands::Ls => {
-                    let aliases = alias_manager.load_aliases()?;
-                    print_to_console(aliases, style);
+                AliasCommands::Ls(l) => {
+                    let mut aliases = alias_manager.load_aliases().error_kind(ErrorKind::Config)?;
+                    aliases.retain(|_, entry| {
+                        l.tag.as_ref().is_none_or(|tag| entry.has_tag(tag))
+                            && l.room
+                                .as_ref()
+                                .is_none_or(|room| entry.metadata.room.as_ref() == Some(room))
+                    });
+                    CommandOutcome::of(aliases)
                 }
             },
-        }
+            Command::Keyring(k) => match k.keyring_cmd {
+                KeyringCommands::Set(a) => {
+                    let api_key = read_secret(&format!("API key for '{}'", a.account), "api key")?;
+                    store_in_keyring(&a.account, &api_key).error_kind(ErrorKind::Config)?;
+                    info!(
+                        "Stored the API key of '{}' in the system keyring",
+                        a.account
+                    );
+                    CommandOutcome::default()
+                }
+                KeyringCommands::Delete(a) => {
+                    delete_from_keyring(&a.account).error_kind(ErrorKind::Config)?;
+                    info!(
+                        "Removed the API key of '{}' from the system keyring",
+                        a.account
+                    );
+                    CommandOutcome::default()
+                }
+            },
+            Command::Token(t) => self.manage_tokens(t).await?,
+            Command::Cert(c) => self.manage_certificates(c).await?,
+            Command::Config(c) => match c.config_cmd {
+                ConfigCommands::Path => {
+                    let paths = ConfigPaths::of(vec![
+                        ("settings", dotenv::get_file_location()),
+                        ("aliases", alias_manager.location().to_path_buf()),
+                        (
+                            "device cache",
+                            DeviceCache::default().location().to_path_buf(),
+                        ),
+                        ("history", HistoryStore::location()),
+                        ("schedule", schedule::get_file_location()),
+                        ("rules", rules::get_file_location()),
+                        ("gateway CA", gateway_ca_location()),
+                        ("shell history", shell::history_location()),
+                    ]);
+                    CommandOutcome::of(paths)
+                }
+                ConfigCommands::Convert(c) => {
+                    let toml_path = toml_file_location();
+                    let backups = convert_to_toml(
+                        &dotenv::json_file_location(),
+                        &json_alias_location(),
+                        &toml_path,
+                        c.force,
+                    )
+                    .error_kind(ErrorKind::Config)?;
+                    for backup in backups {
+                        info!("Converted and renamed to {backup:?}");
+                    }
+                    info!("Wrote {toml_path:?}");
+                    CommandOutcome::default()
+                }
+            },
+            Command::Schedule(s) => {
+                let schedule = load_schedule_file().error_kind(ErrorKind::Config)?;
+                match s.schedule_cmd {
+                    ScheduleCommands::Run => {
+                        scheduler::run(&schedule, self.executor()?, &alias_manager).await?;
+                        CommandOutcome::default()
+                    }
+                    ScheduleCommands::Ls => {
+                        let overview = scheduler::overview(&schedule, Local::now())?;
+                        CommandOutcome::of(overview)
+                    }
+                }
+            }
+            Command::Rules(r) => {
+                let mut engine = RuleEngine::new(
+                    load_rules_file().error_kind(ErrorKind::Config)?,
+                    &alias_manager,
+                )?;
+                match r.rules_cmd {
+                    RulesCommands::Run => {
+                        rule_engine::run(&mut engine, self.executor()?).await?;
+                        CommandOutcome::default()
+                    }
+                    RulesCommands::Test(t) => {
+                        let recording = read_recording(&t.recording)?;
+                        CommandOutcome::of(engine.replay(&recording))
+                    }
+                }
+            }
+            Command::MockGateway(m) => {
+                mock::server::serve(MockServerConfig {
+                    bind: m.bind,
+                    port: m.port,
+                    token: m.token,
+                    speed: m.speed,
+                })
+                .await?;
+                CommandOutcome::default()
+            }
+        };
 
-        Ok(())
+        Ok(outcome)
     }
 }
@@ -0,0 +1,177 @@
+use crate::output::formatter::{OutputFormat, OutputStyle};
+use reqwest::StatusCode;
+use serde::Serialize;
+use serde_json::json;
+use somfy_sdk::err::http::RequestError;
+use std::fmt::{Display, Formatter};
+use std::process::ExitCode;
+
+/// Error categories scripts can rely on, each mapped to a distinct process exit code.
+/// Exit code 2 is used by clap for invalid command line arguments, and for arguments that turn out to be
+/// unusable once they are resolved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ErrorKind {
+    General,
+    Usage,
+    Config,
+    Auth,
+    GatewayUnreachable,
+    DeviceNotFound,
+    ExecutionFailed,
+    Timeout,
+}
+
+impl ErrorKind {
+    pub(crate) fn exit_code(self) -> u8 {
+        match self {
+            ErrorKind::General => 1,
+            ErrorKind::Usage => 2,
+            ErrorKind::Config => 3,
+            ErrorKind::Auth => 4,
+            ErrorKind::GatewayUnreachable => 5,
+            ErrorKind::DeviceNotFound => 6,
+            ErrorKind::ExecutionFailed => 7,
+            ErrorKind::Timeout => 8,
+        }
+    }
+}
+
+/// An error tagged with its kind. Survives further `.context(..)` calls, the outermost kind wins.
+#[derive(Debug)]
+pub(crate) struct CliError {
+    pub kind: ErrorKind,
+    error: anyhow::Error,
+}
+
+impl Display for CliError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:#}", self.error)
+    }
+}
+
+impl std::error::Error for CliError {}
+
+pub(crate) fn cli_error(kind: ErrorKind, error: impl Into<anyhow::Error>) -> anyhow::Error {
+    CliError {
+        kind,
+        error: error.into(),
+    }
+    .into()
+}
+
+pub(crate) trait WithErrorKind<T> {
+    fn error_kind(self, kind: ErrorKind) -> anyhow::Result<T>;
+}
+
+impl<T, E: Into<anyhow::Error>> WithErrorKind<T> for Result<T, E> {
+    fn error_kind(self, kind: ErrorKind) -> anyhow::Result<T> {
+        self.map_err(|e| cli_error(kind, e))
+    }
+}
+
+/// Categorizes errors of the SDK. Other non-2xx responses are reported as `fallback`.
+pub(crate) fn request_error(error: RequestError, fallback: ErrorKind) -> anyhow::Error {
+    let kind = match &error {
+        RequestError::Auth { .. } => ErrorKind::Auth,
+        RequestError::Status { status, .. } if [401, 403].contains(&status.as_u16()) => {
+            ErrorKind::Auth
+        }
+        RequestError::Status { .. } => fallback,
+        RequestError::Transport(e) if e.is_timeout() => ErrorKind::Timeout,
+        RequestError::Transport(_) | RequestError::Cert => ErrorKind::GatewayUnreachable,
+        RequestError::Body(_) | RequestError::Server(_) => ErrorKind::General,
+    };
+    cli_error(kind, error)
+}
+
+pub(crate) fn error_kind_of(error: &anyhow::Error) -> ErrorKind {
+    error
+        .downcast_ref::<CliError>()
+        .map(|e| e.kind)
+        .unwrap_or(ErrorKind::General)
+}
+
+/// HTTP status of a failed gateway request, e.g. to tell an expired event listener apart
+pub(crate) fn request_status(error: &anyhow::Error) -> Option<StatusCode> {
+    let request_error = match error.downcast_ref::<CliError>() {
+        Some(cli_error) => cli_error.error.downcast_ref::<RequestError>(),
+        None => error.downcast_ref::<RequestError>(),
+    };
+    match request_error? {
+        RequestError::Status { status, .. } => Some(*status),
+        _ => None,
+    }
+}
+
+/// Prints the error to stderr, as a JSON object for the JSON output styles
+pub(crate) fn report(error: &anyhow::Error, format: &OutputFormat) -> ExitCode {
+    let kind = error_kind_of(error);
+    match format {
+        OutputFormat::Style(OutputStyle::Json | OutputStyle::JsonCompact) => {
+            let json = json!({
+                "error": {
+                    "kind": kind,
+                    "exit_code": kind.exit_code(),
+                    "message": format!("{error:#}"),
+                }
+            });
+            eprintln!("{json}");
+        }
+        _ => eprintln!("Error: {error:#}"),
+    }
+    ExitCode::from(kind.exit_code())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    #[test]
+    fn test_kind_survives_context() {
+        let err: anyhow::Result<()> = Err(anyhow::anyhow!("missing hostname"));
+        let err = err
+            .error_kind(ErrorKind::Config)
+            .context("Failed to load configuration")
+            .unwrap_err();
+
+        assert_eq!(error_kind_of(&err), ErrorKind::Config);
+        assert_eq!(
+            format!("{err:#}"),
+            "Failed to load configuration: missing hostname"
+        );
+    }
+
+    #[test]
+    fn test_untagged_errors_are_general() {
+        let err = anyhow::anyhow!("something");
+        assert_eq!(error_kind_of(&err), ErrorKind::General);
+    }
+
+    #[test]
+    fn test_request_error_kinds() {
+        let auth = RequestError::Auth {
+            message: "auth failed".to_string(),
+            status: Some(StatusCode::UNAUTHORIZED),
+            source: None,
+        };
+        let status = RequestError::Status {
+            status: StatusCode::BAD_REQUEST,
+            source: None,
+        };
+
+        assert_eq!(
+            error_kind_of(&request_error(auth, ErrorKind::General)),
+            ErrorKind::Auth
+        );
+        assert_eq!(
+            error_kind_of(&request_error(status, ErrorKind::ExecutionFailed)),
+            ErrorKind::ExecutionFailed
+        );
+        assert_eq!(
+            error_kind_of(&request_error(RequestError::Cert, ErrorKind::General)),
+            ErrorKind::GatewayUnreachable
+        );
+    }
+}
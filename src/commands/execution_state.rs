@@ -0,0 +1,74 @@
+use reqwest::header::HeaderMap;
+use reqwest::Body;
+use serde::Deserialize;
+use somfy_sdk::commands::traits::{
+    HttpMethod, RequestData, SomfyApiRequestCommand, SomfyApiRequestResponse,
+};
+use somfy_sdk::commands::types::ActionGroupExecution;
+use somfy_sdk::err::http::RequestError;
+use std::collections::HashMap;
+use urlencoding::encode;
+
+/// Same endpoint as the SDK's `GetExecutionCommand`, which reports finished and unknown executions
+/// both as 404. The gateway answers `null` for the former and `[]` for the latter.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct GetExecutionStateCommand<'a> {
+    pub execution_id: &'a str,
+}
+
+impl SomfyApiRequestCommand for GetExecutionStateCommand<'_> {
+    type Response = ExecutionState;
+    fn to_request(&self) -> Result<RequestData, RequestError> {
+        let encoded_execution_id = encode(self.execution_id);
+        Ok(RequestData {
+            path: format!("/enduser-mobile-web/1/enduserAPI/exec/current/{encoded_execution_id}"),
+            method: HttpMethod::GET,
+            body: Body::default(),
+            query_params: HashMap::default(),
+            header_map: HeaderMap::default(),
+        })
+    }
+}
+
+/// Deserialize is required by the SDK, `from_body` decides between the variants
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub(crate) enum ExecutionState {
+    Running(ActionGroupExecution),
+    Finished,
+    Unknown,
+}
+
+impl SomfyApiRequestResponse for ExecutionState {
+    fn from_body(body: &str) -> Result<Self, RequestError> {
+        match body.trim() {
+            "null" => Ok(ExecutionState::Finished),
+            "[]" => Ok(ExecutionState::Unknown),
+            body => Ok(ExecutionState::Running(serde_json::from_str(body)?)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_distinguishes_finished_from_unknown_executions() {
+        assert_eq!(
+            ExecutionState::from_body("null").unwrap(),
+            ExecutionState::Finished
+        );
+        assert_eq!(
+            ExecutionState::from_body("[]").unwrap(),
+            ExecutionState::Unknown
+        );
+        let running = ExecutionState::from_body(
+            r#"{"owner": "", "id": "123", "executionType": "Immediate execution",
+                "executionSubType": "MANUAL_CONTROL", "description": "", "startTime": 0,
+                "actionGroup": {"label": "test", "actions": []}, "state": "IN_PROGRESS"}"#,
+        )
+        .unwrap();
+        assert!(matches!(running, ExecutionState::Running(e) if e.id == "123"));
+    }
+}
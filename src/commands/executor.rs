@@ -1,28 +1,69 @@
+use crate::commands::capabilities::{DeviceCapabilities, GetDeviceCapabilitiesCommand};
+use crate::commands::error::{cli_error, request_error, ErrorKind, WithErrorKind};
+use crate::commands::execution_state::{ExecutionState, GetExecutionStateCommand};
+use crate::commands::gateway_client::GatewayClient;
+use crate::config::device_cache::DeviceCache;
+use crate::events::fetch_device_events::{DeviceEvent, FetchDeviceEventsCommand};
+use crate::history::store::HistoryStore;
+use crate::output::formatter::Searchable;
 use crate::utils::poller::PollerConfig;
-use somfy_sdk::api_client::ApiClient;
+use chrono::Utc;
+use futures_util::future::try_join_all;
+use log::warn;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
 use somfy_sdk::commands::execute_action_group::ExecuteActionGroupResponse;
 use somfy_sdk::commands::get_current_executions::GetCurrentExecutionsResponse;
+use somfy_sdk::commands::get_device_states::GetDeviceStatesResponse;
 use somfy_sdk::commands::get_devices::GetDevicesResponse;
 use somfy_sdk::commands::get_execution::GetExecutionResponse;
-use somfy_sdk::commands::types::{Action, ActionGroup, Command};
+use somfy_sdk::commands::types::{Action, ActionGroup, Command, DeviceStateValue};
+use somfy_sdk::err::http::RequestError;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::sync::Mutex;
 use std::time::SystemTime;
 use tokio::time::sleep;
 
+const CLOSURE_STATE: &str = "core:ClosureState";
+/// State of executions the gateway already forgot, it does not tell whether they completed or failed
+const FINISHED_STATE: &str = "FINISHED";
+
 pub struct CommandExecutor {
-    pub(crate) api_client: ApiClient,
+    pub(crate) api_client: GatewayClient,
+    /// Records executions and received events when the history is enabled
+    pub(crate) history: Option<HistoryStore>,
+    /// Definitions fetched so far, they do not change while the CLI runs
+    pub(crate) capabilities: Mutex<HashMap<String, DeviceCapabilities>>,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum OpenClose {
     Open,
     Close,
+    Stop,
+    #[serde(rename = "position")]
     Closure(u8),
 }
 
+impl Display for OpenClose {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OpenClose::Open => write!(f, "open"),
+            OpenClose::Close => write!(f, "close"),
+            OpenClose::Stop => write!(f, "stop"),
+            OpenClose::Closure(p) => write!(f, "position {p}"),
+        }
+    }
+}
+
 impl From<OpenClose> for String {
     fn from(value: OpenClose) -> Self {
         match value {
             OpenClose::Open => "open".to_string(),
             OpenClose::Close => "close".to_string(),
+            OpenClose::Stop => "stop".to_string(),
             OpenClose::Closure(_) => "setClosure".to_string(),
         }
     }
@@ -31,7 +72,7 @@ impl From<OpenClose> for String {
 impl CommandExecutor {
     async fn open_close(
         &self,
-        device_url: String,
+        device_urls: Vec<String>,
         state: OpenClose,
     ) -> anyhow::Result<ExecuteActionGroupResponse> {
         let params = match state {
@@ -40,53 +81,111 @@ impl CommandExecutor {
         };
 
         let action: String = state.into();
-        let action_group_label = format!("{action} {device_url}").to_string();
+        let command = Command {
+            name: action.clone(),
+            parameters: params.clone(),
+        };
+        // Rejected before anything is sent, the definitions are fetched concurrently
+        let capabilities =
+            try_join_all(device_urls.iter().map(|url| self.get_capabilities(url))).await?;
+        for device in capabilities {
+            device.validate(&command).error_kind(ErrorKind::Usage)?;
+        }
+        let action_group_label = format!("{action} {}", device_urls.join(", ")).to_string();
 
         let request = ActionGroup {
             label: Some(action_group_label),
-            actions: vec![Action {
-                device_url,
-                commands: vec![Command {
-                    name: action,
-                    parameters: params,
-                }],
-            }],
+            actions: device_urls
+                .iter()
+                .map(|device_url| Action {
+                    device_url: device_url.clone(),
+                    commands: vec![command.clone()],
+                })
+                .collect(),
         };
 
-        self.api_client
+        let response = self
+            .api_client
             .execute_actions(&request)
             .await
-            .map_err(|e| anyhow::anyhow!("{}", e))
+            .map_err(|e| request_error(e, ErrorKind::ExecutionFailed))?;
+        if let Some(history) = &self.history {
+            if let Err(e) =
+                history.record_execution(&device_urls, &action, &params, &response.exec_id)
+            {
+                warn!("Recording the execution in the history failed: {e:#}");
+            }
+        }
+        Ok(response)
     }
 
-    pub(crate) async fn open(
+    /// Applies the same command to all given devices within a single execution
+    pub(crate) async fn apply(
         &self,
-        device_url: String,
+        device_urls: Vec<String>,
+        state: OpenClose,
     ) -> anyhow::Result<ExecuteActionGroupResponse> {
-        self.open_close(device_url, OpenClose::Open).await
+        self.open_close(device_urls, state).await
     }
 
-    pub(crate) async fn closure(
+    /// Commands supported by the device, fetched once per device
+    pub(crate) async fn get_capabilities(
         &self,
-        device_url: String,
-        percent: u8,
-    ) -> anyhow::Result<ExecuteActionGroupResponse> {
-        self.open_close(device_url, OpenClose::Closure(percent))
+        device_url: &str,
+    ) -> anyhow::Result<DeviceCapabilities> {
+        if let Some(capabilities) = self.capabilities.lock().unwrap().get(device_url) {
+            return Ok(capabilities.clone());
+        }
+        let capabilities = self
+            .api_client
+            .execute(GetDeviceCapabilitiesCommand { device_url })
             .await
+            .map_err(|e| request_error(e, ErrorKind::DeviceNotFound))?;
+        self.capabilities
+            .lock()
+            .unwrap()
+            .insert(device_url.to_string(), capabilities.clone());
+        Ok(capabilities)
     }
 
-    pub(crate) async fn close(
-        &self,
-        device_url: String,
-    ) -> anyhow::Result<ExecuteActionGroupResponse> {
-        self.open_close(device_url, OpenClose::Close).await
+    /// Every successful fetch refreshes the device cache
+    pub(crate) async fn list_devices(&self) -> anyhow::Result<GetDevicesResponse> {
+        let devices = self
+            .api_client
+            .get_devices()
+            .await
+            .map_err(|e| request_error(e, ErrorKind::General))?;
+        if let Err(e) = DeviceCache::default().store(&devices) {
+            warn!("Updating the device cache failed: {e:#}");
+        }
+        Ok(devices)
     }
 
-    pub(crate) async fn list_devices(&self) -> anyhow::Result<GetDevicesResponse> {
+    pub(crate) async fn get_device_states(
+        &self,
+        device_url: &str,
+    ) -> anyhow::Result<GetDeviceStatesResponse> {
         self.api_client
-            .get_devices()
+            .get_device_states(device_url)
             .await
-            .map_err(|e| anyhow::anyhow!("{}", e))
+            .map_err(|e| request_error(e, ErrorKind::DeviceNotFound))
+    }
+
+    /// Current `core:ClosureState` of the device in percent
+    pub(crate) async fn get_closure(&self, device_url: &str) -> anyhow::Result<u8> {
+        let closure = self
+            .get_device_states(device_url)
+            .await?
+            .find_by_name(CLOSURE_STATE)
+            .and_then(|state| match state.value {
+                DeviceStateValue::Int(i) => Some(i),
+                DeviceStateValue::String(s) => s.parse().ok(),
+                _ => None,
+            })
+            .ok_or(anyhow::anyhow!(
+                "{device_url} does not report a {CLOSURE_STATE}"
+            ))?;
+        Ok(closure.clamp(0, 100) as u8)
     }
 
     pub(crate) async fn get_current_executions(
@@ -95,34 +194,63 @@ impl CommandExecutor {
         self.api_client
             .get_current_executions()
             .await
-            .map_err(|e| anyhow::anyhow!("{}", e))
+            .map_err(|e| request_error(e, ErrorKind::General))
     }
 
-    pub(crate) async fn listen(&self) -> anyhow::Result<()> {
-        let event_listener = self.api_client.register_event_listener().await?;
-        let poller_config = PollerConfig::EVENT_LISTENER;
+    pub(crate) async fn register_event_listener(&self) -> anyhow::Result<String> {
+        let event_listener = self
+            .api_client
+            .register_event_listener()
+            .await
+            .map_err(|e| request_error(e, ErrorKind::General))?;
+        Ok(event_listener.id)
+    }
 
-        let now = SystemTime::now();
-        sleep(poller_config.refresh_interval).await;
-        while now.elapsed().map_err(|e| anyhow::anyhow!("{}", e))? < poller_config.max_wait {
-            let events = self
-                .api_client
-                .fetch_events(event_listener.id.as_str())
-                .await;
-            if let Ok(events) = events {
-                for e in events {
-                    println!("{e:?}")
-                }
+    pub(crate) async fn fetch_device_events(
+        &self,
+        listener_id: &str,
+    ) -> anyhow::Result<Vec<DeviceEvent>> {
+        let events = self
+            .api_client
+            .execute(FetchDeviceEventsCommand { listener_id })
+            .await
+            .map(|events| events.0)
+            .map_err(|e| request_error(e, ErrorKind::General))?;
+        if let Err(e) = DeviceCache::default().apply_events(&events) {
+            warn!("Updating the device cache failed: {e:#}");
+        }
+        if let Some(history) = &self.history {
+            if let Err(e) = history.record_events(&events, Utc::now()) {
+                warn!("Recording events in the history failed: {e:#}");
             }
-            sleep(poller_config.refresh_interval).await;
         }
+        Ok(events)
+    }
 
+    /// Polls the running executions until there are none left
+    pub(crate) async fn wait_until_idle(&self, poller_config: PollerConfig) -> anyhow::Result<()> {
+        let started = SystemTime::now();
+        while !self.get_current_executions().await?.is_empty() {
+            if started.elapsed()? >= poller_config.max_wait {
+                return Err(cli_error(
+                    ErrorKind::Timeout,
+                    anyhow::anyhow!(
+                        "Executions still running after {}s",
+                        poller_config.max_wait.as_secs()
+                    ),
+                ));
+            }
+            sleep(poller_config.refresh_interval).await;
+        }
         Ok(())
     }
 
     /// Execution results are available asynchronously on the API.
     /// This means that calling get_execution(execId) is not guaranteed to return the full execution result
-    /// The fn provides support for a configurable poller that polls that polls /exec/current/:execid for the result
+    /// The fn provides support for a configurable poller that polls /exec/current/:execid for the result
+    /// Executions that finished in the meantime are reported as `FINISHED` with an unknown outcome, the gateway
+    /// no longer tells whether they succeeded. The poller only times out when the gateway does not know the
+    /// execution within `max_wait`
     ///
     /// # Arguments
     ///
@@ -135,7 +263,7 @@ impl CommandExecutor {
     ///
     /// ```
     /// let device_url = "".to_string()
-    /// let exec_resp = cmd_dispatcher.close(device_url).await?;
+    /// let exec_resp = cmd_dispatcher.apply(vec![device_url], OpenClose::Close).await?;
     /// let exec_details = cmd_dispatcher.get_execution_with_full_response(exec_resp.exec_id.as_str(), ExecutionResultPollerConfig::default()).await?;
     /// ```
     pub(crate) async fn get_execution_with_full_response(
@@ -143,19 +271,49 @@ impl CommandExecutor {
         exec_id: &str,
         poller_config: PollerConfig,
     ) -> anyhow::Result<GetExecutionResponse> {
-        let mut res = self.api_client.get_execution(exec_id).await;
-        let now = SystemTime::now();
-        sleep(poller_config.refresh_interval).await;
-        while res.is_err()
-            && now.elapsed().map_err(|e| anyhow::anyhow!("{}", e))? < poller_config.max_wait
-        {
-            res = self.api_client.get_execution(exec_id).await;
+        let started = SystemTime::now();
+        loop {
+            let state = self
+                .api_client
+                .execute(GetExecutionStateCommand {
+                    execution_id: exec_id,
+                })
+                .await;
+            match state {
+                Ok(ExecutionState::Running(execution)) => return Ok(execution),
+                Ok(ExecutionState::Finished) => return Ok(finished_execution(exec_id)),
+                // Not known yet, or never, which only tells the timeout
+                Ok(ExecutionState::Unknown) => {}
+                Err(RequestError::Status { status, .. }) if status == StatusCode::NOT_FOUND => {}
+                Err(e) => return Err(request_error(e, ErrorKind::General)),
+            }
+            if started.elapsed()? >= poller_config.max_wait {
+                return Err(cli_error(
+                    ErrorKind::Timeout,
+                    anyhow::anyhow!(
+                        "No result for execution {exec_id} within {}s",
+                        poller_config.max_wait.as_secs_f64()
+                    ),
+                ));
+            }
             sleep(poller_config.refresh_interval).await;
         }
+    }
+}
 
-        self.api_client
-            .get_execution(exec_id)
-            .await
-            .map_err(|e| anyhow::anyhow!("{}", e))
+/// The gateway forgets executions once they finished, only the id is left
+fn finished_execution(exec_id: &str) -> GetExecutionResponse {
+    GetExecutionResponse {
+        owner: String::new(),
+        id: exec_id.to_string(),
+        execution_type: String::new(),
+        execution_sub_type: String::new(),
+        description: "Finished before its details were fetched, outcome unknown".to_string(),
+        start_time: 0,
+        action_group: ActionGroup {
+            label: None,
+            actions: vec![],
+        },
+        state: FINISHED_STATE.to_string(),
     }
 }
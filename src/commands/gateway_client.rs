@@ -0,0 +1,142 @@
+use crate::commands::error::{request_error, ErrorKind};
+use crate::config::certificate::PinnedVerifier;
+use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION, CONTENT_TYPE};
+use reqwest::Client;
+use somfy_sdk::api_client::{ApiClient, ApiClientConfig, HttpProtocol};
+use somfy_sdk::commands::execute_action_group::{
+    ExecuteActionGroupCommand, ExecuteActionGroupResponse,
+};
+use somfy_sdk::commands::get_current_executions::{
+    GetCurrentExecutionsCommand, GetCurrentExecutionsResponse,
+};
+use somfy_sdk::commands::get_device_states::{GetDeviceStatesCommand, GetDeviceStatesResponse};
+use somfy_sdk::commands::get_devices::{GetDevicesCommand, GetDevicesResponse};
+use somfy_sdk::commands::register_event_listener::{
+    RegisterEventListenerCommand, RegisterEventListenerResponse,
+};
+use somfy_sdk::commands::traits::{HttpMethod, SomfyApiRequestCommand, SomfyApiRequestResponse};
+use somfy_sdk::commands::types::ActionGroup;
+use somfy_sdk::err::http::RequestError;
+use std::sync::Arc;
+
+/// Sends commands to the gateway, through the SDK client unless a certificate is pinned
+pub(crate) enum GatewayClient {
+    Sdk(ApiClient),
+    Pinned(PinnedClient),
+}
+
+/// Its TLS connections only accept the certificate with the pinned fingerprint
+pub(crate) struct PinnedClient {
+    host: String,
+    port: usize,
+    http_client: Client,
+    verifier: Arc<PinnedVerifier>,
+}
+
+impl GatewayClient {
+    /// `pinned` is the `cert_fingerprint` of the profile, it only applies to HTTPS
+    pub(crate) async fn new(config: ApiClientConfig, pinned: Option<&str>) -> anyhow::Result<Self> {
+        match (pinned, &config.protocol) {
+            (Some(pinned), HttpProtocol::HTTPS) => {
+                Ok(GatewayClient::Pinned(PinnedClient::new(config, pinned)?))
+            }
+            _ => ApiClient::new(config)
+                .await
+                .map(GatewayClient::Sdk)
+                .map_err(|e| request_error(e, ErrorKind::GatewayUnreachable)),
+        }
+    }
+
+    pub(crate) async fn execute<C>(&self, command: C) -> Result<C::Response, RequestError>
+    where
+        C: SomfyApiRequestCommand,
+    {
+        match self {
+            GatewayClient::Sdk(client) => client.execute(command).await,
+            GatewayClient::Pinned(client) => client.execute(command).await,
+        }
+    }
+
+    pub(crate) async fn execute_actions(
+        &self,
+        action_group: &ActionGroup,
+    ) -> Result<ExecuteActionGroupResponse, RequestError> {
+        self.execute(ExecuteActionGroupCommand { action_group })
+            .await
+    }
+
+    pub(crate) async fn get_devices(&self) -> Result<GetDevicesResponse, RequestError> {
+        self.execute(GetDevicesCommand).await
+    }
+
+    pub(crate) async fn get_device_states(
+        &self,
+        device_url: &str,
+    ) -> Result<GetDeviceStatesResponse, RequestError> {
+        self.execute(GetDeviceStatesCommand { device_url }).await
+    }
+
+    pub(crate) async fn get_current_executions(
+        &self,
+    ) -> Result<GetCurrentExecutionsResponse, RequestError> {
+        self.execute(GetCurrentExecutionsCommand).await
+    }
+
+    pub(crate) async fn register_event_listener(
+        &self,
+    ) -> Result<RegisterEventListenerResponse, RequestError> {
+        self.execute(RegisterEventListenerCommand).await
+    }
+}
+
+impl PinnedClient {
+    fn new(config: ApiClientConfig, pinned: &str) -> anyhow::Result<Self> {
+        let verifier = Arc::new(PinnedVerifier::new(pinned)?);
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            AUTHORIZATION,
+            HeaderValue::from_str(&format!("Bearer {}", config.api_key))?,
+        );
+        let http_client = Client::builder()
+            .default_headers(headers)
+            .use_preconfigured_tls(verifier.client_config()?)
+            .build()?;
+        Ok(Self {
+            host: config.url,
+            port: config.port,
+            http_client,
+            verifier,
+        })
+    }
+
+    /// Sends the request like the SDK client does
+    async fn execute<C>(&self, command: C) -> Result<C::Response, RequestError>
+    where
+        C: SomfyApiRequestCommand,
+    {
+        let request_data = command.to_request()?;
+        let url = format!("https://{}:{}{}", self.host, self.port, request_data.path);
+        let request = match request_data.method {
+            HttpMethod::GET => self.http_client.get(&url),
+            HttpMethod::POST => self
+                .http_client
+                .post(&url)
+                .header(CONTENT_TYPE, "application/json")
+                .body(request_data.body),
+            HttpMethod::DELETE => self.http_client.delete(&url),
+        };
+        let response = request.send().await.map_err(|e| {
+            match self.verifier.mismatch(&self.host, self.port) {
+                Some(message) => RequestError::Auth {
+                    message,
+                    status: None,
+                    source: None,
+                },
+                None => e.into(),
+            }
+        })?;
+
+        let body = response.error_for_status()?.text().await?;
+        C::Response::from_body(&body)
+    }
+}
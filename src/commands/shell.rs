@@ -0,0 +1,467 @@
+use crate::commands::batch::parse_command;
+use crate::commands::cli::{Cli, Command};
+use crate::commands::dispatcher::CommandDispatcher;
+use crate::commands::error::report;
+use crate::commands::executor::CommandExecutor;
+use crate::config::alias::{AliasManager, Aliases, TAG_PREFIX};
+use crate::config::common::get_config_folder;
+use crate::output::formatter::OutputFormat;
+use anyhow::Context as _;
+use clap::CommandFactory;
+use log::warn;
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::FileHistory;
+use rustyline::validate::Validator;
+use rustyline::{Context, Editor, ExternalPrinter as _, Helper};
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::time::{Duration, Instant};
+
+const PROMPT: &str = "somfy> ";
+const HISTORY_FILENAME: &str = "shell_history";
+const BACKGROUND_SUFFIX: &str = "&";
+const EXIT_BUILTINS: [&str; 2] = ["exit", "quit"];
+const HELP_BUILTIN: &str = "help";
+const STOP_LISTEN_BUILTIN: &str = "stop-listen";
+const LABEL_REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+
+pub(crate) fn history_location() -> PathBuf {
+    get_config_folder().join(HISTORY_FILENAME)
+}
+
+type Background<'a> = Pin<Box<dyn Future<Output = anyhow::Result<()>> + 'a>>;
+
+/// Completion candidates for the shell
+#[derive(Default)]
+struct ShellHelper {
+    /// Top level commands and shell builtins
+    commands: Vec<String>,
+    /// Subcommand names per command, e.g. `alias` -> `add`, `rm`, `ls`
+    subcommands: HashMap<String, Vec<String>>,
+    /// Aliases, groups of tagged aliases, device labels and device URLs
+    devices: Vec<String>,
+}
+
+impl ShellHelper {
+    fn new() -> Self {
+        let cli = Cli::command();
+        let mut helper = ShellHelper::default();
+        for command in cli.get_subcommands() {
+            let name = command.get_name().to_string();
+            let subcommands = command
+                .get_subcommands()
+                .map(|s| s.get_name().to_string())
+                .collect();
+            helper.subcommands.insert(name.clone(), subcommands);
+            helper.commands.push(name);
+        }
+        helper.commands.extend(
+            EXIT_BUILTINS
+                .into_iter()
+                .chain([HELP_BUILTIN, STOP_LISTEN_BUILTIN])
+                .map(String::from),
+        );
+        helper.commands.sort();
+        helper
+    }
+
+    fn set_devices(&mut self, aliases: &Aliases, labels: &HashMap<String, String>) {
+        let quoted_labels = labels.keys().map(|label| match label.contains(' ') {
+            true => format!("\"{label}\""),
+            false => label.clone(),
+        });
+        let groups = aliases
+            .values()
+            .flat_map(|entry| &entry.metadata.tags)
+            .map(|tag| format!("{TAG_PREFIX}{tag}"));
+        let mut devices: Vec<String> = aliases
+            .keys()
+            .cloned()
+            .chain(groups)
+            .chain(quoted_labels)
+            .chain(labels.values().cloned())
+            .collect();
+        devices.sort();
+        devices.dedup();
+        self.devices = devices;
+    }
+
+    /// Start of the word under the cursor and its candidates. A word starting with an open quote may contain spaces.
+    fn complete_word(&self, before_cursor: &str) -> (usize, Vec<String>) {
+        let start = if before_cursor.matches('"').count() % 2 == 1 {
+            before_cursor.rfind('"').unwrap_or(0)
+        } else {
+            before_cursor
+                .rfind(char::is_whitespace)
+                .map(|i| i + 1)
+                .unwrap_or(0)
+        };
+        let prefix = &before_cursor[start..];
+        let previous: Vec<&str> = before_cursor[..start].split_whitespace().collect();
+
+        let pool = match previous.as_slice() {
+            [] => &self.commands,
+            [command]
+                if self
+                    .subcommands
+                    .get(*command)
+                    .is_some_and(|s| !s.is_empty()) =>
+            {
+                &self.subcommands[*command]
+            }
+            _ => &self.devices,
+        };
+        let candidates = pool
+            .iter()
+            .filter(|c| c.starts_with(prefix))
+            .cloned()
+            .collect();
+        (start, candidates)
+    }
+}
+
+impl Completer for ShellHelper {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        Ok(self.complete_word(&line[..pos]))
+    }
+}
+
+impl Hinter for ShellHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ShellHelper {}
+
+impl Validator for ShellHelper {}
+
+impl Helper for ShellHelper {}
+
+/// The positional device argument of the command, if it has one
+fn device_argument(command: &mut Command) -> Option<&mut String> {
+    match command {
+        Command::Open(args) => Some(&mut args.device_url),
+        Command::Close(args) => Some(&mut args.device_url),
+        Command::Position(args) => Some(&mut args.device_url),
+        Command::State(args) => Some(&mut args.device_url),
+        Command::Commands(args) => Some(&mut args.device_url),
+        Command::History(args) => args.device_url.as_mut(),
+        _ => None,
+    }
+}
+
+/// Replaces a device label given as device argument with its URL, aliases take precedence
+fn resolve_label(command: &mut Command, aliases: &Aliases, labels: &HashMap<String, String>) {
+    if let Some(device) = device_argument(command) {
+        if let Some(url) = labels.get(device.as_str()) {
+            if !aliases.contains_key(device.as_str()) {
+                *device = url.clone();
+            }
+        }
+    }
+}
+
+/// Device URLs by their label, refreshed while the shell runs so that renamed devices are picked up
+struct Labels {
+    labels: HashMap<String, String>,
+    fetched: Instant,
+}
+
+impl Labels {
+    async fn fetch(executor: &CommandExecutor) -> anyhow::Result<Self> {
+        let labels = executor
+            .list_devices()
+            .await?
+            .into_iter()
+            .map(|device| (device.label, device.device_url))
+            .collect();
+        Ok(Self {
+            labels,
+            fetched: Instant::now(),
+        })
+    }
+
+    /// Keeps the known labels if the gateway cannot be reached
+    async fn refresh(&mut self, executor: &CommandExecutor) {
+        if self.fetched.elapsed() < LABEL_REFRESH_INTERVAL {
+            return;
+        }
+        match Self::fetch(executor).await {
+            Ok(labels) => *self = labels,
+            Err(e) => {
+                warn!("Failed to refresh the device labels: {e:#}");
+                self.fetched = Instant::now();
+            }
+        }
+    }
+}
+
+/// Prints above the prompt while the editor reads a line, plainly if the terminal does not support that
+fn printer(editor: &mut Editor<ShellHelper, FileHistory>) -> impl FnMut(String) {
+    let mut external = editor.create_external_printer().ok();
+    move |message: String| {
+        let printed = external
+            .as_mut()
+            .is_some_and(|printer| printer.print(format!("{message}\n")).is_ok());
+        if !printed {
+            println!("{message}");
+        }
+    }
+}
+
+/// Drives the background listener while waiting for `future`
+async fn alongside<T>(
+    background: &mut Option<Background<'_>>,
+    future: impl Future<Output = T>,
+    notify: &mut impl FnMut(String),
+) -> T {
+    tokio::pin!(future);
+    loop {
+        let Some(task) = background.as_mut() else {
+            return future.await;
+        };
+        let ended = tokio::select! {
+            result = &mut future => return result,
+            ended = task => ended,
+        };
+        *background = None;
+        notify(match ended {
+            Ok(()) => "Background listener stopped".to_string(),
+            Err(e) => format!("Background listener failed: {e:#}"),
+        });
+    }
+}
+
+/// The editor blocks while reading, so it is moved to a blocking thread and handed back afterwards
+async fn read_line(
+    mut editor: Editor<ShellHelper, FileHistory>,
+) -> anyhow::Result<(
+    Editor<ShellHelper, FileHistory>,
+    Result<String, ReadlineError>,
+)> {
+    tokio::task::spawn_blocking(move || {
+        let line = editor.readline(PROMPT);
+        (editor, line)
+    })
+    .await
+    .context("Reading input failed")
+}
+
+fn print_help() {
+    println!("Enter commands without the leading 'somfy', e.g. 'open kitchen' or 'ls'. Tab completes commands, aliases, groups and device labels.");
+    println!(
+        "  listen [OPTIONS] &   listen for events in the background while running other commands"
+    );
+    println!("  {STOP_LISTEN_BUILTIN}          stop the background listener");
+    println!("  help                 this help, '<command> --help' shows the options of a command");
+    println!("  exit, quit           leave the shell (or Ctrl+D)");
+}
+
+/// Interactive prompt running commands over the connection of `dispatcher` until the user exits
+pub(crate) async fn run(
+    dispatcher: &CommandDispatcher,
+    format: &OutputFormat,
+) -> anyhow::Result<()> {
+    let alias_manager = AliasManager::default();
+    let executor = dispatcher.executor()?;
+    let mut labels = Labels::fetch(executor).await?;
+
+    let mut editor: Editor<ShellHelper, FileHistory> = Editor::new()?;
+    editor.set_helper(Some(ShellHelper::new()));
+    let history = history_location();
+    let _ = editor.load_history(&history);
+    let mut notify = printer(&mut editor);
+    let mut background: Option<Background<'_>> = None;
+
+    loop {
+        labels.refresh(executor).await;
+        let aliases = alias_manager.load_aliases().unwrap_or_default();
+        if let Some(helper) = editor.helper_mut() {
+            helper.set_devices(&aliases, &labels.labels);
+        }
+
+        let (returned, line) = alongside(&mut background, read_line(editor), &mut notify).await?;
+        editor = returned;
+        let line = match line {
+            Ok(line) => line.trim().to_string(),
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(e) => return Err(e.into()),
+        };
+        if line.is_empty() {
+            continue;
+        }
+        let _ = editor.add_history_entry(line.as_str());
+
+        match line.as_str() {
+            l if EXIT_BUILTINS.contains(&l) => break,
+            HELP_BUILTIN => {
+                print_help();
+                continue;
+            }
+            STOP_LISTEN_BUILTIN => {
+                match background.take() {
+                    Some(_) => println!("Background listener stopped"),
+                    None => println!("No background listener running"),
+                }
+                continue;
+            }
+            _ => {}
+        }
+
+        let (line, in_background) = match line.strip_suffix(BACKGROUND_SUFFIX) {
+            Some(rest) => (rest.trim(), true),
+            None => (line.as_str(), false),
+        };
+        let Some(words) = shlex::split(line) else {
+            eprintln!("Error: Unbalanced quotes");
+            continue;
+        };
+        let command = match parse_command(words) {
+            Ok(mut command) => {
+                resolve_label(&mut command, &aliases, &labels.labels);
+                command
+            }
+            Err(e) => {
+                eprintln!("{e:#}");
+                continue;
+            }
+        };
+
+        match command {
+            Command::Shell | Command::Tui | Command::MockGateway(_) => {
+                eprintln!("Error: This command cannot be used inside the shell");
+            }
+            Command::Listen(args) if in_background => {
+                if background.is_some() {
+                    eprintln!("Error: A background listener is already running, stop it with '{STOP_LISTEN_BUILTIN}'");
+                    continue;
+                }
+                let mut print = printer(&mut editor);
+                background = Some(Box::pin(dispatcher.listen(args, format, move |line| {
+                    print(line);
+                    Ok(())
+                })));
+                println!("Listening in the background, stop with '{STOP_LISTEN_BUILTIN}'");
+            }
+            _ if in_background => {
+                eprintln!("Error: Only 'listen' can run in the background");
+            }
+            command => {
+                let interruptible = async {
+                    tokio::select! {
+                        result = Box::pin(dispatcher.dispatch(command, format)) => result,
+                        _ = tokio::signal::ctrl_c() => Err(anyhow::anyhow!("Interrupted")),
+                    }
+                };
+                if let Err(e) = alongside(&mut background, interruptible, &mut notify).await {
+                    report(&e, format);
+                }
+            }
+        }
+    }
+
+    editor
+        .save_history(&history)
+        .context("Failed to save shell history")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::cli::PositionChange;
+    use crate::config::alias::{AliasEntry, AliasMetadata};
+
+    fn helper() -> ShellHelper {
+        let mut helper = ShellHelper::new();
+        let labels = HashMap::from([
+            ("Living Room".to_string(), "io://1/1".to_string()),
+            ("Kitchen".to_string(), "io://1/2".to_string()),
+        ]);
+        let aliases = Aliases::from([(
+            "kitchen".to_string(),
+            AliasEntry {
+                device_url: "io://1/2".to_string(),
+                metadata: AliasMetadata {
+                    tags: vec!["south".to_string()],
+                    ..AliasMetadata::default()
+                },
+            },
+        )]);
+        helper.set_devices(&aliases, &labels);
+        helper
+    }
+
+    #[test]
+    fn test_completes_commands_and_builtins() {
+        let (start, candidates) = helper().complete_word("cl");
+        assert_eq!((start, candidates), (0, vec!["close".to_string()]));
+
+        let (_, candidates) = helper().complete_word("ex");
+        assert_eq!(candidates, vec!["exit".to_string()]);
+    }
+
+    #[test]
+    fn test_completes_subcommands() {
+        let (start, candidates) = helper().complete_word("alias a");
+        assert_eq!((start, candidates), (6, vec!["add".to_string()]));
+    }
+
+    #[test]
+    fn test_completes_aliases_labels_and_quoted_labels() {
+        let (start, candidates) = helper().complete_word("open k");
+        assert_eq!((start, candidates), (5, vec!["kitchen".to_string()]));
+
+        let (start, candidates) = helper().complete_word("close \"Living R");
+        assert_eq!(
+            (start, candidates),
+            (6, vec!["\"Living Room\"".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_completes_groups() {
+        let (start, candidates) = helper().complete_word("close tag:s");
+        assert_eq!((start, candidates), (6, vec!["tag:south".to_string()]));
+    }
+
+    #[test]
+    fn test_only_the_device_argument_is_resolved() {
+        let labels = HashMap::from([
+            ("Living Room".to_string(), "io://1/1".to_string()),
+            ("kitchen".to_string(), "io://1/2".to_string()),
+            ("50".to_string(), "io://1/3".to_string()),
+        ]);
+        let aliases = Aliases::from([(
+            "kitchen".to_string(),
+            AliasEntry::from("io://1/4".to_string()),
+        )]);
+        let resolved = |line: &str| {
+            let mut command = parse_command(shlex::split(line).unwrap()).unwrap();
+            resolve_label(&mut command, &aliases, &labels);
+            command
+        };
+
+        let Command::Position(args) = resolved("position \"Living Room\" 50") else {
+            panic!("Expected position command");
+        };
+        assert_eq!(args.device_url, "io://1/1");
+        assert_eq!(args.percentage, Some(PositionChange::Absolute(50)));
+        let Command::Open(args) = resolved("open kitchen") else {
+            panic!("Expected open command");
+        };
+        assert_eq!(args.device_url, "kitchen");
+    }
+}
@@ -1,21 +1,171 @@
-use crate::config::common::get_config_folder;
+use crate::commands::error::{cli_error, ErrorKind, WithErrorKind};
+use crate::config::common::{backup_hint, get_config_folder, lock_exclusive, write_atomically};
+use crate::config::toml_file::{is_toml, read_document, toml_file_location, toml_value};
 use anyhow::Context;
-use std::collections::HashMap;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fs;
-use std::path::PathBuf;
+use std::io::Write;
+use std::path::{Path, PathBuf};
 
 const CONFIG_LOCATION_FILENAME: &str = "alias.json";
+/// Device arguments like `tag:south` stand for all aliases with this tag
+pub(crate) const TAG_PREFIX: &str = "tag:";
 
+/// Optional details of an alias
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub(crate) struct AliasMetadata {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub room: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// Closure in percent used by `position` without a percentage
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_position: Option<u8>,
+}
+
+impl AliasMetadata {
+    pub(crate) fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Catches hand-edited or imported positions the gateway would be asked to move to
+    fn check_default_position(&self) -> Result<(), String> {
+        match self.default_position {
+            Some(position) if position > 100 => Err(format!(
+                "invalid default position {position}, expected 0-100"
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Device URL of an alias and its metadata. Aliases without metadata are stored as plain device URL,
+/// the format of earlier versions.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "StoredAlias", into = "StoredAlias")]
+pub(crate) struct AliasEntry {
+    pub device_url: String,
+    pub metadata: AliasMetadata,
+}
+
+impl AliasEntry {
+    pub(crate) fn has_tag(&self, tag: &str) -> bool {
+        self.metadata.tags.iter().any(|t| t == tag)
+    }
+}
+
+impl From<String> for AliasEntry {
+    fn from(device_url: String) -> Self {
+        Self {
+            device_url,
+            metadata: AliasMetadata::default(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum StoredAlias {
+    Url(String),
+    Entry {
+        device_url: String,
+        #[serde(flatten)]
+        metadata: AliasMetadata,
+    },
+}
+
+impl TryFrom<StoredAlias> for AliasEntry {
+    type Error = String;
+
+    fn try_from(stored: StoredAlias) -> Result<Self, Self::Error> {
+        match stored {
+            StoredAlias::Url(device_url) => Ok(device_url.into()),
+            StoredAlias::Entry {
+                device_url,
+                metadata,
+            } => {
+                metadata
+                    .check_default_position()
+                    .map_err(|e| format!("{device_url} has {e}"))?;
+                Ok(Self {
+                    device_url,
+                    metadata,
+                })
+            }
+        }
+    }
+}
+
+impl From<AliasEntry> for StoredAlias {
+    fn from(entry: AliasEntry) -> Self {
+        if entry.metadata.is_empty() {
+            return StoredAlias::Url(entry.device_url);
+        }
+        StoredAlias::Entry {
+            device_url: entry.device_url,
+            metadata: entry.metadata,
+        }
+    }
+}
+
+/// Alias names and their entries, sorted by name
+pub(crate) type Aliases = BTreeMap<String, AliasEntry>;
+
+/// The `[aliases]` table of config.toml
+#[derive(Deserialize)]
+struct AliasSection {
+    #[serde(default)]
+    aliases: Aliases,
+}
+
+pub(crate) fn json_alias_location() -> PathBuf {
+    let mut location = get_config_folder();
+    location.push(CONFIG_LOCATION_FILENAME);
+    location
+}
+
+/// Aliases are stored in alias.json, or in config.toml once it exists
 pub struct AliasManager {
     location: PathBuf,
 }
 
 impl Default for AliasManager {
     fn default() -> Self {
-        let mut location = get_config_folder();
-        location.push(CONFIG_LOCATION_FILENAME);
+        let toml = toml_file_location();
+        if toml.exists() {
+            return AliasManager::new(toml);
+        }
+        AliasManager::new(json_alias_location())
+    }
+}
 
-        AliasManager::new(location)
+/// Runs at startup: an alias.json left next to config.toml would be ignored, so its aliases are
+/// moved into config.toml
+pub(crate) fn migrate_json_aliases() {
+    let toml = toml_file_location();
+    let json = json_alias_location();
+    if !toml.exists() || !json.exists() {
+        return;
+    }
+    let manager = AliasManager::new(toml);
+    match manager.absorb_json_aliases(&json) {
+        Ok((backup, kept)) if kept.is_empty() => info!(
+            "Moved the aliases of {json:?} into {:?}, the old file is kept as {backup:?}",
+            manager.location
+        ),
+        Ok((backup, kept)) => warn!(
+            "Moved the aliases of {json:?} into {:?}, except {} already defined there. The old file is kept as {backup:?}",
+            manager.location,
+            kept.join(", ")
+        ),
+        Err(e) => warn!(
+            "{json:?} is ignored because {:?} exists, moving its aliases failed: {e:#}",
+            manager.location
+        ),
     }
 }
 
@@ -26,6 +176,32 @@ impl AliasManager {
         }
     }
 
+    /// Moves the aliases of an alias.json left next to config.toml into it, entries of config.toml win.
+    /// alias.json is kept as alias.json.bak like `somfy config convert` does. Returns the backup and
+    /// the aliases whose differing entry in alias.json was dropped.
+    fn absorb_json_aliases(&self, json: &Path) -> anyhow::Result<(PathBuf, Vec<String>)> {
+        let _lock = lock_exclusive(&self.location)?;
+        let mut aliases = self.load_aliases()?;
+        let mut kept = vec![];
+        for (alias, entry) in AliasManager::new(json.to_path_buf()).load_aliases()? {
+            match aliases.get(&alias) {
+                None => {
+                    aliases.insert(alias, entry);
+                }
+                Some(current) if *current != entry => kept.push(alias),
+                Some(_) => {}
+            }
+        }
+        self.write_alias_file(&aliases)?;
+        let backup = json.with_extension("json.bak");
+        fs::rename(json, &backup).context(format!("Failed to rename {json:?} to {backup:?}"))?;
+        Ok((backup, kept))
+    }
+
+    pub(crate) fn location(&self) -> &Path {
+        &self.location
+    }
+
     fn ensure_file(&self) -> anyhow::Result<()> {
         if let Some(parent) = self.location.parent() {
             fs::create_dir_all(parent).context(format!(
@@ -34,73 +210,412 @@ impl AliasManager {
             ))?;
         }
 
-        // Only create file if it doesn't exist
-        if !self.location.exists() {
-            let empty_file = serde_json::to_string(&HashMap::<String, String>::default())
-                .context("Failed to create empty JSON dict")?;
-            fs::write(&self.location, empty_file).context(format!("Could not create empty alias file at location {:?}. Make sure the location is writeable", &self.location))?;
+        // Only create file if it doesn't exist, never truncate a file written concurrently
+        let empty_file =
+            serde_json::to_string(&Aliases::new()).context("Failed to create empty JSON dict")?;
+        match fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&self.location)
+        {
+            Ok(mut file) => file.write_all(empty_file.as_bytes()).context(format!("Could not create empty alias file at location {:?}. Make sure the location is writeable", &self.location)),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => Ok(()),
+            Err(e) => Err(e).context(format!("Could not create empty alias file at location {:?}. Make sure the location is writeable", &self.location)),
         }
-        Ok(())
     }
 
-    pub(crate) fn load_aliases(&self) -> anyhow::Result<HashMap<String, String>> {
+    fn load_toml_aliases(&self) -> anyhow::Result<Aliases> {
+        let contents = match fs::read_to_string(&self.location) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Aliases::new()),
+            Err(e) => return Err(e).context(format!("Failed to read {:?}", self.location)),
+        };
+        let section: AliasSection = toml::from_str(&contents).context(format!(
+            "Failed to parse aliases. Check that [aliases] in {:?} only contains device URLs or tables with a device_url{}",
+            self.location,
+            backup_hint(&self.location)
+        ))?;
+        Ok(section.aliases)
+    }
+
+    /// Only changed entries are touched, comments on the others are kept
+    fn write_toml_aliases(&self, aliases: &Aliases) -> anyhow::Result<()> {
+        let current = self.load_toml_aliases()?;
+        let mut document = read_document(&self.location)?.unwrap_or_default();
+        let table = document
+            .entry("aliases")
+            .or_insert(toml_edit::table())
+            .as_table_mut()
+            .context(format!("aliases in {:?} has to be a table", self.location))?;
+        table.retain(|alias, _| aliases.contains_key(alias));
+        for (alias, entry) in aliases {
+            if current.get(alias) == Some(entry) {
+                continue;
+            }
+            let value = toml_value(&serde_json::to_value(entry)?)
+                .context(format!("Alias '{alias}' cannot be stored in TOML"))?;
+            table[alias.as_str()] = toml_edit::value(value);
+        }
+        write_atomically(&self.location, document.to_string().as_bytes())
+    }
+
+    pub(crate) fn load_aliases(&self) -> anyhow::Result<Aliases> {
+        if is_toml(&self.location) {
+            return self.load_toml_aliases();
+        }
         self.ensure_file()?;
-        let file_contents = fs::read(&self.location).context(
-            "Failed to read alias file. Check that ~/.config/somfy-cli/alias.json exists",
-        )?;
-        let aliases: HashMap<String, String> = serde_json::from_slice(file_contents.as_slice())
-            .context(
-                "Failed to parse alias file into JSON. Check that ~/.config/somfy-cli/alias.json is valid",
-            )?;
+        let file_contents = fs::read(&self.location).context(format!(
+            "Failed to read alias file. Check that {:?} exists",
+            self.location
+        ))?;
+        let aliases: Aliases =
+            serde_json::from_slice(file_contents.as_slice()).context(format!(
+                "Failed to parse alias file into JSON. Check that {:?} is valid{}",
+                self.location,
+                backup_hint(&self.location)
+            ))?;
 
         Ok(aliases)
     }
 
     pub(crate) fn get_alias(&self, alias: &str) -> Option<String> {
-        self.load_aliases().ok()?.get(alias).cloned()
+        self.get_entry(alias).map(|entry| entry.device_url)
+    }
+
+    pub(crate) fn get_entry(&self, alias: &str) -> Option<AliasEntry> {
+        self.load_aliases().ok()?.remove(alias)
     }
 
     pub(crate) fn add_alias(
         &self,
         alias: String,
-        device_url: String,
+        entry: impl Into<AliasEntry>,
         overwrite: bool,
-    ) -> anyhow::Result<HashMap<String, String>> {
+    ) -> anyhow::Result<Aliases> {
+        let _lock = lock_exclusive(&self.location)?;
         let mut aliases = self.load_aliases()?;
 
         if !aliases.contains_key(&alias) || overwrite {
-            aliases.insert(alias, device_url);
+            aliases.insert(alias, entry.into());
         }
 
-        let _ = self.write_alias_file(&aliases);
+        self.write_alias_file(&aliases)?;
 
         Ok(aliases)
     }
 
-    fn write_alias_file(&self, aliases: &HashMap<String, String>) -> anyhow::Result<()> {
-        self.ensure_file()?;
+    fn write_alias_file(&self, aliases: &Aliases) -> anyhow::Result<()> {
+        if is_toml(&self.location) {
+            return self.write_toml_aliases(aliases);
+        }
         let json_str = serde_json::to_string(&aliases)?;
-        fs::write(&self.location, json_str)?;
-
-        Ok(())
+        write_atomically(&self.location, json_str.as_bytes())
     }
 
-    pub(crate) fn delete_alias(&self, alias: String) -> anyhow::Result<HashMap<String, String>> {
+    pub(crate) fn delete_alias(&self, alias: String) -> anyhow::Result<Aliases> {
+        let _lock = lock_exclusive(&self.location)?;
         let mut aliases = self.load_aliases()?;
         aliases.remove(&alias);
-        let _ = self.write_alias_file(&aliases);
+        self.write_alias_file(&aliases)?;
+
+        Ok(aliases)
+    }
+
+    pub(crate) fn rename_alias(
+        &self,
+        alias: &str,
+        new_name: String,
+        overwrite: bool,
+    ) -> anyhow::Result<Aliases> {
+        let _lock = lock_exclusive(&self.location)?;
+        let mut aliases = self.load_aliases()?;
+        if aliases.contains_key(&new_name) && !overwrite {
+            anyhow::bail!("Alias '{new_name}' already exists, pass --overwrite to replace it");
+        }
+        let entry = aliases
+            .remove(alias)
+            .ok_or(anyhow::anyhow!("Alias '{alias}' does not exist"))?;
+        aliases.insert(new_name, entry);
+        self.write_alias_file(&aliases)?;
 
         Ok(aliases)
     }
 
+    /// Applies `imported` and returns what changed, nothing is written with `dry_run`
+    pub(crate) fn import_aliases(
+        &self,
+        imported: Aliases,
+        mode: ImportMode,
+        overwrite: bool,
+        dry_run: bool,
+    ) -> anyhow::Result<AliasChanges> {
+        let _lock = lock_exclusive(&self.location)?;
+        let current = self.load_aliases()?;
+        let (aliases, changes) = plan_import(&current, imported, mode, overwrite)?;
+        if !dry_run && aliases != current {
+            self.write_alias_file(&aliases)?;
+        }
+
+        Ok(changes)
+    }
+
     pub(crate) fn resolve_alias(&self, alias: &str) -> String {
         self.get_alias(alias).unwrap_or(alias.to_string())
     }
+
+    /// Device URLs of all aliases tagged with `tag`, sorted and without duplicates
+    pub(crate) fn tagged_devices(&self, tag: &str) -> anyhow::Result<Vec<String>> {
+        let mut device_urls: Vec<String> = self
+            .load_aliases()?
+            .into_values()
+            .filter(|entry| entry.has_tag(tag))
+            .map(|entry| entry.device_url)
+            .collect();
+        device_urls.sort();
+        device_urls.dedup();
+        Ok(device_urls)
+    }
+
+    /// Like `resolve_alias`, `tag:<tag>` arguments resolve to all tagged devices. Fails if no alias
+    /// has the tag or the aliases cannot be loaded.
+    pub(crate) fn resolve_aliases(&self, device: &str) -> anyhow::Result<Vec<String>> {
+        let Some(tag) = device.strip_prefix(TAG_PREFIX) else {
+            let aliases = self.load_aliases().error_kind(ErrorKind::Config)?;
+            return Ok(vec![aliases
+                .get(device)
+                .map_or(device.to_string(), |entry| entry.device_url.clone())]);
+        };
+        let device_urls = self.tagged_devices(tag).error_kind(ErrorKind::Config)?;
+        if device_urls.is_empty() {
+            return Err(cli_error(
+                ErrorKind::DeviceNotFound,
+                anyhow::anyhow!("No alias is tagged with '{tag}'"),
+            ));
+        }
+        Ok(device_urls)
+    }
+
+    /// Default position of the alias, or the one shared by all aliases with the tag of a `tag:<tag>` argument
+    pub(crate) fn default_position(&self, device: &str) -> anyhow::Result<u8> {
+        let aliases = self.load_aliases().error_kind(ErrorKind::Config)?;
+        let Some(tag) = device.strip_prefix(TAG_PREFIX) else {
+            return aliases
+                .get(device)
+                .and_then(|entry| entry.metadata.default_position)
+                .ok_or(cli_error(
+                    ErrorKind::Usage,
+                    anyhow::anyhow!("'{device}' has no default position, pass a percentage"),
+                ));
+        };
+        let mut positions = BTreeMap::new();
+        for (alias, entry) in aliases.iter().filter(|(_, entry)| entry.has_tag(tag)) {
+            let position = entry.metadata.default_position.ok_or(cli_error(
+                ErrorKind::Usage,
+                anyhow::anyhow!(
+                    "Alias '{alias}' tagged with '{tag}' has no default position, pass a percentage"
+                ),
+            ))?;
+            positions.insert(position, alias.as_str());
+        }
+        match positions.len() {
+            0 => Err(cli_error(
+                ErrorKind::DeviceNotFound,
+                anyhow::anyhow!("No alias is tagged with '{tag}'"),
+            )),
+            1 => Ok(*positions.keys().next().unwrap()),
+            _ => Err(cli_error(
+                ErrorKind::Usage,
+                anyhow::anyhow!(
+                    "The aliases tagged with '{tag}' have different default positions ({}), pass a percentage",
+                    positions
+                        .iter()
+                        .map(|(position, alias)| format!("{alias}: {position}"))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub(crate) enum AliasFileFormat {
+    Json,
+    Yaml,
+    Csv,
+}
+
+impl AliasFileFormat {
+    /// The format given explicitly, otherwise the one of the file extension
+    pub(crate) fn of(path: &Path, format: Option<Self>) -> anyhow::Result<Self> {
+        if let Some(format) = format {
+            return Ok(format);
+        }
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(str::to_ascii_lowercase);
+        match extension.as_deref() {
+            Some("json") => Ok(Self::Json),
+            Some("yaml" | "yml") => Ok(Self::Yaml),
+            Some("csv") => Ok(Self::Csv),
+            _ => anyhow::bail!(
+                "Cannot tell the format of {path:?} from its extension, pass --file-format"
+            ),
+        }
+    }
+}
+
+/// One row of an exported CSV file, tags are separated by commas
+#[derive(Deserialize)]
+struct AliasRecord {
+    alias: String,
+    device_url: String,
+    #[serde(default)]
+    description: Option<String>,
+    #[serde(default)]
+    room: Option<String>,
+    #[serde(default)]
+    tags: Option<String>,
+    #[serde(default)]
+    default_position: Option<u8>,
+}
+
+impl From<AliasRecord> for AliasEntry {
+    fn from(record: AliasRecord) -> Self {
+        let tags = record
+            .tags
+            .iter()
+            .flat_map(|tags| tags.split(','))
+            .map(str::trim)
+            .filter(|tag| !tag.is_empty())
+            .map(str::to_string)
+            .collect();
+        Self {
+            device_url: record.device_url,
+            metadata: AliasMetadata {
+                description: record.description,
+                room: record.room,
+                tags,
+                default_position: record.default_position,
+            },
+        }
+    }
+}
+
+/// Reads aliases in the format written by `alias export`
+pub(crate) fn parse_aliases(contents: &str, format: AliasFileFormat) -> anyhow::Result<Aliases> {
+    Ok(match format {
+        AliasFileFormat::Json => serde_json::from_str(contents)?,
+        AliasFileFormat::Yaml => serde_yaml_ng::from_str(contents)?,
+        AliasFileFormat::Csv => {
+            let mut aliases = Aliases::new();
+            for record in csv::Reader::from_reader(contents.as_bytes()).deserialize() {
+                let record: AliasRecord = record?;
+                if aliases.contains_key(&record.alias) {
+                    anyhow::bail!("Alias '{}' is defined more than once", record.alias);
+                }
+                let alias = record.alias.clone();
+                let entry: AliasEntry = record.into();
+                entry
+                    .metadata
+                    .check_default_position()
+                    .map_err(|e| anyhow::anyhow!("Alias '{alias}' has {e}"))?;
+                aliases.insert(alias, entry);
+            }
+            aliases
+        }
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub(crate) enum ImportMode {
+    /// Keep aliases missing in the file
+    Merge,
+    /// Remove aliases missing in the file
+    Replace,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum AliasChangeKind {
+    Added,
+    Updated,
+    Unchanged,
+    /// Exists with another device URL or metadata and was kept
+    Conflict,
+    Removed,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct AliasChange {
+    pub alias: String,
+    pub change: AliasChangeKind,
+    pub device_url: Option<String>,
+    pub previous: Option<String>,
+}
+
+pub(crate) type AliasChanges = Vec<AliasChange>;
+
+/// The aliases after the import and the change of every alias involved, sorted by name.
+/// Existing aliases are only replaced with `overwrite` or in replace mode.
+fn plan_import(
+    current: &Aliases,
+    imported: Aliases,
+    mode: ImportMode,
+    overwrite: bool,
+) -> anyhow::Result<(Aliases, AliasChanges)> {
+    for (alias, entry) in &imported {
+        entry
+            .metadata
+            .check_default_position()
+            .map_err(|e| anyhow::anyhow!("Alias '{alias}' has {e}"))?;
+    }
+    let mut aliases = match mode {
+        ImportMode::Merge => current.clone(),
+        ImportMode::Replace => Aliases::new(),
+    };
+    let mut changes = vec![];
+    if mode == ImportMode::Replace {
+        changes.extend(
+            current
+                .iter()
+                .filter(|(alias, _)| !imported.contains_key(*alias))
+                .map(|(alias, entry)| AliasChange {
+                    alias: alias.clone(),
+                    change: AliasChangeKind::Removed,
+                    device_url: None,
+                    previous: Some(entry.device_url.clone()),
+                }),
+        );
+    }
+    for (alias, entry) in imported {
+        let previous = current.get(&alias);
+        let change = match previous {
+            None => AliasChangeKind::Added,
+            Some(existing) if *existing == entry => AliasChangeKind::Unchanged,
+            Some(_) if overwrite || mode == ImportMode::Replace => AliasChangeKind::Updated,
+            Some(_) => AliasChangeKind::Conflict,
+        };
+        changes.push(AliasChange {
+            alias: alias.clone(),
+            change,
+            device_url: Some(entry.device_url.clone()),
+            previous: previous.map(|p| p.device_url.clone()),
+        });
+        if change != AliasChangeKind::Conflict {
+            aliases.insert(alias, entry);
+        }
+    }
+    changes.sort_by(|a, b| a.alias.cmp(&b.alias));
+
+    Ok((aliases, changes))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::commands::error::error_kind_of;
     use std::fs;
     use tempfile::TempDir;
 
@@ -159,11 +674,11 @@ mod tests {
         let result = manager
             .add_alias("test".to_string(), "http://test.com".to_string(), false)
             .unwrap();
-        assert_eq!(result.get("test"), Some(&"http://test.com".to_string()));
+        assert_eq!(result["test"].device_url, "http://test.com");
 
         // Verify persistence
         let aliases = manager.load_aliases().unwrap();
-        assert_eq!(aliases.get("test"), Some(&"http://test.com".to_string()));
+        assert_eq!(aliases["test"].device_url, "http://test.com");
     }
 
     #[test]
@@ -177,7 +692,7 @@ mod tests {
             .add_alias("test".to_string(), "http://new.com".to_string(), false)
             .unwrap();
 
-        assert_eq!(result.get("test"), Some(&"http://original.com".to_string()));
+        assert_eq!(result["test"].device_url, "http://original.com");
     }
 
     #[test]
@@ -191,7 +706,7 @@ mod tests {
             .add_alias("test".to_string(), "http://new.com".to_string(), true)
             .unwrap();
 
-        assert_eq!(result.get("test"), Some(&"http://new.com".to_string()));
+        assert_eq!(result["test"].device_url, "http://new.com");
     }
 
     #[test]
@@ -268,13 +783,327 @@ mod tests {
 
         let aliases = manager.load_aliases().unwrap();
         assert_eq!(aliases.len(), 2);
+        assert_eq!(aliases["alias1"].device_url, "http://device1.com");
+        assert_eq!(aliases["alias2"].device_url, "http://device2.com");
+    }
+
+    #[test]
+    fn test_aliases_in_config_toml() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_file = temp_dir.path().join("config.toml");
+        fs::write(
+            &config_file,
+            "hostname = \"home.local\"\n\n[aliases]\n# Blinds in the living room\nliving = \"io://1/1\"\nkitchen = \"io://1/2\"\n",
+        )
+        .unwrap();
+        let manager = AliasManager::new(config_file.clone());
+
+        manager
+            .add_alias("office".to_string(), "io://1/3".to_string(), false)
+            .unwrap();
+        manager.delete_alias("kitchen".to_string()).unwrap();
+
+        let aliases = manager.load_aliases().unwrap();
+        assert_eq!(aliases.len(), 2);
+        assert_eq!(aliases["office"].device_url, "io://1/3");
+        let content = fs::read_to_string(&config_file).unwrap();
+        assert!(content.contains("hostname = \"home.local\""));
+        assert!(content.contains("# Blinds in the living room\nliving"));
+    }
+
+    #[test]
+    fn test_json_aliases_are_moved_into_config_toml() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_file = temp_dir.path().join("config.toml");
+        let json_file = temp_dir.path().join("alias.json");
+        fs::write(&config_file, "[aliases]\nliving = \"io://1/1\"\n").unwrap();
+        fs::write(
+            &json_file,
+            r#"{"living": "io://1/9", "kitchen": "io://1/2"}"#,
+        )
+        .unwrap();
+        let manager = AliasManager::new(config_file);
+
+        let (backup, kept) = manager.absorb_json_aliases(&json_file).unwrap();
+
+        assert_eq!(kept, vec!["living"]);
+        let aliases = manager.load_aliases().unwrap();
+        assert_eq!(aliases["living"].device_url, "io://1/1");
+        assert_eq!(aliases["kitchen"].device_url, "io://1/2");
+        assert!(!json_file.exists());
+        assert_eq!(backup, temp_dir.path().join("alias.json.bak"));
+    }
+
+    #[test]
+    fn test_write_errors_are_reported() {
+        let (manager, _temp_dir) = create_test_alias_manager();
+        manager
+            .add_alias("test".to_string(), "io://1/1".to_string(), false)
+            .unwrap();
+        // The temporary file cannot be created if a directory is in its place
+        let temp = format!("{}.{}.tmp", manager.location.display(), std::process::id());
+        fs::create_dir(temp).unwrap();
+
+        assert!(manager
+            .add_alias("other".to_string(), "io://1/2".to_string(), false)
+            .is_err());
+        assert_eq!(manager.load_aliases().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_concurrent_writes_keep_all_aliases() {
+        let (manager, _temp_dir) = create_test_alias_manager();
+        let location = manager.location.clone();
+
+        let handles: Vec<_> = (0..8)
+            .map(|n| {
+                let location = location.clone();
+                std::thread::spawn(move || {
+                    AliasManager::new(location)
+                        .add_alias(format!("alias{n}"), format!("io://1/{n}"), false)
+                        .unwrap();
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        assert_eq!(manager.load_aliases().unwrap().len(), 8);
+    }
+
+    fn aliases(entries: &[(&str, &str)]) -> Aliases {
+        entries
+            .iter()
+            .map(|(alias, url)| (alias.to_string(), url.to_string().into()))
+            .collect()
+    }
+
+    #[test]
+    fn test_plan_import_reports_conflicts() {
+        let current = aliases(&[("living", "io://1/1"), ("kitchen", "io://1/2")]);
+        let imported = aliases(&[("living", "io://1/9"), ("office", "io://1/3")]);
+
+        let (merged, changes) =
+            plan_import(&current, imported.clone(), ImportMode::Merge, false).unwrap();
+        let kinds: Vec<_> = changes
+            .iter()
+            .map(|c| (c.alias.as_str(), c.change))
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                ("living", AliasChangeKind::Conflict),
+                ("office", AliasChangeKind::Added)
+            ]
+        );
+        assert_eq!(merged["living"].device_url, "io://1/1");
+        assert_eq!(merged.len(), 3);
+
+        let (replaced, changes) =
+            plan_import(&current, imported, ImportMode::Replace, false).unwrap();
+        assert_eq!(changes[0].alias, "kitchen");
+        assert_eq!(changes[0].change, AliasChangeKind::Removed);
+        assert_eq!(changes[1].change, AliasChangeKind::Updated);
+        assert_eq!(
+            replaced,
+            aliases(&[("living", "io://1/9"), ("office", "io://1/3")])
+        );
+    }
+
+    #[test]
+    fn test_out_of_range_default_positions_are_rejected() {
+        let err = serde_json::from_str::<Aliases>(
+            r#"{"living": {"device_url": "io://1/1", "default_position": 250}}"#,
+        )
+        .unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("io://1/1 has invalid default position 250, expected 0-100"));
+
+        let csv =
+            "alias,device_url,description,room,tags,default_position\nliving,io://1/1,,,,101\n";
+        let err = parse_aliases(csv, AliasFileFormat::Csv).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Alias 'living' has invalid default position 101, expected 0-100"
+        );
+
+        let mut imported = aliases(&[("living", "io://1/1")]);
+        imported
+            .get_mut("living")
+            .unwrap()
+            .metadata
+            .default_position = Some(250);
+        let err = plan_import(&Aliases::new(), imported, ImportMode::Merge, false).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Alias 'living' has invalid default position 250, expected 0-100"
+        );
+    }
+
+    #[test]
+    fn test_hand_edited_default_position_is_rejected() {
+        let (manager, _temp_dir) = create_test_alias_manager();
+        fs::write(
+            manager.location(),
+            r#"{"living": {"device_url": "io://1/1", "default_position": 250}}"#,
+        )
+        .unwrap();
+
+        assert!(manager.default_position("living").is_err());
+        assert_eq!(manager.get_alias("living"), None);
+    }
+
+    #[test]
+    fn test_parse_exported_formats() {
+        let expected = aliases(&[("living", "io://1/1"), ("kitchen", "io://1/2")]);
+
+        let json = parse_aliases(
+            r#"{"living": "io://1/1", "kitchen": "io://1/2"}"#,
+            AliasFileFormat::Json,
+        );
+        let yaml = parse_aliases(
+            "kitchen: io://1/2\nliving: io://1/1\n",
+            AliasFileFormat::Yaml,
+        );
+        let csv = parse_aliases(
+            "alias,device_url\nkitchen,io://1/2\nliving,io://1/1\n",
+            AliasFileFormat::Csv,
+        );
+        let duplicate = parse_aliases(
+            "alias,device_url\nliving,io://1/1\nliving,io://1/2\n",
+            AliasFileFormat::Csv,
+        );
+
+        assert_eq!(json.unwrap(), expected);
+        assert_eq!(yaml.unwrap(), expected);
+        assert_eq!(csv.unwrap(), expected);
+        assert!(duplicate.is_err());
         assert_eq!(
-            aliases.get("alias1"),
-            Some(&"http://device1.com".to_string())
+            AliasFileFormat::of(Path::new("aliases.yml"), None).unwrap(),
+            AliasFileFormat::Yaml
         );
+    }
+
+    #[test]
+    fn test_rename_alias() {
+        let (manager, _temp_dir) = create_test_alias_manager();
+        manager
+            .add_alias("living".to_string(), "io://1/1".to_string(), false)
+            .unwrap();
+        manager
+            .add_alias("kitchen".to_string(), "io://1/2".to_string(), false)
+            .unwrap();
+
+        assert!(manager
+            .rename_alias("living", "kitchen".to_string(), false)
+            .is_err());
+        assert!(manager
+            .rename_alias("office", "den".to_string(), false)
+            .is_err());
+        let renamed = manager
+            .rename_alias("living", "lounge".to_string(), false)
+            .unwrap();
+
         assert_eq!(
-            aliases.get("alias2"),
-            Some(&"http://device2.com".to_string())
+            renamed,
+            aliases(&[("kitchen", "io://1/2"), ("lounge", "io://1/1")])
         );
     }
+
+    #[test]
+    fn test_flat_and_structured_entries() {
+        let (manager, _temp_dir) = create_test_alias_manager();
+        fs::write(
+            &manager.location,
+            r#"{"living": "io://1/1", "terrace": {"device_url": "io://1/2", "room": "Garden", "tags": ["south"]}}"#,
+        )
+        .unwrap();
+        let metadata = AliasMetadata {
+            tags: vec!["south".to_string()],
+            default_position: Some(40),
+            ..Default::default()
+        };
+        manager
+            .add_alias(
+                "office".to_string(),
+                AliasEntry {
+                    device_url: "io://1/3".to_string(),
+                    metadata: metadata.clone(),
+                },
+                false,
+            )
+            .unwrap();
+
+        let aliases = manager.load_aliases().unwrap();
+        assert!(aliases["living"].metadata.is_empty());
+        assert_eq!(aliases["terrace"].metadata.room.as_deref(), Some("Garden"));
+        assert_eq!(aliases["office"].metadata, metadata);
+        let stored: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&manager.location).unwrap()).unwrap();
+        assert_eq!(stored["living"], "io://1/1");
+        assert_eq!(stored["office"]["default_position"], 40);
+        assert_eq!(
+            manager.resolve_aliases("tag:south").unwrap(),
+            vec!["io://1/2".to_string(), "io://1/3".to_string()]
+        );
+        let unknown_tag = manager.resolve_aliases("tag:north").unwrap_err();
+        assert_eq!(error_kind_of(&unknown_tag), ErrorKind::DeviceNotFound);
+        assert_eq!(manager.resolve_aliases("living").unwrap(), vec!["io://1/1"]);
+    }
+
+    #[test]
+    fn test_default_position_of_aliases_and_tags() {
+        let (manager, _temp_dir) = create_test_alias_manager();
+        fs::write(
+            &manager.location,
+            r#"{"living": {"device_url": "io://1/1", "default_position": 30, "tags": ["south", "front"]},
+                "office": {"device_url": "io://1/2", "default_position": 30, "tags": ["south"]},
+                "terrace": {"device_url": "io://1/3", "default_position": 60, "tags": ["front"]},
+                "kitchen": {"device_url": "io://1/4", "tags": ["back"]}}"#,
+        )
+        .unwrap();
+
+        assert_eq!(manager.default_position("terrace").unwrap(), 60);
+        assert_eq!(manager.default_position("tag:south").unwrap(), 30);
+        let differing = manager.default_position("tag:front").unwrap_err();
+        assert_eq!(error_kind_of(&differing), ErrorKind::Usage);
+        assert!(differing.to_string().contains("living: 30, terrace: 60"));
+        let missing = manager.default_position("tag:back").unwrap_err();
+        assert_eq!(
+            missing.to_string(),
+            "Alias 'kitchen' tagged with 'back' has no default position, pass a percentage"
+        );
+        assert_eq!(error_kind_of(&missing), ErrorKind::Usage);
+        let unknown = manager.default_position("tag:north").unwrap_err();
+        assert_eq!(error_kind_of(&unknown), ErrorKind::DeviceNotFound);
+    }
+
+    #[test]
+    fn test_metadata_in_config_toml() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_file = temp_dir.path().join("config.toml");
+        fs::write(&config_file, "[aliases]\nliving = \"io://1/1\"\n").unwrap();
+        let manager = AliasManager::new(config_file.clone());
+
+        let entry = AliasEntry {
+            device_url: "io://1/2".to_string(),
+            metadata: AliasMetadata {
+                description: Some("Left of the door".to_string()),
+                tags: vec!["south".to_string()],
+                ..Default::default()
+            },
+        };
+        manager
+            .add_alias("kitchen".to_string(), entry.clone(), false)
+            .unwrap();
+
+        let aliases = manager.load_aliases().unwrap();
+        assert_eq!(aliases["kitchen"], entry);
+        assert_eq!(aliases["living"].device_url, "io://1/1");
+        let content = fs::read_to_string(&config_file).unwrap();
+        assert!(content.contains("living = \"io://1/1\""));
+        assert!(content.contains("tags = [\"south\"]"));
+    }
 }
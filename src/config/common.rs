@@ -1,11 +1,448 @@
-use std::path::PathBuf;
-const CONFIG_FOLDER: &str = ".somfy_cli";
+use anyhow::Context;
+use log::info;
+use serde::Serialize;
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+const CONFIG_FOLDER: &str = "somfy-cli";
+/// Config folder of earlier versions, its files are moved on the first start
+const LEGACY_CONFIG_FOLDER: &str = ".somfy_cli";
+pub(crate) const CONFIG_DIR_VAR: &str = "SOMFY_CONFIG_DIR";
+/// Config files whose path settings are rewritten when they are moved out of the legacy folder
+const MIGRATED_CONFIG_FILES: [&str; 2] = ["env.json", "config.toml"];
+/// Settings holding a path, absolute ones into the legacy folder have to follow the files
+const PATH_SETTINGS: [&str; 2] = ["cert_path", "api_key_file"];
+/// Previous versions kept by `write_atomically`, as `<file>.1` (newest) to `<file>.3`
+const BACKUP_COUNT: usize = 3;
+
+/// Set once at startup from `--config-dir`
+static CONFIG_DIR_OVERRIDE: OnceLock<PathBuf> = OnceLock::new();
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ConfigDirSource {
+    Flag,
+    Environment,
+    Xdg,
+}
+
+pub(crate) fn set_config_folder(path: PathBuf) {
+    let _ = CONFIG_DIR_OVERRIDE.set(path);
+}
+
+fn home_dir() -> PathBuf {
+    dirs::home_dir().unwrap_or(PathBuf::from("."))
+}
+
+fn non_empty_var(name: &str) -> Option<PathBuf> {
+    std::env::var_os(name)
+        .filter(|value| !value.is_empty())
+        .map(PathBuf::from)
+}
+
+/// `$XDG_CONFIG_HOME/somfy-cli`, falling back to `~/.config` or `%APPDATA%` on Windows
+fn xdg_config_folder() -> PathBuf {
+    let base = non_empty_var("XDG_CONFIG_HOME")
+        .or_else(|| {
+            if cfg!(windows) {
+                dirs::config_dir()
+            } else {
+                None
+            }
+        })
+        .unwrap_or_else(|| home_dir().join(".config"));
+    base.join(CONFIG_FOLDER)
+}
+
+/// The config folder and where it was configured, `--config-dir` wins over `SOMFY_CONFIG_DIR`
+pub(crate) fn resolve_config_folder() -> (PathBuf, ConfigDirSource) {
+    if let Some(folder) = CONFIG_DIR_OVERRIDE.get() {
+        return (folder.clone(), ConfigDirSource::Flag);
+    }
+    if let Some(folder) = non_empty_var(CONFIG_DIR_VAR) {
+        return (folder, ConfigDirSource::Environment);
+    }
+    (xdg_config_folder(), ConfigDirSource::Xdg)
+}
 
 pub(crate) fn get_config_folder() -> PathBuf {
-    let mut path = PathBuf::new();
-    let home = dirs::home_dir().unwrap_or(PathBuf::from("."));
-    path.push(home);
-    path.push(CONFIG_FOLDER);
+    resolve_config_folder().0
+}
+
+pub(crate) fn legacy_config_folder() -> PathBuf {
+    home_dir().join(LEGACY_CONFIG_FOLDER)
+}
+
+fn move_entry(from: &Path, to: &Path) -> std::io::Result<()> {
+    // Renaming fails across file systems, files are copied including their permissions then
+    fs::rename(from, to).or_else(|e| {
+        if !from.is_file() {
+            return Err(e);
+        }
+        fs::copy(from, to)?;
+        fs::remove_file(from)
+    })
+}
+
+/// Moves everything from `legacy` into `target`, entries already present in `target` are left alone.
+/// Returns the names of the moved entries.
+fn migrate_folder(legacy: &Path, target: &Path) -> anyhow::Result<Vec<String>> {
+    if !legacy.is_dir() {
+        return Ok(vec![]);
+    }
+    fs::create_dir_all(target).context(format!("Failed to create {target:?}"))?;
+
+    let mut moved = vec![];
+    for entry in fs::read_dir(legacy)? {
+        let entry = entry?;
+        let destination = target.join(entry.file_name());
+        if destination.exists() {
+            continue;
+        }
+        move_entry(&entry.path(), &destination).context(format!(
+            "Failed to move {:?} to {destination:?}",
+            entry.path()
+        ))?;
+        moved.push(entry.file_name().to_string_lossy().into_owned());
+    }
+    for name in MIGRATED_CONFIG_FILES {
+        if moved.iter().any(|m| m == name) {
+            relocate_path_settings(&target.join(name), legacy, target)?;
+        }
+    }
+    // Only succeeds once the legacy folder is empty
+    let _ = fs::remove_dir(legacy);
+    moved.sort();
+    Ok(moved)
+}
+
+/// `path` within `target` if it points into `legacy`
+fn relocated(path: &str, legacy: &Path, target: &Path) -> Option<String> {
+    let relative = Path::new(path).strip_prefix(legacy).ok()?;
+    Some(target.join(relative).to_string_lossy().into_owned())
+}
+
+fn relocate_json(value: &mut serde_json::Value, legacy: &Path, target: &Path) -> bool {
+    let serde_json::Value::Object(map) = value else {
+        return false;
+    };
+    let mut changed = false;
+    for (key, value) in map.iter_mut() {
+        match value {
+            serde_json::Value::String(path) if PATH_SETTINGS.contains(&key.as_str()) => {
+                if let Some(path) = relocated(path, legacy, target) {
+                    *value = path.into();
+                    changed = true;
+                }
+            }
+            value => changed |= relocate_json(value, legacy, target),
+        }
+    }
+    changed
+}
+
+fn relocate_toml(table: &mut dyn toml_edit::TableLike, legacy: &Path, target: &Path) -> bool {
+    let mut changed = false;
+    for (key, item) in table.iter_mut() {
+        if let Some(nested) = item.as_table_like_mut() {
+            changed |= relocate_toml(nested, legacy, target);
+            continue;
+        }
+        let Some(value) = item.as_value_mut() else {
+            continue;
+        };
+        let Some(path) = value
+            .as_str()
+            .filter(|_| PATH_SETTINGS.contains(&key.get()))
+            .and_then(|path| relocated(path, legacy, target))
+        else {
+            continue;
+        };
+        // Keeps comments next to the value
+        let decor = value.decor().clone();
+        *value = path.into();
+        *value.decor_mut() = decor;
+        changed = true;
+    }
+    changed
+}
+
+/// Points `cert_path` and `api_key_file` of all profiles in a moved config file into `target`
+fn relocate_path_settings(path: &Path, legacy: &Path, target: &Path) -> anyhow::Result<()> {
+    let contents = fs::read_to_string(path).context(format!("Failed to read {path:?}"))?;
+    let relocated = if path.extension().is_some_and(|ext| ext == "toml") {
+        let mut document: toml_edit::DocumentMut = contents
+            .parse()
+            .context(format!("Failed to parse {path:?} as TOML"))?;
+        relocate_toml(document.as_table_mut(), legacy, target).then(|| document.to_string())
+    } else {
+        let mut value: serde_json::Value =
+            serde_json::from_str(&contents).context(format!("Failed to parse {path:?}"))?;
+        relocate_json(&mut value, legacy, target)
+            .then(|| serde_json::to_string_pretty(&value))
+            .transpose()?
+    };
+    match relocated {
+        Some(contents) => write_atomically(path, contents.as_bytes()),
+        None => Ok(()),
+    }
+}
+
+/// Moves env.json, alias.json and the other files of `~/.somfy_cli` to the XDG config folder,
+/// unless the config folder was chosen explicitly
+pub(crate) fn migrate_legacy_config_folder() -> anyhow::Result<()> {
+    let (folder, source) = resolve_config_folder();
+    if source != ConfigDirSource::Xdg {
+        return Ok(());
+    }
+    let legacy = legacy_config_folder();
+    let moved = migrate_folder(&legacy, &folder)?;
+    if !moved.is_empty() {
+        info!("Moved {} from {legacy:?} to {folder:?}", moved.join(", "));
+    }
+    Ok(())
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+/// The most recent backup created by `write_atomically`
+pub(crate) fn backup_location(path: &Path) -> PathBuf {
+    with_suffix(path, ".1")
+}
+
+/// Points to the most recent backup in parse errors, empty if there is none
+pub(crate) fn backup_hint(path: &Path) -> String {
+    let backup = backup_location(path);
+    if backup.exists() {
+        format!(", the previous version is kept in {backup:?}")
+    } else {
+        String::new()
+    }
+}
+
+/// Exclusive advisory lock on `<path>.lock`, held until the returned file is dropped.
+/// A separate file is locked because `write_atomically` replaces `path` itself.
+pub(crate) fn lock_exclusive(path: &Path) -> anyhow::Result<File> {
+    let lock_path = with_suffix(path, ".lock");
+    if let Some(parent) = lock_path.parent() {
+        fs::create_dir_all(parent).context(format!("Failed to create {parent:?}"))?;
+    }
+    let file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&lock_path)
+        .context(format!("Failed to open the lock file {lock_path:?}"))?;
+    file.lock()
+        .context(format!("Failed to lock {lock_path:?}"))?;
+    Ok(file)
+}
+
+fn rotate_backups(path: &Path) -> std::io::Result<()> {
+    if !path.exists() {
+        return Ok(());
+    }
+    for n in (1..BACKUP_COUNT).rev() {
+        let older = with_suffix(path, &format!(".{n}"));
+        if older.exists() {
+            fs::rename(&older, with_suffix(path, &format!(".{}", n + 1)))?;
+        }
+    }
+    fs::copy(path, backup_location(path))?;
+    Ok(())
+}
+
+/// Writes a temporary file next to `path` and renames it, so that readers never see a partially
+/// written file. The previous version is kept as backup, permissions of an existing file are kept,
+/// new files are only readable by the user.
+pub(crate) fn write_atomically(path: &Path, contents: &[u8]) -> anyhow::Result<()> {
+    write_through_temp(path, contents, true)
+}
+
+/// Like `write_atomically`, but without backups, for files that are rewritten often and can be
+/// recreated from the gateway
+pub(crate) fn replace_atomically(path: &Path, contents: &[u8]) -> anyhow::Result<()> {
+    write_through_temp(path, contents, false)
+}
+
+fn write_through_temp(path: &Path, contents: &[u8], keep_backup: bool) -> anyhow::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).context(format!("Failed to create {parent:?}"))?;
+    }
+    let temp = with_suffix(path, &format!(".{}.tmp", std::process::id()));
+    let result = (|| {
+        let mut options = OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut file = options.open(&temp)?;
+        file.write_all(contents)?;
+        if let Ok(metadata) = fs::metadata(path) {
+            file.set_permissions(metadata.permissions())?;
+        }
+        file.sync_all()?;
+        if keep_backup {
+            rotate_backups(path)?;
+        }
+        fs::rename(&temp, path)
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result.context(format!("Failed to write {path:?}"))
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct ConfigFileStatus {
+    pub name: String,
+    pub path: PathBuf,
+    pub exists: bool,
+}
+
+/// Output of `somfy config path`
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct ConfigPaths {
+    pub config_dir: PathBuf,
+    pub source: ConfigDirSource,
+    pub files: Vec<ConfigFileStatus>,
+}
+
+impl ConfigPaths {
+    /// The config folder and the state of the given files
+    pub(crate) fn of(files: Vec<(&str, PathBuf)>) -> Self {
+        let (config_dir, source) = resolve_config_folder();
+        let files = files
+            .into_iter()
+            .map(|(name, path)| ConfigFileStatus {
+                name: name.to_string(),
+                exists: path.exists(),
+                path,
+            })
+            .collect();
+        Self {
+            config_dir,
+            source,
+            files,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_migrate_folder_keeps_existing_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let legacy = temp_dir.path().join(".somfy_cli");
+        let target = temp_dir.path().join(".config").join("somfy-cli");
+        fs::create_dir_all(&legacy).unwrap();
+        fs::create_dir_all(&target).unwrap();
+        fs::write(legacy.join("env.json"), "legacy").unwrap();
+        fs::write(legacy.join("alias.json"), "{}").unwrap();
+        fs::write(target.join("env.json"), "current").unwrap();
+
+        let moved = migrate_folder(&legacy, &target).unwrap();
+
+        assert_eq!(moved, vec!["alias.json"]);
+        assert_eq!(
+            fs::read_to_string(target.join("env.json")).unwrap(),
+            "current"
+        );
+        assert!(target.join("alias.json").exists());
+        assert!(legacy.join("env.json").exists());
+    }
+
+    #[test]
+    fn test_migrate_folder_removes_empty_legacy_folder() {
+        let temp_dir = TempDir::new().unwrap();
+        let legacy = temp_dir.path().join(".somfy_cli");
+        let target = temp_dir.path().join("somfy-cli");
+        fs::create_dir_all(&legacy).unwrap();
+        fs::write(legacy.join("env.json"), "{}").unwrap();
+
+        assert_eq!(migrate_folder(&legacy, &target).unwrap(), vec!["env.json"]);
+        assert!(!legacy.exists());
+        assert!(migrate_folder(&legacy, &target).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_migrate_folder_relocates_paths_into_the_legacy_folder() {
+        let temp_dir = TempDir::new().unwrap();
+        let legacy = temp_dir.path().join(".somfy_cli");
+        let target = temp_dir.path().join("somfy-cli");
+        fs::create_dir_all(&legacy).unwrap();
+        let legacy_cert = legacy.join("gateway-ca.crt").to_string_lossy().into_owned();
+        let env = serde_json::json!({
+            "hostname": "gateway-1234.local",
+            "cert_path": legacy_cert,
+            "profiles": {
+                "holiday": {"cert_path": legacy_cert, "api_key_file": "/etc/somfy/key"}
+            }
+        });
+        fs::write(legacy.join("env.json"), env.to_string()).unwrap();
+        fs::write(
+            legacy.join("config.toml"),
+            format!("[profiles.home]\ncert_path = '{legacy_cert}' # renewed yearly\n"),
+        )
+        .unwrap();
+
+        migrate_folder(&legacy, &target).unwrap();
+
+        let target_cert = target.join("gateway-ca.crt").to_string_lossy().into_owned();
+        let env: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(target.join("env.json")).unwrap()).unwrap();
+        assert_eq!(env["cert_path"], target_cert.as_str());
+        assert_eq!(
+            env["profiles"]["holiday"]["cert_path"],
+            target_cert.as_str()
+        );
+        assert_eq!(env["profiles"]["holiday"]["api_key_file"], "/etc/somfy/key");
+        let toml = fs::read_to_string(target.join("config.toml")).unwrap();
+        assert!(toml.contains(&format!("cert_path = \"{target_cert}\" # renewed yearly")));
+    }
+
+    #[test]
+    fn test_write_atomically_keeps_backups() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("alias.json");
+
+        for version in 1..=5 {
+            write_atomically(&path, format!("{version}").as_bytes()).unwrap();
+        }
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "5");
+        assert_eq!(fs::read_to_string(backup_location(&path)).unwrap(), "4");
+        assert_eq!(fs::read_to_string(with_suffix(&path, ".3")).unwrap(), "2");
+        assert!(!with_suffix(&path, ".4").exists());
+        assert_eq!(fs::read_dir(temp_dir.path()).unwrap().count(), 4);
+    }
+
+    #[test]
+    fn test_backup_hint_only_names_existing_backups() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("env.json");
 
-    path
+        write_atomically(&path, b"{}").unwrap();
+        assert_eq!(backup_hint(&path), "");
+        write_atomically(&path, b"{}").unwrap();
+        assert_eq!(
+            backup_hint(&path),
+            format!(
+                ", the previous version is kept in {:?}",
+                backup_location(&path)
+            )
+        );
+    }
 }
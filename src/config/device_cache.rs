@@ -0,0 +1,271 @@
+use crate::config::common::{get_config_folder, lock_exclusive, replace_atomically};
+use crate::config::dotenv::selected_profile;
+use crate::events::fetch_device_events::{DeviceEvent, DEVICE_STATE_CHANGED_EVENT};
+use crate::events::watch::apply_states;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use somfy_sdk::commands::get_devices::GetDevicesResponse;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+const DEVICE_CACHE_FILENAME: &str = "devices.json";
+
+/// The last device list received from the gateway
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct CachedDevices {
+    /// When the list was fetched, state changes from events do not update it
+    pub updated: DateTime<Utc>,
+    pub devices: GetDevicesResponse,
+}
+
+impl CachedDevices {
+    pub(crate) fn is_fresh(&self, max_age: Duration, now: DateTime<Utc>) -> bool {
+        (now - self.updated)
+            .to_std()
+            .is_ok_and(|age| age <= max_age)
+    }
+
+    /// Device URL of the device with this label. Exact matches win over case-insensitive ones.
+    pub(crate) fn device_url_of(&self, label: &str) -> Option<String> {
+        let exact = self.devices.iter().find(|d| d.label == label);
+        exact
+            .or_else(|| {
+                let mut matching = self
+                    .devices
+                    .iter()
+                    .filter(|d| d.label.eq_ignore_ascii_case(label));
+                match (matching.next(), matching.next()) {
+                    (Some(device), None) => Some(device),
+                    _ => None,
+                }
+            })
+            .map(|d| d.device_url.clone())
+    }
+
+    /// Applies the states of `DeviceStateChangedEvent`s. Returns true if any state changed.
+    fn apply_events(&mut self, events: &[DeviceEvent]) -> bool {
+        let mut changed = false;
+        for event in events
+            .iter()
+            .filter(|e| e.name == DEVICE_STATE_CHANGED_EVENT)
+        {
+            let device = self
+                .devices
+                .iter_mut()
+                .find(|d| Some(&d.device_url) == event.device_url.as_ref());
+            if let Some(device) = device {
+                let before = device.states.clone();
+                apply_states(&mut device.states, &event.device_states);
+                changed |= device.states != before;
+            }
+        }
+        changed
+    }
+}
+
+/// Set once at startup, the profile whose gateway the cached devices belong to
+static CACHE_PROFILE: OnceLock<Option<String>> = OnceLock::new();
+
+pub(crate) fn set_cache_profile(profile: Option<String>) {
+    let _ = CACHE_PROFILE.set(profile);
+}
+
+/// `devices.json` for the default profile, `devices.<profile>.json` for the others
+fn cache_filename(profile: Option<&str>) -> String {
+    match profile {
+        None => DEVICE_CACHE_FILENAME.to_string(),
+        Some(profile) => {
+            let profile: String = profile
+                .chars()
+                .map(
+                    |c| match c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                        true => c,
+                        false => '_',
+                    },
+                )
+                .collect();
+            format!("devices.{profile}.json")
+        }
+    }
+}
+
+pub struct DeviceCache {
+    location: PathBuf,
+}
+
+/// Devices of different gateways never mix, every profile has a cache of its own
+impl Default for DeviceCache {
+    fn default() -> Self {
+        let profile = CACHE_PROFILE.get_or_init(|| selected_profile(None));
+        DeviceCache::new(get_config_folder().join(cache_filename(profile.as_deref())))
+    }
+}
+
+impl DeviceCache {
+    fn new(cache_file: PathBuf) -> Self {
+        Self {
+            location: cache_file,
+        }
+    }
+
+    pub(crate) fn location(&self) -> &Path {
+        &self.location
+    }
+
+    /// The cached devices, None if nothing has been cached yet
+    pub(crate) fn load(&self) -> anyhow::Result<Option<CachedDevices>> {
+        if !self.location.exists() {
+            return Ok(None);
+        }
+        let file_contents = fs::read(&self.location)
+            .context(format!("Failed to read device cache {:?}", self.location))?;
+        let cached: CachedDevices = serde_json::from_slice(&file_contents).context(format!(
+            "Failed to parse device cache {:?}, run 'somfy ls' to refresh it",
+            self.location
+        ))?;
+        Ok(Some(cached))
+    }
+
+    fn write(&self, cached: &CachedDevices) -> anyhow::Result<()> {
+        replace_atomically(&self.location, serde_json::to_string(cached)?.as_bytes())
+            .context("Failed to write the device cache")
+    }
+
+    /// Stores the devices with the current time as fetch time
+    pub(crate) fn store(&self, devices: &GetDevicesResponse) -> anyhow::Result<()> {
+        let _lock = lock_exclusive(&self.location)?;
+        self.write(&CachedDevices {
+            updated: Utc::now(),
+            devices: devices.clone(),
+        })
+    }
+
+    /// Keeps the cached states up to date with the events received from the gateway
+    pub(crate) fn apply_events(&self, events: &[DeviceEvent]) -> anyhow::Result<()> {
+        let _lock = lock_exclusive(&self.location)?;
+        let Some(mut cached) = self.load()? else {
+            return Ok(());
+        };
+        if cached.apply_events(events) {
+            self.write(&cached)?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn device_url_of(&self, label: &str) -> Option<String> {
+        self.load().ok()??.device_url_of(label)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::common::backup_location;
+    use crate::output::formatter::Searchable;
+    use serde_json::json;
+    use tempfile::TempDir;
+
+    fn devices() -> GetDevicesResponse {
+        serde_json::from_value(json!([
+            {"deviceURL": "io://1/1", "label": "Living Room", "controllableName": "io:Shutter",
+             "subsystemId": 0, "type": 1, "available": true, "synced": true, "enabled": true,
+             "states": [{"name": "core:ClosureState", "type": 1, "value": 0}], "attributes": []},
+            {"deviceURL": "io://1/2", "label": "Kitchen", "controllableName": "io:Shutter",
+             "subsystemId": 0, "type": 1, "available": true, "synced": true, "enabled": true,
+             "states": [], "attributes": []}
+        ]))
+        .unwrap()
+    }
+
+    fn create_test_cache() -> (DeviceCache, TempDir) {
+        let temp_dir = TempDir::new().unwrap();
+        let cache = DeviceCache::new(temp_dir.path().join("devices.json"));
+        (cache, temp_dir)
+    }
+
+    #[test]
+    fn test_load_without_cache() {
+        let (cache, _temp_dir) = create_test_cache();
+        assert!(cache.load().unwrap().is_none());
+        assert_eq!(cache.device_url_of("Kitchen"), None);
+    }
+
+    #[test]
+    fn test_store_and_resolve_labels() {
+        let (cache, _temp_dir) = create_test_cache();
+        cache.store(&devices()).unwrap();
+
+        let cached = cache.load().unwrap().unwrap();
+        assert_eq!(cached.devices, devices());
+        assert_eq!(cache.device_url_of("Living Room"), Some("io://1/1".into()));
+        assert_eq!(cache.device_url_of("kitchen"), Some("io://1/2".into()));
+        assert_eq!(cache.device_url_of("Office"), None);
+    }
+
+    #[test]
+    fn test_storing_updates_the_fetch_time_without_backups() {
+        let (cache, _temp_dir) = create_test_cache();
+        cache
+            .write(&CachedDevices {
+                updated: "2024-01-01T12:00:00Z".parse().unwrap(),
+                devices: devices(),
+            })
+            .unwrap();
+
+        cache.store(&devices()).unwrap();
+        let cached = cache.load().unwrap().unwrap();
+        assert!(cached.is_fresh(Duration::from_secs(60), Utc::now()));
+
+        let mut renamed = devices();
+        renamed[1].label = "Kitchen Blind".to_string();
+        cache.store(&renamed).unwrap();
+        assert_eq!(cache.load().unwrap().unwrap().devices, renamed);
+        assert!(!backup_location(cache.location()).exists());
+    }
+
+    #[test]
+    fn test_profiles_have_separate_caches() {
+        assert_eq!(cache_filename(None), "devices.json");
+        assert_eq!(
+            cache_filename(Some("holiday-home")),
+            "devices.holiday-home.json"
+        );
+        assert_eq!(cache_filename(Some("../other")), "devices.___other.json");
+    }
+
+    #[test]
+    fn test_freshness() {
+        let cached = CachedDevices {
+            updated: "2024-01-01T12:00:00Z".parse().unwrap(),
+            devices: vec![],
+        };
+        let now = "2024-01-01T12:05:00Z".parse().unwrap();
+
+        assert!(cached.is_fresh(Duration::from_secs(600), now));
+        assert!(!cached.is_fresh(Duration::from_secs(60), now));
+    }
+
+    #[test]
+    fn test_state_changes_update_cached_devices() {
+        let (cache, _temp_dir) = create_test_cache();
+        cache.store(&devices()).unwrap();
+        let event: DeviceEvent = serde_json::from_value(json!({
+            "name": "DeviceStateChangedEvent", "deviceURL": "io://1/1",
+            "deviceStates": [{"name": "core:ClosureState", "type": 1, "value": "80"}]
+        }))
+        .unwrap();
+
+        cache.apply_events(&[event]).unwrap();
+
+        let cached = cache.load().unwrap().unwrap();
+        assert_eq!(
+            cached.devices[0]
+                .states
+                .value_from_name("core:ClosureState"),
+            " 80"
+        );
+    }
+}
@@ -1,40 +1,445 @@
-use crate::config::common::get_config_folder;
+use crate::config::common::{backup_hint, get_config_folder, lock_exclusive, write_atomically};
+use crate::config::secrets::ensure_private;
+use crate::config::toml_file::{is_toml, read_document, toml_file_location, toml_value};
 use anyhow::Context;
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
 
 const CONFIG_FILENAME: &str = "env.json";
+/// Selects the profile like `--profile`, also read when the command line is not parsed, e.g. for completions
+const PROFILE_VAR: &str = "SOMFY_PROFILE";
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, clap::ValueEnum)]
 pub enum HttpProtocol {
     Http,
     Https,
 }
 
+fn default_retention_days() -> u32 {
+    90
+}
+
+/// Enables the local history database when present in the config file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct HistorySettings {
+    /// Entries older than this are removed, 0 keeps them forever
+    #[serde(default = "default_retention_days")]
+    pub retention_days: u32,
+}
+
+/// Polling of execution results, missing values keep the defaults. Zero is rejected, it would
+/// give up at once or poll the gateway without pause.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct PollerSettings {
+    /// How long to wait for an execution to finish
+    pub max_wait_ms: Option<u64>,
+    pub refresh_interval_ms: Option<u64>,
+}
+
+impl PollerSettings {
+    /// `profile` names the profile in the error
+    fn validate(&self, profile: &str) -> anyhow::Result<()> {
+        for (name, value) in [
+            ("max_wait_ms", self.max_wait_ms),
+            ("refresh_interval_ms", self.refresh_interval_ms),
+        ] {
+            if value == Some(0) {
+                anyhow::bail!("poller.{name} of {profile} has to be greater than 0");
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub(crate) struct CliApiClientConfig {
     pub protocol: Option<HttpProtocol>,
     pub hostname: Option<String>,
     pub port: Option<usize>,
     pub api_key: Option<String>,
+    /// File containing only the API key
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_key_file: Option<String>,
+    /// Command printing the API key, e.g. `pass show somfy`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_key_cmd: Option<String>,
+    /// Account of the API key in the system keyring, see `somfy keyring set`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_key_keyring: Option<String>,
     pub cert_path: Option<String>,
+    /// SHA-256 fingerprint the certificate of the gateway must have, see `somfy cert pin`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cert_fingerprint: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub history: Option<HistorySettings>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub poller: Option<PollerSettings>,
+    /// Gateway PIN like `1234-5678-9012`, needed to manage tokens with `somfy token`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gateway_id: Option<String>,
+    /// Somfy account used to log into the cloud for `somfy token`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cloud_user: Option<String>,
+    /// Cloud API endpoint, defaults to the Somfy server for Europe
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cloud_url: Option<String>,
+}
+
+/// The top level settings of env.json or config.toml form the default profile. Named profiles are complete
+/// configurations of their own and do not inherit from it.
+#[derive(Debug, Clone, Default, Deserialize)]
+struct ConfigFile {
+    #[serde(flatten)]
+    default: CliApiClientConfig,
+    #[serde(default)]
+    profiles: BTreeMap<String, CliApiClientConfig>,
+    /// Profile used when `--profile` is not given
+    #[serde(default)]
+    active_profile: Option<String>,
+}
+
+impl ConfigFile {
+    /// The explicitly selected profile, otherwise the active one
+    fn profile_name(&self, profile: Option<&str>) -> Option<String> {
+        profile.map(str::to_string).or(self.active_profile.clone())
+    }
+
+    /// Checks what serde cannot
+    fn validate(&self) -> anyhow::Result<()> {
+        let profiles = self
+            .profiles
+            .iter()
+            .map(|(name, config)| (format!("profile '{name}'"), config));
+        for (profile, config) in
+            std::iter::once(("the default profile".to_string(), &self.default)).chain(profiles)
+        {
+            if let Some(poller) = &config.poller {
+                poller.validate(&profile)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn into_profile(mut self, profile: Option<&str>) -> anyhow::Result<CliApiClientConfig> {
+        match self.profile_name(profile) {
+            None => Ok(self.default),
+            Some(name) => self.profiles.remove(&name).ok_or(anyhow::anyhow!(
+                "Profile '{name}' not found in the config file"
+            )),
+        }
+    }
 }
 
-fn get_file_location() -> PathBuf {
+pub(crate) fn json_file_location() -> PathBuf {
     let mut path = get_config_folder();
     path.push(CONFIG_FILENAME);
     path
 }
 
-pub(crate) fn load_config_file() -> anyhow::Result<Option<CliApiClientConfig>> {
-    let path = get_file_location();
-    let config = std::fs::read(&path);
+/// config.toml if it exists, env.json otherwise
+pub(crate) fn get_file_location() -> PathBuf {
+    let toml = toml_file_location();
+    if toml.exists() {
+        toml
+    } else {
+        json_file_location()
+    }
+}
+
+fn parse_config_file(path: &Path, contents: &[u8]) -> anyhow::Result<ConfigFile> {
+    let config: ConfigFile = if is_toml(path) {
+        let contents = std::str::from_utf8(contents).context(format!("{path:?} is not UTF-8"))?;
+        toml::from_str(contents).context(format!(
+            "Failed to parse config file. Check that {path:?} contains valid TOML{}",
+            backup_hint(path)
+        ))?
+    } else {
+        serde_json::from_slice(contents).context(format!(
+            "Failed to parse config file. Check that {path:?} contains valid JSON{}",
+            backup_hint(path)
+        ))?
+    };
+    config
+        .validate()
+        .context(format!("Invalid config file {path:?}"))?;
+    Ok(config)
+}
+
+fn read_config_file(path: &Path) -> anyhow::Result<Option<ConfigFile>> {
+    let config = std::fs::read(path);
     match config {
         Ok(val) => {
-            let config: CliApiClientConfig = serde_json::from_slice(val.as_slice())
-                .context("Failed to parse config file. Check that ~/.config/somfy-cli/env.json contains valid JSON")?;
-            Ok(Some(config))
+            ensure_private(path)?;
+            Ok(Some(parse_config_file(path, &val)?))
         }
         Err(_) => Ok(None),
     }
 }
+
+/// The settings of `profile`, or of the active profile if none is given
+pub(crate) fn load_config_file(
+    profile: Option<&str>,
+) -> anyhow::Result<Option<CliApiClientConfig>> {
+    let path = get_file_location();
+    match (read_config_file(&path)?, profile) {
+        (Some(config), _) => config.into_profile(profile).map(Some),
+        (None, Some(name)) => anyhow::bail!("Profile '{name}' not found, {path:?} does not exist"),
+        (None, None) => Ok(None),
+    }
+}
+
+/// Name of the explicitly selected profile, otherwise of the active one. None for the default profile.
+pub(crate) fn selected_profile(profile: Option<&str>) -> Option<String> {
+    let profile = profile
+        .map(str::to_string)
+        .or(std::env::var(PROFILE_VAR).ok().filter(|p| !p.is_empty()));
+    match read_config_file(&get_file_location()) {
+        Ok(Some(config)) => config.profile_name(profile.as_deref()),
+        _ => profile,
+    }
+}
+
+/// Settings of a profile about to be updated, empty if neither the profile nor the file exist yet
+pub(crate) fn load_profile_settings(profile: Option<&str>) -> anyhow::Result<CliApiClientConfig> {
+    let Some(mut config) = read_config_file(&get_file_location())? else {
+        return Ok(CliApiClientConfig::default());
+    };
+    Ok(match config.profile_name(profile) {
+        None => config.default,
+        Some(name) => config.profiles.remove(&name).unwrap_or_default(),
+    })
+}
+
+/// Replaces the file atomically, new files are only readable by the user
+pub(crate) fn write_private(path: &Path, contents: &str) -> anyhow::Result<()> {
+    write_atomically(path, contents.as_bytes()).context("Failed to write config file")
+}
+
+/// Like `update_profile`, comments and formatting of the TOML file are kept
+fn update_toml_profile(
+    path: &Path,
+    profile: Option<&str>,
+    values: Map<String, Value>,
+) -> anyhow::Result<()> {
+    let mut document = read_document(path)
+        .context(format!("Failed to update config file{}", backup_hint(path)))?
+        .unwrap_or_default();
+    let profile = profile.map(str::to_string).or(document
+        .get("active_profile")
+        .and_then(|p| p.as_str())
+        .map(str::to_string));
+
+    let settings = match &profile {
+        None => document.as_table_mut(),
+        Some(name) => {
+            let profiles = document
+                .entry("profiles")
+                .or_insert(toml_edit::table())
+                .as_table_mut()
+                .context(format!("profiles in {path:?} has to be a table"))?;
+            profiles.set_implicit(true);
+            profiles
+                .entry(name)
+                .or_insert(toml_edit::table())
+                .as_table_mut()
+                .context(format!("Profile '{name}' in {path:?} has to be a table"))?
+        }
+    };
+    for (key, value) in values {
+        match toml_value(&value) {
+            Some(value) => settings[key.as_str()] = toml_edit::value(value),
+            None => {
+                settings.remove(&key);
+            }
+        }
+    }
+
+    write_private(path, &document.to_string())
+}
+
+/// Sets `values` in the settings of the profile, creating the file or the profile if needed.
+/// Everything else in the file is kept as is. Runs under the lock of the file, so that concurrent
+/// updates are not lost, the previous version is kept as backup.
+fn update_profile(
+    path: &Path,
+    profile: Option<&str>,
+    values: Map<String, Value>,
+) -> anyhow::Result<()> {
+    let _lock = lock_exclusive(path)?;
+    if is_toml(path) {
+        return update_toml_profile(path, profile, values);
+    }
+    let mut root: Value = match std::fs::read(path) {
+        Ok(contents) => serde_json::from_slice(&contents).context(format!(
+            "Failed to parse config file {path:?}{}",
+            backup_hint(path)
+        ))?,
+        Err(_) => json!({}),
+    };
+    let profile = profile
+        .map(str::to_string)
+        .or(root["active_profile"].as_str().map(str::to_string));
+
+    let settings = match &profile {
+        None => &mut root,
+        Some(name) => {
+            if !root["profiles"].is_object() {
+                root["profiles"] = json!({});
+            }
+            if !root["profiles"][name].is_object() {
+                root["profiles"][name] = json!({});
+            }
+            &mut root["profiles"][name]
+        }
+    };
+    let Value::Object(settings) = settings else {
+        anyhow::bail!("Config file {path:?} has to contain a JSON object");
+    };
+    settings.extend(values);
+
+    write_private(path, &serde_json::to_string_pretty(&root)?)
+}
+
+/// Sets `values` in the settings of `profile`, or of the active profile if none is given
+pub(crate) fn store_profile_values(
+    profile: Option<&str>,
+    values: Map<String, Value>,
+) -> anyhow::Result<()> {
+    update_profile(&get_file_location(), profile, values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::common::backup_location;
+    use tempfile::TempDir;
+
+    fn config_file(json: &str) -> ConfigFile {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn test_profile_selection() {
+        let config = r#"{"hostname": "home.local", "active_profile": "office",
+            "profiles": {"office": {"hostname": "office.local"}, "cabin": {"hostname": "cabin.local"}}}"#;
+
+        let active = config_file(config).into_profile(None).unwrap();
+        let selected = config_file(config).into_profile(Some("cabin")).unwrap();
+        let missing = config_file(config).into_profile(Some("boat")).unwrap_err();
+        let default = config_file(r#"{"hostname": "home.local"}"#)
+            .into_profile(None)
+            .unwrap();
+
+        assert_eq!(active.hostname.as_deref(), Some("office.local"));
+        assert_eq!(selected.hostname.as_deref(), Some("cabin.local"));
+        assert_eq!(
+            missing.to_string(),
+            "Profile 'boat' not found in the config file"
+        );
+        assert_eq!(default.hostname.as_deref(), Some("home.local"));
+    }
+
+    #[test]
+    fn test_update_profile_keeps_other_settings() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("env.json");
+        std::fs::write(&path, r#"{"hostname": "home.local", "api_key": "old"}"#).unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600)).unwrap();
+        }
+        let values = |key: &str| Map::from_iter([("api_key".to_string(), json!(key))]);
+
+        update_profile(&path, None, values("new")).unwrap();
+        update_profile(&path, Some("office"), values("office")).unwrap();
+
+        let config = read_config_file(&path).unwrap().unwrap();
+        assert_eq!(config.default.hostname.as_deref(), Some("home.local"));
+        assert_eq!(config.default.api_key.as_deref(), Some("new"));
+        assert_eq!(config.profiles["office"].api_key.as_deref(), Some("office"));
+    }
+
+    #[test]
+    fn test_concurrent_profile_updates_are_kept() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("config.toml");
+        write_private(&path, "# Gateway at home\nhostname = \"home.local\"\n").unwrap();
+
+        let handles: Vec<_> = (0..8)
+            .map(|n| {
+                let path = path.clone();
+                std::thread::spawn(move || {
+                    let values = Map::from_iter([("api_key".to_string(), json!(format!("{n}")))]);
+                    update_profile(&path, Some(&format!("profile{n}")), values).unwrap();
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        let config = read_config_file(&path).unwrap().unwrap();
+        assert_eq!(config.profiles.len(), 8);
+        assert!(backup_location(&path).exists());
+    }
+
+    #[test]
+    fn test_poller_settings_have_to_be_positive() {
+        let parse = |contents: &str| parse_config_file(Path::new("env.json"), contents.as_bytes());
+
+        assert!(parse(r#"{"poller": {"max_wait_ms": 5000}}"#).is_ok());
+        let error = parse(r#"{"profiles": {"office": {"poller": {"max_wait_ms": 0}}}}"#)
+            .err()
+            .unwrap();
+        assert_eq!(
+            format!("{error:#}"),
+            "Invalid config file \"env.json\": poller.max_wait_ms of profile 'office' has to be greater than 0"
+        );
+    }
+
+    #[test]
+    fn test_update_toml_profile_keeps_comments() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("config.toml");
+        let contents = "# Gateway at home\nhostname = \"home.local\"\n\n[poller]\nmax_wait_ms = 5000\n\n[aliases]\nliving = \"io://1234/1\"\n";
+        write_private(&path, contents).unwrap();
+        let values = |key: &str| Map::from_iter([("api_key".to_string(), json!(key))]);
+
+        update_profile(&path, None, values("new")).unwrap();
+        update_profile(&path, Some("office"), values("office")).unwrap();
+
+        let written = std::fs::read_to_string(&path).unwrap();
+        assert!(written.starts_with("# Gateway at home\n"));
+        let config = read_config_file(&path).unwrap().unwrap();
+        assert_eq!(config.default.hostname.as_deref(), Some("home.local"));
+        assert_eq!(config.default.api_key.as_deref(), Some("new"));
+        assert_eq!(config.default.poller.unwrap().max_wait_ms, Some(5000));
+        assert_eq!(config.profiles["office"].api_key.as_deref(), Some("office"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_new_config_file_is_private() {
+        use std::os::unix::fs::PermissionsExt;
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("env.json");
+
+        update_profile(
+            &path,
+            None,
+            Map::from_iter([("api_key".to_string(), json!("key"))]),
+        )
+        .unwrap();
+
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        let leftovers = std::fs::read_dir(temp_dir.path())
+            .unwrap()
+            .filter(|entry| entry.as_ref().unwrap().path().extension().unwrap() == "tmp")
+            .count();
+        assert_eq!(leftovers, 0);
+    }
+}
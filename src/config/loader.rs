@@ -1,5 +1,6 @@
 use crate::commands::cli::Cli;
-use crate::config::dotenv::CliApiClientConfig;
+use crate::config::dotenv::{CliApiClientConfig, HttpProtocol as ConfigProtocol};
+use crate::config::secrets::resolve_api_key;
 use anyhow::Error;
 use somfy_sdk::api_client::{ApiClientConfig, CertificateHandling, HttpProtocol};
 
@@ -7,19 +8,45 @@ const DEFAULT_SOMFY_PORT: usize = 8443;
 const API_KEY_ERROR: &str = "api key not found in CLI args, ENV variables or in .env config file";
 const HOSTNAME_ERROR: &str = "hostname not found in CLI args, ENV variables or in .env config file";
 
-pub(crate) fn merge_config_sources(
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct GatewayAddress {
+    pub hostname: String,
+    pub port: usize,
+}
+
+/// Hostname and port of the gateway, for commands that connect without an API key
+pub(crate) fn gateway_address(
     cli_args: &Cli,
     config_file: &Option<CliApiClientConfig>,
-) -> anyhow::Result<ApiClientConfig> {
+) -> anyhow::Result<GatewayAddress> {
     let port: usize = match (cli_args.gateway_port, config_file) {
         (Some(port), _) => port,
         (None, Some(cfg)) => cfg.port.unwrap_or(DEFAULT_SOMFY_PORT),
         _ => DEFAULT_SOMFY_PORT,
     };
 
+    let url = match (&cli_args.gateway_url, config_file) {
+        (Some(url), _) => url,
+        (None, Some(cfg)) => match &cfg.hostname {
+            None => return Err(Error::msg(HOSTNAME_ERROR)),
+            Some(key) => key,
+        },
+        _ => return Err(Error::msg(HOSTNAME_ERROR)),
+    };
+
+    Ok(GatewayAddress {
+        hostname: url.to_string(),
+        port,
+    })
+}
+
+pub(crate) fn merge_config_sources(
+    cli_args: &Cli,
+    config_file: &Option<CliApiClientConfig>,
+) -> anyhow::Result<ApiClientConfig> {
     let api_key = match (&cli_args.api_key, config_file) {
-        (Some(api_key), _) => api_key,
-        (None, Some(cfg)) => match &cfg.api_key {
+        (Some(api_key), _) => api_key.clone(),
+        (None, Some(cfg)) => match resolve_api_key(cfg)? {
             None => return Err(Error::msg(API_KEY_ERROR)),
             Some(key) => key,
         },
@@ -30,14 +57,12 @@ pub(crate) fn merge_config_sources(
         return Err(Error::msg("The provided api key is empty"));
     }
 
-    let url = match (&cli_args.gateway_url, config_file) {
-        (Some(url), _) => url,
-        (None, Some(cfg)) => match &cfg.hostname {
-            None => return Err(Error::msg(HOSTNAME_ERROR)),
-            Some(key) => key,
-        },
-        _ => return Err(Error::msg(HOSTNAME_ERROR)),
-    };
+    let GatewayAddress { hostname, port } = gateway_address(cli_args, config_file)?;
+
+    let protocol = cli_args
+        .gateway_protocol
+        .or(config_file.as_ref().and_then(|cfg| cfg.protocol))
+        .unwrap_or(ConfigProtocol::Https);
 
     let cert_strategy = match (&cli_args.cert, config_file) {
         (Some(cert_path), _) => CertificateHandling::CertProvided(cert_path.to_string()),
@@ -48,12 +73,18 @@ pub(crate) fn merge_config_sources(
         _ => CertificateHandling::DefaultCert,
     };
 
+    let (protocol, cert_handling) = match protocol {
+        // Certificates only apply to TLS, e.g. the mock gateway serves plain HTTP
+        ConfigProtocol::Http => (HttpProtocol::HTTP, CertificateHandling::NoCustomCert),
+        ConfigProtocol::Https => (HttpProtocol::HTTPS, cert_strategy),
+    };
+
     let config = ApiClientConfig {
-        protocol: HttpProtocol::HTTPS,
-        cert_handling: cert_strategy,
+        protocol,
+        cert_handling,
         port,
-        api_key: api_key.to_string(),
-        url: url.to_string(),
+        api_key,
+        url: hostname,
     };
 
     Ok(config)
@@ -81,12 +112,16 @@ mod tests {
         cert: Option<String>,
     ) -> Cli {
         Cli {
-            command: Command::ListDevices,
+            command: Command::ListDevices(Default::default()),
             api_key,
             gateway_url,
             gateway_port,
+            gateway_protocol: None,
             output_style: OutputStyle::Json,
+            format: None,
             cert,
+            profile: None,
+            config_dir: None,
         }
     }
 
@@ -109,7 +144,16 @@ mod tests {
             hostname,
             port,
             api_key,
+            api_key_file: None,
+            api_key_cmd: None,
+            api_key_keyring: None,
             cert_path,
+            cert_fingerprint: None,
+            history: None,
+            poller: None,
+            gateway_id: None,
+            cloud_user: None,
+            cloud_url: None,
         }
     }
 
@@ -292,6 +336,39 @@ mod tests {
         assert_eq!(result.port, DEFAULT_SOMFY_PORT);
     }
 
+    #[test]
+    fn test_config_file_protocol_is_used() {
+        let cli_args = create_test_cli(
+            Some("test_key".to_string()),
+            Some("test_host".to_string()),
+            None,
+        );
+        let mut config_file = create_test_config(None, None, None);
+        config_file.protocol = Some(ConfigProtocol::Http);
+
+        let result = merge_config_sources(&cli_args, &Some(config_file)).unwrap();
+
+        assert_eq!(result.protocol, HttpProtocol::HTTP);
+        assert_eq!(result.cert_handling, CertificateHandling::NoCustomCert);
+    }
+
+    #[test]
+    fn test_cli_protocol_takes_precedence_over_config() {
+        let mut cli_args = create_test_cli_with_cert(
+            Some("test_key".to_string()),
+            Some("test_host".to_string()),
+            None,
+            Some("/cli/cert.pem".to_string()),
+        );
+        cli_args.gateway_protocol = Some(ConfigProtocol::Http);
+        let config_file = Some(create_test_config(None, None, None));
+
+        let result = merge_config_sources(&cli_args, &config_file).unwrap();
+
+        assert_eq!(result.protocol, HttpProtocol::HTTP);
+        assert_eq!(result.cert_handling, CertificateHandling::NoCustomCert);
+    }
+
     #[test]
     fn test_whitespace_api_key_considered_empty() {
         let cli_args = create_test_cli(
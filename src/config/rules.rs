@@ -0,0 +1,156 @@
+use crate::commands::executor::OpenClose;
+use crate::config::common::get_config_folder;
+use crate::events::fetch_device_events::DEVICE_STATE_CHANGED_EVENT;
+use anyhow::Context;
+use chrono::NaiveTime;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::PathBuf;
+
+const RULES_FILENAME: &str = "rules.json";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Comparison {
+    Eq(Value),
+    Ne(Value),
+    Gt(f64),
+    Gte(f64),
+    Lt(f64),
+    Lte(f64),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct Condition {
+    pub state: String,
+    #[serde(flatten)]
+    pub comparison: Comparison,
+}
+
+/// Local time window in which a rule is active. `from` > `to` spans midnight.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct TimeWindow {
+    pub from: NaiveTime,
+    pub to: NaiveTime,
+}
+
+fn default_event_name() -> String {
+    DEVICE_STATE_CHANGED_EVENT.to_string()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct Rule {
+    pub name: String,
+    /// Name of the event to react to, defaults to `DeviceStateChangedEvent`
+    #[serde(default = "default_event_name")]
+    pub event: String,
+    /// Device URL or alias the event has to originate from
+    pub device: Option<String>,
+    /// All conditions have to match the states contained in the event
+    #[serde(default)]
+    pub conditions: Vec<Condition>,
+    pub time_window: Option<TimeWindow>,
+    /// Minimum time between two firings of the rule
+    #[serde(default)]
+    pub debounce_seconds: u64,
+    pub action: OpenClose,
+    /// Device URLs or aliases the action is applied to
+    pub devices: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub(crate) struct RulesFile {
+    #[serde(default)]
+    pub rules: Vec<Rule>,
+}
+
+impl RulesFile {
+    fn validate(&self) -> anyhow::Result<()> {
+        for rule in &self.rules {
+            if let OpenClose::Closure(p) = rule.action {
+                if p > 100 {
+                    anyhow::bail!(
+                        "Rule '{}' has invalid position {p}, expected 0-100",
+                        rule.name
+                    );
+                }
+            }
+            if rule.devices.is_empty() {
+                anyhow::bail!("Rule '{}' has no target devices", rule.name);
+            }
+        }
+        Ok(())
+    }
+}
+
+pub(crate) fn get_file_location() -> PathBuf {
+    let mut path = get_config_folder();
+    path.push(RULES_FILENAME);
+    path
+}
+
+pub(crate) fn load_rules_file() -> anyhow::Result<RulesFile> {
+    let path = get_file_location();
+    let contents = std::fs::read(&path).context(format!(
+        "Failed to read rules file. Check that {path:?} exists"
+    ))?;
+    parse_rules(contents.as_slice())
+}
+
+fn parse_rules(contents: &[u8]) -> anyhow::Result<RulesFile> {
+    let rules: RulesFile = serde_json::from_slice(contents)
+        .context("Failed to parse rules file. Check that it contains valid JSON")?;
+    rules.validate()?;
+    Ok(rules)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_rules() {
+        let json = r#"{
+            "rules": [
+                {
+                    "name": "wind-protection",
+                    "device": "wind-sensor",
+                    "conditions": [{"state": "core:WindSpeedState", "gt": 40}],
+                    "debounce_seconds": 300,
+                    "action": "open",
+                    "devices": ["awning"]
+                },
+                {
+                    "name": "terrace-door",
+                    "device": "io://1234/door",
+                    "conditions": [{"state": "core:ContactState", "eq": "open"}],
+                    "time_window": {"from": "22:00", "to": "06:00"},
+                    "action": {"position": 0},
+                    "devices": ["terrace"]
+                }
+            ]
+        }"#;
+
+        let rules = parse_rules(json.as_bytes()).unwrap();
+
+        assert_eq!(rules.rules.len(), 2);
+        assert_eq!(rules.rules[0].event, DEVICE_STATE_CHANGED_EVENT);
+        assert_eq!(
+            rules.rules[0].conditions[0].comparison,
+            Comparison::Gt(40.0)
+        );
+        assert_eq!(
+            rules.rules[1].conditions[0].comparison,
+            Comparison::Eq(Value::from("open"))
+        );
+        assert_eq!(rules.rules[1].action, OpenClose::Closure(0));
+    }
+
+    #[test]
+    fn test_rule_without_targets_is_rejected() {
+        let json = r#"{"rules": [{"name": "nothing", "action": "close", "devices": []}]}"#;
+
+        let err = parse_rules(json.as_bytes()).unwrap_err();
+        assert!(err.to_string().contains("nothing"));
+    }
+}
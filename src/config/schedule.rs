@@ -0,0 +1,218 @@
+use crate::commands::executor::OpenClose;
+use crate::config::alias::TAG_PREFIX;
+use crate::config::common::get_config_folder;
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+const SCHEDULE_FILENAME: &str = "schedule.json";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct Location {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum SunEvent {
+    Sunrise,
+    Sunset,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Trigger {
+    /// Standard 5-field crontab expression, or 6/7 fields including seconds and years
+    Cron(String),
+    Sun {
+        event: SunEvent,
+        #[serde(default)]
+        offset_minutes: i64,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct ScheduleEntry {
+    pub name: String,
+    #[serde(flatten)]
+    pub trigger: Trigger,
+    pub action: OpenClose,
+    /// Device URLs or aliases the action is applied to
+    #[serde(default)]
+    pub devices: Vec<String>,
+    /// Alias tags, the action is applied to all tagged devices as well
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub groups: Vec<String>,
+}
+
+impl ScheduleEntry {
+    /// Devices and groups as device arguments, groups as `tag:<group>`
+    pub(crate) fn targets(&self) -> Vec<String> {
+        let groups = self.groups.iter().map(|g| format!("{TAG_PREFIX}{g}"));
+        self.devices.iter().cloned().chain(groups).collect()
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub(crate) struct ScheduleFile {
+    pub location: Option<Location>,
+    #[serde(default)]
+    pub entries: Vec<ScheduleEntry>,
+}
+
+impl Trigger {
+    /// The cron crate expects a leading seconds field, crontab syntax does not have one
+    pub(crate) fn cron_schedule(expression: &str) -> anyhow::Result<cron::Schedule> {
+        let expression = match expression.split_whitespace().count() {
+            5 => format!("0 {expression}"),
+            _ => expression.to_string(),
+        };
+        cron::Schedule::from_str(&expression)
+            .context(format!("Invalid cron expression '{expression}'"))
+    }
+}
+
+impl Display for Trigger {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Trigger::Cron(expr) => write!(f, "cron {expr}"),
+            Trigger::Sun {
+                event,
+                offset_minutes,
+            } => {
+                let event = match event {
+                    SunEvent::Sunrise => "sunrise",
+                    SunEvent::Sunset => "sunset",
+                };
+                match offset_minutes {
+                    0 => write!(f, "{event}"),
+                    o => write!(f, "{event} {o:+}min"),
+                }
+            }
+        }
+    }
+}
+
+impl ScheduleFile {
+    fn validate(&self) -> anyhow::Result<()> {
+        for entry in &self.entries {
+            match &entry.trigger {
+                Trigger::Cron(expr) => {
+                    Trigger::cron_schedule(expr)
+                        .context(format!("Schedule entry '{}' is invalid", entry.name))?;
+                }
+                Trigger::Sun { .. } if self.location.is_none() => {
+                    anyhow::bail!(
+                        "Schedule entry '{}' uses sunrise/sunset but no location (latitude/longitude) is configured",
+                        entry.name
+                    );
+                }
+                Trigger::Sun { .. } => {}
+            }
+
+            if let OpenClose::Closure(p) = entry.action {
+                if p > 100 {
+                    anyhow::bail!(
+                        "Schedule entry '{}' has invalid position {p}, expected 0-100",
+                        entry.name
+                    );
+                }
+            }
+            if entry.devices.is_empty() && entry.groups.is_empty() {
+                anyhow::bail!("Schedule entry '{}' has no devices or groups", entry.name);
+            }
+        }
+        Ok(())
+    }
+}
+
+pub(crate) fn get_file_location() -> PathBuf {
+    let mut path = get_config_folder();
+    path.push(SCHEDULE_FILENAME);
+    path
+}
+
+pub(crate) fn load_schedule_file() -> anyhow::Result<ScheduleFile> {
+    let path = get_file_location();
+    let contents = std::fs::read(&path).context(format!(
+        "Failed to read schedule file. Check that {path:?} exists"
+    ))?;
+    parse_schedule(contents.as_slice())
+}
+
+fn parse_schedule(contents: &[u8]) -> anyhow::Result<ScheduleFile> {
+    let schedule: ScheduleFile = serde_json::from_slice(contents)
+        .context("Failed to parse schedule file. Check that it contains valid JSON")?;
+    schedule.validate()?;
+    Ok(schedule)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_cron_and_sun_entries() {
+        let json = r#"{
+            "location": {"latitude": 48.1, "longitude": 11.5},
+            "entries": [
+                {"name": "morning", "cron": "30 7 * * Mon-Fri", "action": "open", "devices": ["living-room"]},
+                {"name": "dusk", "sun": {"event": "sunset", "offset_minutes": -15}, "action": {"position": 80}, "devices": ["io://1234/5678"]}
+            ]
+        }"#;
+
+        let schedule = parse_schedule(json.as_bytes()).unwrap();
+
+        assert_eq!(schedule.entries.len(), 2);
+        assert_eq!(
+            schedule.entries[0].trigger.to_string(),
+            "cron 30 7 * * Mon-Fri"
+        );
+        assert_eq!(schedule.entries[0].action.to_string(), "open");
+        assert_eq!(schedule.entries[1].trigger.to_string(), "sunset -15min");
+        assert_eq!(schedule.entries[1].action.to_string(), "position 80");
+    }
+
+    #[test]
+    fn test_groups_are_targeted_as_tags() {
+        let json = r#"{"entries": [{"name": "south", "cron": "0 8 * * *", "action": "open", "devices": ["kitchen"], "groups": ["south"]}]}"#;
+
+        let schedule = parse_schedule(json.as_bytes()).unwrap();
+        assert_eq!(schedule.entries[0].targets(), vec!["kitchen", "tag:south"]);
+
+        let json = r#"{"entries": [{"name": "nothing", "cron": "0 8 * * *", "action": "open"}]}"#;
+        assert!(parse_schedule(json.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_sun_entry_without_location_is_rejected() {
+        let json = r#"{"entries": [{"name": "dawn", "sun": {"event": "sunrise"}, "action": "open", "devices": []}]}"#;
+
+        let err = parse_schedule(json.as_bytes()).unwrap_err();
+        assert!(err.to_string().contains("no location"));
+    }
+
+    #[test]
+    fn test_invalid_cron_expression_is_rejected() {
+        let json = r#"{"entries": [{"name": "broken", "cron": "not a cron", "action": "close", "devices": []}]}"#;
+
+        let err = parse_schedule(json.as_bytes()).unwrap_err();
+        assert!(err.to_string().contains("broken"));
+    }
+
+    #[test]
+    fn test_invalid_position_is_rejected() {
+        let json = r#"{"entries": [{"name": "too-far", "cron": "0 8 * * *", "action": {"position": 150}, "devices": []}]}"#;
+
+        assert!(parse_schedule(json.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_cron_schedule_accepts_crontab_and_seconds_syntax() {
+        assert!(Trigger::cron_schedule("*/5 * * * *").is_ok());
+        assert!(Trigger::cron_schedule("0 */5 * * * *").is_ok());
+    }
+}
@@ -0,0 +1,191 @@
+use crate::config::dotenv::CliApiClientConfig;
+use anyhow::Context;
+use std::path::Path;
+use std::process::Command;
+
+/// Service name of the API keys in the system keyring
+pub(crate) const KEYRING_SERVICE: &str = "somfy-cli";
+
+/// Secrets must not be readable by other users
+#[cfg(unix)]
+pub(crate) fn ensure_private(path: &Path) -> anyhow::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mode = std::fs::metadata(path)
+        .context(format!("Failed to read permissions of {path:?}"))?
+        .permissions()
+        .mode();
+    if mode & 0o004 != 0 {
+        anyhow::bail!(
+            "{path:?} is readable by all users and may contain secrets, restrict it with 'chmod 600 {}'",
+            path.display()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub(crate) fn ensure_private(_path: &Path) -> anyhow::Result<()> {
+    Ok(())
+}
+
+fn read_key_file(path: &str) -> anyhow::Result<String> {
+    let path = Path::new(path);
+    ensure_private(path)?;
+    let key =
+        std::fs::read_to_string(path).context(format!("Failed to read api_key_file {path:?}"))?;
+    Ok(key.trim().to_string())
+}
+
+fn run_key_command(command: &str) -> anyhow::Result<String> {
+    let output = if cfg!(windows) {
+        Command::new("cmd").args(["/C", command]).output()
+    } else {
+        Command::new("sh").args(["-c", command]).output()
+    }
+    .context(format!("Failed to run api_key_cmd '{command}'"))?;
+
+    if !output.status.success() {
+        anyhow::bail!(
+            "api_key_cmd '{command}' failed with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8(output.stdout)
+        .context(format!("api_key_cmd '{command}' did not print valid UTF-8"))?
+        .trim()
+        .to_string())
+}
+
+fn keyring_entry(account: &str) -> anyhow::Result<keyring::Entry> {
+    keyring::Entry::new(KEYRING_SERVICE, account).context("Failed to access the system keyring")
+}
+
+fn read_keyring(account: &str) -> anyhow::Result<String> {
+    match keyring_entry(account)?.get_password() {
+        Err(keyring::Error::NoEntry) => anyhow::bail!(
+            "No API key for '{account}' in the system keyring, store it with 'somfy keyring set {account}'"
+        ),
+        result => result.context(format!(
+            "Failed to read the API key of '{account}' from the system keyring"
+        )),
+    }
+}
+
+pub(crate) fn store_in_keyring(account: &str, api_key: &str) -> anyhow::Result<()> {
+    keyring_entry(account)?
+        .set_password(api_key)
+        .context("Failed to store the API key in the system keyring")
+}
+
+pub(crate) fn delete_from_keyring(account: &str) -> anyhow::Result<()> {
+    keyring_entry(account)?.delete_credential().context(format!(
+        "Failed to delete the API key of '{account}' from the system keyring"
+    ))
+}
+
+/// The API key of the config file, in the order `api_key`, `api_key_file`, `api_key_cmd`, `api_key_keyring`
+pub(crate) fn resolve_api_key(config: &CliApiClientConfig) -> anyhow::Result<Option<String>> {
+    if let Some(key) = &config.api_key {
+        return Ok(Some(key.clone()));
+    }
+    if let Some(path) = &config.api_key_file {
+        return read_key_file(path).map(Some);
+    }
+    if let Some(command) = &config.api_key_cmd {
+        return run_key_command(command).map(Some);
+    }
+    if let Some(account) = &config.api_key_keyring {
+        return read_keyring(account).map(Some);
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn empty_config() -> CliApiClientConfig {
+        CliApiClientConfig::default()
+    }
+
+    fn private_key_file(temp_dir: &TempDir, content: &str) -> String {
+        let path = temp_dir.path().join("api_key");
+        std::fs::write(&path, content).unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600)).unwrap();
+        }
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn test_api_key_takes_precedence() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = CliApiClientConfig {
+            api_key: Some("plain".to_string()),
+            api_key_file: Some(private_key_file(&temp_dir, "from-file")),
+            ..empty_config()
+        };
+
+        assert_eq!(resolve_api_key(&config).unwrap(), Some("plain".to_string()));
+    }
+
+    #[test]
+    fn test_api_key_file_is_trimmed() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = CliApiClientConfig {
+            api_key_file: Some(private_key_file(&temp_dir, "from-file\n")),
+            ..empty_config()
+        };
+
+        assert_eq!(
+            resolve_api_key(&config).unwrap(),
+            Some("from-file".to_string())
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_world_readable_key_file_is_rejected() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp_dir = TempDir::new().unwrap();
+        let path = private_key_file(&temp_dir, "from-file");
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644)).unwrap();
+        let config = CliApiClientConfig {
+            api_key_file: Some(path),
+            ..empty_config()
+        };
+
+        let err = resolve_api_key(&config).unwrap_err();
+        assert!(err.to_string().contains("readable by all users"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_api_key_cmd() {
+        let config = CliApiClientConfig {
+            api_key_cmd: Some("echo from-cmd".to_string()),
+            ..empty_config()
+        };
+        assert_eq!(
+            resolve_api_key(&config).unwrap(),
+            Some("from-cmd".to_string())
+        );
+
+        let failing = CliApiClientConfig {
+            api_key_cmd: Some("echo locked >&2; exit 1".to_string()),
+            ..empty_config()
+        };
+        assert!(format!("{:#}", resolve_api_key(&failing).unwrap_err()).contains("locked"));
+    }
+
+    #[test]
+    fn test_no_api_key_configured() {
+        assert_eq!(resolve_api_key(&empty_config()).unwrap(), None);
+    }
+}
@@ -0,0 +1,164 @@
+use crate::config::common::get_config_folder;
+use crate::config::dotenv::write_private;
+use anyhow::Context;
+use serde_json::{Map, Value};
+use std::path::{Path, PathBuf};
+use toml_edit::{DocumentMut, Item, Table};
+
+/// Unified configuration with settings, profiles and aliases, used instead of env.json and alias.json
+const TOML_CONFIG_FILENAME: &str = "config.toml";
+
+pub(crate) fn toml_file_location() -> PathBuf {
+    get_config_folder().join(TOML_CONFIG_FILENAME)
+}
+
+pub(crate) fn is_toml(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "toml")
+}
+
+/// The document with its comments and formatting, None if the file does not exist
+pub(crate) fn read_document(path: &Path) -> anyhow::Result<Option<DocumentMut>> {
+    match std::fs::read_to_string(path) {
+        Ok(contents) => Ok(Some(
+            contents
+                .parse()
+                .context(format!("Failed to parse {path:?} as TOML"))?,
+        )),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e).context(format!("Failed to read {path:?}")),
+    }
+}
+
+/// TOML has no null, null values are left out
+pub(crate) fn toml_value(value: &Value) -> Option<toml_edit::Value> {
+    Some(match value {
+        Value::Null => return None,
+        Value::Bool(b) => (*b).into(),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => i.into(),
+            None => n.as_f64()?.into(),
+        },
+        Value::String(s) => s.as_str().into(),
+        Value::Array(items) => items
+            .iter()
+            .filter_map(toml_value)
+            .collect::<toml_edit::Array>()
+            .into(),
+        Value::Object(map) => map
+            .iter()
+            .filter_map(|(key, value)| Some((key.as_str(), toml_value(value)?)))
+            .collect::<toml_edit::InlineTable>()
+            .into(),
+    })
+}
+
+/// Objects become tables of their own, tables only holding other tables get no header
+fn toml_table(map: &Map<String, Value>) -> Table {
+    let mut table = Table::new();
+    table.set_implicit(!map.is_empty() && map.values().all(Value::is_object));
+    for (key, value) in map {
+        let item = match value {
+            Value::Object(inner) => Item::Table(toml_table(inner)),
+            _ => match toml_value(value) {
+                Some(value) => Item::Value(value),
+                None => continue,
+            },
+        };
+        table.insert(key, item);
+    }
+    table
+}
+
+fn read_json(path: &Path) -> anyhow::Result<Option<Map<String, Value>>> {
+    let contents = match std::fs::read(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).context(format!("Failed to read {path:?}")),
+    };
+    match serde_json::from_slice(&contents).context(format!("Failed to parse {path:?}"))? {
+        Value::Object(map) => Ok(Some(map)),
+        _ => anyhow::bail!("{path:?} has to contain a JSON object"),
+    }
+}
+
+/// Merges env.json and alias.json into `toml_path`. The converted files are kept with a `.bak`
+/// suffix, so that they are not mistaken for the configuration in use. Returns their paths.
+pub(crate) fn convert_to_toml(
+    env_path: &Path,
+    alias_path: &Path,
+    toml_path: &Path,
+    force: bool,
+) -> anyhow::Result<Vec<PathBuf>> {
+    if toml_path.exists() && !force {
+        anyhow::bail!("{toml_path:?} already exists, pass --force to overwrite it");
+    }
+    let mut root = Map::new();
+    let mut sources = vec![];
+    if let Some(settings) = read_json(env_path)? {
+        root.extend(settings);
+        sources.push(env_path);
+    }
+    if let Some(aliases) = read_json(alias_path)? {
+        root.insert("aliases".to_string(), Value::Object(aliases));
+        sources.push(alias_path);
+    }
+    if sources.is_empty() {
+        anyhow::bail!("Neither {env_path:?} nor {alias_path:?} exist, there is nothing to convert");
+    }
+
+    let mut document = DocumentMut::new();
+    *document.as_table_mut() = toml_table(&root);
+    document.as_table_mut().set_implicit(false);
+    write_private(
+        toml_path,
+        &format!(
+            "# Converted from env.json and alias.json by `somfy config convert`\n\n{document}"
+        ),
+    )?;
+
+    sources
+        .into_iter()
+        .map(|source| {
+            let backup = source.with_extension("json.bak");
+            std::fs::rename(source, &backup)
+                .context(format!("Failed to rename {source:?} to {backup:?}"))?;
+            Ok(backup)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_convert_merges_settings_and_aliases() {
+        let temp_dir = TempDir::new().unwrap();
+        let env_path = temp_dir.path().join("env.json");
+        let alias_path = temp_dir.path().join("alias.json");
+        let toml_path = temp_dir.path().join("config.toml");
+        std::fs::write(
+            &env_path,
+            r#"{"hostname": "home.local", "port": 8443, "cert_path": null,
+                "profiles": {"office": {"hostname": "office.local"}}}"#,
+        )
+        .unwrap();
+        std::fs::write(&alias_path, r#"{"living": "io://1234/1"}"#).unwrap();
+
+        let backups = convert_to_toml(&env_path, &alias_path, &toml_path, false).unwrap();
+
+        assert_eq!(backups.len(), 2);
+        assert!(!env_path.exists() && !alias_path.exists());
+        let document = read_document(&toml_path).unwrap().unwrap();
+        assert_eq!(document["hostname"].as_str(), Some("home.local"));
+        assert_eq!(document["port"].as_integer(), Some(8443));
+        assert!(document.get("cert_path").is_none());
+        assert_eq!(
+            document["profiles"]["office"]["hostname"].as_str(),
+            Some("office.local")
+        );
+        assert_eq!(document["aliases"]["living"].as_str(), Some("io://1234/1"));
+        assert!(convert_to_toml(&env_path, &alias_path, &toml_path, false).is_err());
+    }
+}
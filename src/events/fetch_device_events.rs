@@ -0,0 +1,152 @@
+use crate::commands::error::request_status;
+use chrono::{DateTime, Utc};
+use reqwest::header::HeaderMap;
+use reqwest::Body;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use somfy_sdk::commands::traits::{
+    HttpMethod, RequestData, SomfyApiRequestCommand, SomfyApiRequestResponse,
+};
+use somfy_sdk::err::http::RequestError;
+use std::collections::HashMap;
+use urlencoding::encode;
+
+pub(crate) const DEVICE_STATE_CHANGED_EVENT: &str = "DeviceStateChangedEvent";
+
+/// The gateway drops listeners that were not fetched for about 10 minutes and then rejects fetches of them
+pub(crate) fn is_listener_expired(error: &anyhow::Error) -> bool {
+    request_status(error)
+        .is_some_and(|status| [StatusCode::BAD_REQUEST, StatusCode::NOT_FOUND].contains(&status))
+}
+
+/// Same endpoint as the SDK's `FetchEventsCommand`, but keeps the full event payload
+/// (device URL, changed states, execution ids, ...) instead of only the event name.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct FetchDeviceEventsCommand<'a> {
+    pub listener_id: &'a str,
+}
+
+impl SomfyApiRequestCommand for FetchDeviceEventsCommand<'_> {
+    type Response = FetchDeviceEventsResponse;
+    fn to_request(&self) -> Result<RequestData, RequestError> {
+        let encoded_listener_id = encode(self.listener_id);
+        Ok(RequestData {
+            path: format!("/enduser-mobile-web/1/enduserAPI/events/{encoded_listener_id}/fetch"),
+            method: HttpMethod::POST,
+            body: Body::default(),
+            query_params: HashMap::default(),
+            header_map: HeaderMap::default(),
+        })
+    }
+}
+
+/// State values in events are mostly transmitted as strings, e.g. `"50"` for a closure of 50%
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct EventState {
+    pub name: String,
+    #[serde(rename = "type", default)]
+    pub state_type: i64,
+    pub value: Value,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct DeviceEvent {
+    pub name: String,
+    #[serde(rename = "deviceURL", default, skip_serializing_if = "Option::is_none")]
+    pub device_url: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub device_states: Vec<EventState>,
+    /// All remaining fields of the raw event, e.g. `execId`, `newState` or `timestamp`
+    #[serde(flatten)]
+    pub other: Map<String, Value>,
+}
+
+impl DeviceEvent {
+    /// When the gateway emitted the event, sent as epoch milliseconds
+    pub(crate) fn timestamp(&self) -> Option<DateTime<Utc>> {
+        self.other
+            .get("timestamp")
+            .and_then(Value::as_i64)
+            .and_then(DateTime::from_timestamp_millis)
+    }
+
+    pub(crate) fn state(&self, name: &str) -> Option<&Value> {
+        self.device_states
+            .iter()
+            .find(|s| s.name == name)
+            .map(|s| &s.value)
+    }
+}
+
+/// Newtype around the event list, the SDK response trait cannot be implemented for `Vec` here
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub(crate) struct FetchDeviceEventsResponse(pub Vec<DeviceEvent>);
+
+impl SomfyApiRequestResponse for FetchDeviceEventsResponse {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rejected_fetch_means_expired_listener() {
+        let status = |status| {
+            crate::commands::error::request_error(
+                RequestError::Status {
+                    status,
+                    source: None,
+                },
+                crate::commands::error::ErrorKind::General,
+            )
+        };
+
+        assert!(is_listener_expired(&status(StatusCode::BAD_REQUEST)));
+        assert!(!is_listener_expired(&status(
+            StatusCode::INTERNAL_SERVER_ERROR
+        )));
+        assert!(!is_listener_expired(&anyhow::anyhow!("connection reset")));
+    }
+
+    #[test]
+    fn test_generates_correct_request_path() {
+        let command = FetchDeviceEventsCommand {
+            listener_id: "test-id-with-special-chars!@#",
+        };
+        let request_data = command.to_request().unwrap();
+        assert_eq!(
+            request_data.path,
+            "/enduser-mobile-web/1/enduserAPI/events/test-id-with-special-chars%21%40%23/fetch"
+        );
+        assert_eq!(request_data.method, HttpMethod::POST);
+    }
+
+    #[test]
+    fn test_parses_state_changed_event_with_raw_fields() {
+        let body = r#"[
+          {
+            "name": "DeviceStateChangedEvent",
+            "deviceURL": "io://1234-5678-9012/device1",
+            "deviceStates": [{"name": "core:ClosureState", "type": 1, "value": "50"}],
+            "timestamp": 1700000000000
+          },
+          {"name": "ExecutionStateChangedEvent", "execId": "abc", "newState": "COMPLETED"}
+        ]"#;
+
+        let events = FetchDeviceEventsResponse::from_body(body).unwrap().0;
+
+        assert_eq!(events.len(), 2);
+        assert_eq!(
+            events[0].device_url.as_deref(),
+            Some("io://1234-5678-9012/device1")
+        );
+        assert_eq!(
+            events[0].state("core:ClosureState"),
+            Some(&Value::String("50".to_string()))
+        );
+        assert!(events[0].other.contains_key("timestamp"));
+        assert_eq!(events[1].other.get("execId"), Some(&Value::from("abc")));
+    }
+}
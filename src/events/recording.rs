@@ -0,0 +1,104 @@
+use crate::events::fetch_device_events::DeviceEvent;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// A single line of a recorded event stream (newline delimited JSON)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct RecordedEvent {
+    pub timestamp: DateTime<Utc>,
+    pub event: DeviceEvent,
+}
+
+/// Appends events to a recording file, one JSON object per line
+pub(crate) struct EventRecorder {
+    writer: BufWriter<File>,
+}
+
+impl EventRecorder {
+    pub(crate) fn create(path: &Path) -> anyhow::Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .context(format!("Failed to open event recording at {path:?}"))?;
+
+        Ok(Self {
+            writer: BufWriter::new(file),
+        })
+    }
+
+    /// Flushes after every event, so that recordings stay usable when the process is killed
+    pub(crate) fn record(&mut self, event: &RecordedEvent) -> anyhow::Result<()> {
+        serde_json::to_writer(&mut self.writer, event)?;
+        self.writer.write_all(b"\n")?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+pub(crate) fn read_recording(path: &Path) -> anyhow::Result<Vec<RecordedEvent>> {
+    let contents = std::fs::read_to_string(path)
+        .context(format!("Failed to read event recording at {path:?}"))?;
+    parse_recording(&contents)
+}
+
+fn parse_recording(contents: &str) -> anyhow::Result<Vec<RecordedEvent>> {
+    contents
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(idx, line)| {
+            serde_json::from_str(line)
+                .context(format!("Invalid recorded event on line {}", idx + 1))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_recording_skips_blank_lines() {
+        let contents = r#"{"timestamp":"2024-06-21T18:00:00Z","event":{"name":"DeviceStateChangedEvent","deviceURL":"io://1/2"}}
+
+{"timestamp":"2024-06-21T18:00:05Z","event":{"name":"ExecutionRegisteredEvent","execId":"abc"}}
+"#;
+
+        let events = parse_recording(contents).unwrap();
+
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].event.device_url.as_deref(), Some("io://1/2"));
+        assert_eq!(events[1].event.name, "ExecutionRegisteredEvent");
+    }
+
+    #[test]
+    fn test_recorder_output_can_be_read_back() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("events.ndjson");
+        let event: RecordedEvent = serde_json::from_str(
+            r#"{"timestamp":"2024-06-21T18:00:00Z","event":{"name":"DeviceStateChangedEvent","deviceURL":"io://1/2","deviceStates":[{"name":"core:ClosureState","type":1,"value":"50"}]}}"#,
+        )
+        .unwrap();
+
+        let mut recorder = EventRecorder::create(&path).unwrap();
+        recorder.record(&event).unwrap();
+        recorder.record(&event).unwrap();
+
+        let events = read_recording(&path).unwrap();
+        assert_eq!(events, vec![event.clone(), event]);
+    }
+
+    #[test]
+    fn test_parse_recording_reports_line_number() {
+        let contents =
+            "{\"timestamp\":\"2024-06-21T18:00:00Z\",\"event\":{\"name\":\"A\"}}\nnot json\n";
+
+        let err = parse_recording(contents).unwrap_err();
+        assert!(err.to_string().contains("line 2"));
+    }
+}
@@ -0,0 +1,292 @@
+use crate::commands::executor::CommandExecutor;
+use crate::events::fetch_device_events::is_listener_expired;
+use crate::events::recording::{EventRecorder, RecordedEvent};
+use crate::output::formatter::{format_stream_item, OutputFormat};
+use crate::utils::poller::PollerConfig;
+use chrono::{DateTime, Utc};
+use log::warn;
+use std::time::Duration;
+use tokio::time::sleep;
+
+/// Upper bound for the delay between fetches while the gateway keeps failing
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Where the events of the listen pipeline come from
+pub(crate) enum EventSource<'a> {
+    /// Polls an event listener registered on the gateway
+    Live {
+        executor: &'a CommandExecutor,
+        listener_id: String,
+        /// Extra delay after failed fetches, reset by the next successful one
+        backoff: Duration,
+        /// Set when a new listener replaced an expired one, events in between are lost
+        renewed: bool,
+    },
+    /// Feeds a recorded event stream, keeping the original pacing divided by `speed`
+    Replay {
+        events: std::vec::IntoIter<RecordedEvent>,
+        speed: f64,
+        previous: Option<DateTime<Utc>>,
+    },
+}
+
+impl<'a> EventSource<'a> {
+    pub(crate) async fn live(executor: &'a CommandExecutor) -> anyhow::Result<Self> {
+        Ok(EventSource::Live {
+            listener_id: executor.register_event_listener().await?,
+            executor,
+            backoff: Duration::ZERO,
+            renewed: false,
+        })
+    }
+
+    /// Whether the listener was renewed since the last call, resets the flag
+    pub(crate) fn take_renewed(&mut self) -> bool {
+        match self {
+            EventSource::Live { renewed, .. } => std::mem::take(renewed),
+            EventSource::Replay { .. } => false,
+        }
+    }
+
+    /// A `speed` of 0 replays all events without any delay
+    pub(crate) fn replay(events: Vec<RecordedEvent>, speed: f64) -> Self {
+        EventSource::Replay {
+            events: events.into_iter(),
+            speed,
+            previous: None,
+        }
+    }
+
+    /// Returns the next batch of events, or None once the source is exhausted
+    pub(crate) async fn next_batch(&mut self) -> anyhow::Result<Option<Vec<RecordedEvent>>> {
+        match self {
+            EventSource::Live {
+                executor,
+                listener_id,
+                backoff,
+                renewed,
+            } => {
+                sleep(PollerConfig::EVENT_LISTENER.refresh_interval + *backoff).await;
+                let events = match executor.fetch_device_events(listener_id).await {
+                    Ok(events) => {
+                        *backoff = Duration::ZERO;
+                        events
+                    }
+                    Err(e) if is_listener_expired(&e) => {
+                        warn!("Event listener expired, registering a new one");
+                        match executor.register_event_listener().await {
+                            Ok(id) => {
+                                *listener_id = id;
+                                *renewed = true;
+                            }
+                            Err(e) => {
+                                *backoff = next_backoff(*backoff);
+                                warn!(
+                                    "Registering event listener failed, retrying in {}s: {e:#}",
+                                    backoff.as_secs()
+                                );
+                            }
+                        }
+                        vec![]
+                    }
+                    Err(e) => {
+                        *backoff = next_backoff(*backoff);
+                        warn!(
+                            "Fetching events failed, retrying in {}s: {e:#}",
+                            backoff.as_secs()
+                        );
+                        vec![]
+                    }
+                };
+                let timestamp = Utc::now();
+                Ok(Some(
+                    events
+                        .into_iter()
+                        .map(|event| RecordedEvent { timestamp, event })
+                        .collect(),
+                ))
+            }
+            EventSource::Replay {
+                events,
+                speed,
+                previous,
+            } => {
+                let Some(next) = events.next() else {
+                    return Ok(None);
+                };
+
+                if let Some(prev) = previous {
+                    let gap = (next.timestamp - *prev).to_std().unwrap_or_default();
+                    if *speed > 0.0 {
+                        let delay = Duration::try_from_secs_f64(gap.as_secs_f64() / *speed)
+                            .map_err(|_| {
+                                anyhow::anyhow!(
+                                    "The delay before the event at {} is too long to replay, increase --speed",
+                                    next.timestamp
+                                )
+                            })?;
+                        sleep(delay).await;
+                    }
+                }
+                *previous = Some(next.timestamp);
+                Ok(Some(vec![next]))
+            }
+        }
+    }
+}
+
+/// Replay speed factor of `listen --speed`, 0 replays without delays
+pub(crate) fn parse_replay_speed(value: &str) -> Result<f64, String> {
+    value
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|speed| speed.is_finite() && *speed >= 0.0)
+        .ok_or(format!(
+            "'{value}' is not a replay speed, e.g. 10, or 0 for no delays"
+        ))
+}
+
+/// Doubles the delay after each failure, from 1s up to `MAX_BACKOFF`
+fn next_backoff(backoff: Duration) -> Duration {
+    (backoff * 2).clamp(Duration::from_secs(1), MAX_BACKOFF)
+}
+
+/// Only events matching all configured criteria pass, empty criteria match everything
+#[derive(Debug, Clone, Default)]
+pub(crate) struct EventFilter {
+    pub event_names: Vec<String>,
+    pub device_urls: Vec<String>,
+}
+
+impl EventFilter {
+    pub(crate) fn matches(&self, recorded: &RecordedEvent) -> bool {
+        let event = &recorded.event;
+        let name_matches = self.event_names.is_empty() || self.event_names.contains(&event.name);
+        let device_matches = self.device_urls.is_empty()
+            || event
+                .device_url
+                .as_ref()
+                .is_some_and(|d| self.device_urls.contains(d));
+
+        name_matches && device_matches
+    }
+}
+
+/// Hands the formatted events of the source to `emit` until it is exhausted. All events are recorded unfiltered.
+pub(crate) async fn listen(
+    mut source: EventSource<'_>,
+    filter: &EventFilter,
+    mut recorder: Option<EventRecorder>,
+    format: &OutputFormat,
+    mut emit: impl FnMut(String) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    let mut first = true;
+    while let Some(batch) = source.next_batch().await? {
+        for recorded in batch {
+            if let Some(recorder) = recorder.as_mut() {
+                recorder.record(&recorded)?;
+            }
+            if filter.matches(&recorded) {
+                emit(format_stream_item(recorded, format, first)?)?;
+                first = false;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn recorded(name: &str, device: Option<&str>, timestamp: &str) -> RecordedEvent {
+        serde_json::from_value(json!({
+            "timestamp": timestamp,
+            "event": {"name": name, "deviceURL": device}
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_empty_filter_matches_everything() {
+        let filter = EventFilter::default();
+        assert!(filter.matches(&recorded("A", None, "2024-01-01T00:00:00Z")));
+    }
+
+    #[test]
+    fn test_filter_by_name_and_device() {
+        let filter = EventFilter {
+            event_names: vec!["DeviceStateChangedEvent".to_string()],
+            device_urls: vec!["io://1/2".to_string()],
+        };
+
+        assert!(filter.matches(&recorded(
+            "DeviceStateChangedEvent",
+            Some("io://1/2"),
+            "2024-01-01T00:00:00Z"
+        )));
+        assert!(!filter.matches(&recorded(
+            "DeviceStateChangedEvent",
+            Some("io://1/3"),
+            "2024-01-01T00:00:00Z"
+        )));
+        assert!(!filter.matches(&recorded(
+            "ExecutionRegisteredEvent",
+            None,
+            "2024-01-01T00:00:00Z"
+        )));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_replay_keeps_order_and_scales_delays() {
+        let events = vec![
+            recorded("A", None, "2024-01-01T00:00:00Z"),
+            recorded("B", None, "2024-01-01T00:00:10Z"),
+        ];
+        let mut source = EventSource::replay(events, 10.0);
+
+        let started = tokio::time::Instant::now();
+        let first = source.next_batch().await.unwrap().unwrap();
+        let second = source.next_batch().await.unwrap().unwrap();
+
+        assert_eq!(first[0].event.name, "A");
+        assert_eq!(second[0].event.name, "B");
+        assert_eq!(started.elapsed(), Duration::from_secs(1));
+        assert!(source.next_batch().await.unwrap().is_none());
+    }
+
+    #[test]
+    fn test_replay_speed_has_to_be_finite_and_not_negative() {
+        assert_eq!(parse_replay_speed("0"), Ok(0.0));
+        assert_eq!(parse_replay_speed("2.5"), Ok(2.5));
+        for speed in ["-1", "NaN", "inf", "fast"] {
+            assert!(parse_replay_speed(speed).is_err(), "{speed}");
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_replay_rejects_delays_too_long_to_wait() {
+        let events = vec![
+            recorded("A", None, "2024-01-01T00:00:00Z"),
+            recorded("B", None, "2024-01-01T00:00:10Z"),
+        ];
+        let mut source = EventSource::replay(events, 1e-300);
+
+        source.next_batch().await.unwrap();
+        assert!(source.next_batch().await.is_err());
+    }
+
+    #[test]
+    fn test_backoff_doubles_up_to_the_maximum() {
+        let mut backoff = Duration::ZERO;
+        let mut delays = vec![];
+        for _ in 0..8 {
+            backoff = next_backoff(backoff);
+            delays.push(backoff.as_secs());
+        }
+        assert_eq!(delays, vec![1, 2, 4, 8, 16, 32, 60, 60]);
+    }
+}
@@ -0,0 +1,280 @@
+use crate::commands::executor::CommandExecutor;
+use crate::events::fetch_device_events::{DeviceEvent, EventState, DEVICE_STATE_CHANGED_EVENT};
+use crate::events::stream::EventSource;
+use crate::output::categories::highlighted_device_tables;
+use crate::output::formatter::{
+    build_highlighted_table, print_to_console, OutputFormat, OutputStyle, TableRows,
+};
+use chrono::Local;
+use log::warn;
+use serde_json::Value;
+use somfy_sdk::commands::get_current_executions::GetCurrentExecutionsResponse;
+use somfy_sdk::commands::get_device_states::GetDeviceStatesResponse;
+use somfy_sdk::commands::get_devices::GetDevicesResponse;
+use somfy_sdk::commands::types::{DeviceState, DeviceStateValue};
+use std::io::Write;
+
+const CLEAR_SCREEN: &str = "\x1b[2J\x1b[H";
+const EXECUTION_EVENT_PREFIX: &str = "Execution";
+/// State type ids the gateway uses for typed values
+const INT_STATE_TYPE: i64 = 1;
+const BOOLEAN_STATE_TYPE: i64 = 6;
+
+/// What `--watch` keeps up to date
+pub(crate) enum WatchTarget {
+    Devices,
+    DeviceStates(String),
+    Executions,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Snapshot {
+    Devices(GetDevicesResponse),
+    DeviceStates(GetDeviceStatesResponse),
+    Executions(GetCurrentExecutionsResponse),
+}
+
+impl WatchTarget {
+    fn title(&self) -> String {
+        match self {
+            WatchTarget::Devices => "Devices".to_string(),
+            WatchTarget::DeviceStates(device_url) => format!("States of {device_url}"),
+            WatchTarget::Executions => "Current executions".to_string(),
+        }
+    }
+
+    async fn fetch(&self, executor: &CommandExecutor) -> anyhow::Result<Snapshot> {
+        Ok(match self {
+            WatchTarget::Devices => Snapshot::Devices(executor.list_devices().await?),
+            WatchTarget::DeviceStates(device_url) => {
+                Snapshot::DeviceStates(executor.get_device_states(device_url).await?)
+            }
+            WatchTarget::Executions => {
+                Snapshot::Executions(executor.get_current_executions().await?)
+            }
+        })
+    }
+}
+
+/// Events mostly carry values as strings, they are converted to the type the state already has
+fn state_value(existing: Option<&DeviceStateValue>, state: &EventState) -> DeviceStateValue {
+    let raw = match &state.value {
+        Value::String(s) => s.clone(),
+        other => {
+            return serde_json::from_value(other.clone())
+                .unwrap_or(DeviceStateValue::String(other.to_string()))
+        }
+    };
+
+    let typed = match (existing, state.state_type) {
+        (Some(DeviceStateValue::Int(_)), _) | (None, INT_STATE_TYPE) => {
+            raw.parse().ok().map(DeviceStateValue::Int)
+        }
+        (Some(DeviceStateValue::Boolean(_)), _) | (None, BOOLEAN_STATE_TYPE) => {
+            raw.parse().ok().map(DeviceStateValue::Boolean)
+        }
+        _ => None,
+    };
+    typed.unwrap_or(DeviceStateValue::String(raw))
+}
+
+/// Updates existing states and adds new ones
+pub(crate) fn apply_states(states: &mut Vec<DeviceState>, changes: &[EventState]) {
+    for change in changes {
+        match states.iter_mut().find(|s| s.name == change.name) {
+            Some(state) => state.value = state_value(Some(&state.value), change),
+            None => states.push(DeviceState {
+                value: state_value(None, change),
+                name: change.name.clone(),
+                state_type: change.state_type,
+            }),
+        }
+    }
+}
+
+impl Snapshot {
+    /// Applies state changes locally. Returns true if the snapshot has to be fetched again.
+    fn apply(&mut self, target: &WatchTarget, event: &DeviceEvent) -> bool {
+        if event.name.starts_with(EXECUTION_EVENT_PREFIX) {
+            return matches!(self, Snapshot::Executions(_));
+        }
+        if event.name != DEVICE_STATE_CHANGED_EVENT {
+            return false;
+        }
+        let Some(device_url) = event.device_url.as_deref() else {
+            return false;
+        };
+
+        match (self, target) {
+            (Snapshot::Devices(devices), _) => {
+                if let Some(device) = devices.iter_mut().find(|d| d.device_url == device_url) {
+                    apply_states(&mut device.states, &event.device_states);
+                }
+            }
+            (Snapshot::DeviceStates(states), WatchTarget::DeviceStates(watched))
+                if watched == device_url =>
+            {
+                apply_states(states, &event.device_states);
+            }
+            _ => {}
+        }
+        false
+    }
+
+    fn print(
+        &self,
+        previous: Option<&Snapshot>,
+        target: &WatchTarget,
+        format: &OutputFormat,
+    ) -> anyhow::Result<()> {
+        if !matches!(format, OutputFormat::Style(OutputStyle::Table)) {
+            return match self {
+                Snapshot::Devices(devices) => print_to_console(devices.clone(), format),
+                Snapshot::DeviceStates(states) => print_to_console(states.clone(), format),
+                Snapshot::Executions(execs) => print_to_console(execs.clone(), format),
+            };
+        }
+
+        let table = match (self, previous) {
+            (Snapshot::Devices(devices), Some(Snapshot::Devices(p))) => {
+                highlighted_device_tables(devices, Some(p))
+            }
+            (Snapshot::Devices(devices), _) => highlighted_device_tables(devices, None),
+            (Snapshot::DeviceStates(states), Some(Snapshot::DeviceStates(p))) => {
+                highlighted(states, Some(p))
+            }
+            (Snapshot::DeviceStates(states), _) => highlighted(states, None),
+            (Snapshot::Executions(execs), Some(Snapshot::Executions(p))) => {
+                highlighted(execs, Some(p))
+            }
+            (Snapshot::Executions(execs), _) => highlighted(execs, None),
+        };
+
+        let mut stdout = std::io::stdout().lock();
+        write!(
+            stdout,
+            "{CLEAR_SCREEN}{} - updated {} (Ctrl+C to quit)\n{table}\n",
+            target.title(),
+            Local::now().format("%H:%M:%S")
+        )?;
+        stdout.flush()?;
+        Ok(())
+    }
+}
+
+fn highlighted<T: TableRows>(current: &T, previous: Option<&T>) -> String {
+    build_highlighted_table(current, previous).to_string()
+}
+
+/// Prints the target and redraws it whenever events of the gateway change it, until interrupted
+pub(crate) async fn watch(
+    executor: &CommandExecutor,
+    target: WatchTarget,
+    format: &OutputFormat,
+) -> anyhow::Result<()> {
+    let mut source = EventSource::live(executor).await?;
+    let mut current = target.fetch(executor).await?;
+    current.print(None, &target, format)?;
+    let mut refetch = false;
+
+    loop {
+        let events = source.next_batch().await?.unwrap_or_default();
+        // Changes between the expired and the new listener are only visible in a fresh fetch
+        refetch |= source.take_renewed();
+
+        let mut next = current.clone();
+        for recorded in &events {
+            refetch |= next.apply(&target, &recorded.event);
+        }
+        if refetch {
+            match target.fetch(executor).await {
+                Ok(fetched) => {
+                    next = fetched;
+                    refetch = false;
+                }
+                Err(e) => warn!("Refreshing {} failed, retrying: {e:#}", target.title()),
+            }
+        }
+
+        if next != current {
+            next.print(Some(&current), &target, format)?;
+            current = next;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn event(value: Value) -> DeviceEvent {
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn states() -> Vec<DeviceState> {
+        serde_json::from_value(json!([
+            {"name": "core:ClosureState", "type": 1, "value": 0},
+            {"name": "core:MovingState", "type": 6, "value": false},
+            {"name": "core:OpenClosedState", "type": 3, "value": "open"}
+        ]))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_event_values_keep_state_types() {
+        let mut states = states();
+        apply_states(
+            &mut states,
+            &event(json!({
+                "name": "DeviceStateChangedEvent",
+                "deviceURL": "io://1/2",
+                "deviceStates": [
+                    {"name": "core:ClosureState", "type": 1, "value": "40"},
+                    {"name": "core:MovingState", "type": 6, "value": "true"},
+                    {"name": "core:OpenClosedState", "type": 3, "value": "closed"},
+                    {"name": "core:TargetClosureState", "type": 1, "value": "100"}
+                ]
+            }))
+            .device_states,
+        );
+
+        let values: Vec<_> = states.iter().map(|s| s.value.clone()).collect();
+        assert_eq!(
+            values,
+            vec![
+                DeviceStateValue::Int(40),
+                DeviceStateValue::Boolean(true),
+                DeviceStateValue::String("closed".to_string()),
+                DeviceStateValue::Int(100),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_only_watched_device_is_updated() {
+        let target = WatchTarget::DeviceStates("io://1/2".to_string());
+        let mut snapshot = Snapshot::DeviceStates(states());
+        let change = |url: &str| {
+            event(json!({
+                "name": "DeviceStateChangedEvent",
+                "deviceURL": url,
+                "deviceStates": [{"name": "core:ClosureState", "type": 1, "value": "70"}]
+            }))
+        };
+
+        assert!(!snapshot.apply(&target, &change("io://1/3")));
+        assert_eq!(snapshot, Snapshot::DeviceStates(states()));
+
+        snapshot.apply(&target, &change("io://1/2"));
+        assert_ne!(snapshot, Snapshot::DeviceStates(states()));
+    }
+
+    #[test]
+    fn test_execution_events_refetch_executions() {
+        let execution_event = event(json!({"name": "ExecutionStateChangedEvent", "execId": "1"}));
+
+        assert!(Snapshot::Executions(vec![]).apply(&WatchTarget::Executions, &execution_event));
+        assert!(!Snapshot::Devices(vec![]).apply(&WatchTarget::Devices, &execution_event));
+    }
+}
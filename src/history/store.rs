@@ -0,0 +1,454 @@
+use crate::config::common::get_config_folder;
+use crate::config::dotenv::{CliApiClientConfig, HistorySettings};
+use crate::events::fetch_device_events::{DeviceEvent, DEVICE_STATE_CHANGED_EVENT};
+use crate::utils::duration::parse_duration;
+use anyhow::Context;
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, TimeDelta, Utc};
+use log::warn;
+use rusqlite::{params, Connection};
+use serde::Serialize;
+use serde_json::Value;
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+const HISTORY_FILENAME: &str = "history.sqlite";
+const EXECUTION_STATE_CHANGED_EVENT: &str = "ExecutionStateChangedEvent";
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS history (
+        id INTEGER PRIMARY KEY,
+        timestamp TEXT NOT NULL,
+        device_url TEXT NOT NULL,
+        kind TEXT NOT NULL,
+        name TEXT NOT NULL,
+        value TEXT,
+        exec_id TEXT,
+        status TEXT
+    );
+    CREATE INDEX IF NOT EXISTS history_device_timestamp ON history (device_url, timestamp);
+    CREATE INDEX IF NOT EXISTS history_exec_id ON history (exec_id);
+";
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum HistoryKind {
+    /// A state reported by a `DeviceStateChangedEvent`
+    State,
+    /// A command sent by the CLI
+    Execution,
+}
+
+impl HistoryKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            HistoryKind::State => "state",
+            HistoryKind::Execution => "execution",
+        }
+    }
+}
+
+impl Display for HistoryKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub(crate) struct HistoryEntry {
+    pub timestamp: DateTime<Utc>,
+    pub device_url: String,
+    pub kind: HistoryKind,
+    /// State name, or the command of an execution
+    pub name: String,
+    /// State value, or the parameters of an execution
+    pub value: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exec_id: Option<String>,
+    /// Last execution state reported by the gateway, e.g. `COMPLETED`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<String>,
+}
+
+pub(crate) type HistoryEntries = Vec<HistoryEntry>;
+
+/// Filters of `somfy history`, newest entries first
+#[derive(Debug, Clone, Default)]
+pub(crate) struct HistoryQuery {
+    pub device_url: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+    pub name: Option<String>,
+    pub kind: Option<HistoryKind>,
+    pub limit: usize,
+}
+
+/// Accepts a duration into the past like `2h` or `7d`, a date like `2024-05-01` or a local time like `2024-05-01 18:00`
+pub(crate) fn parse_since(value: &str) -> Result<DateTime<Utc>, String> {
+    if let Ok(duration) = parse_duration(value) {
+        let delta = TimeDelta::from_std(duration).map_err(|e| e.to_string())?;
+        return Ok(Utc::now() - delta);
+    }
+    if let Ok(timestamp) = DateTime::parse_from_rfc3339(value) {
+        return Ok(timestamp.to_utc());
+    }
+    let local = NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M")
+        .or_else(|_| NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M"))
+        .or_else(|_| {
+            NaiveDate::parse_from_str(value, "%Y-%m-%d").map(|d| d.and_time(Default::default()))
+        })
+        .map_err(|_| {
+            format!("'{value}' is neither a duration like 2h nor a date like 2024-05-01")
+        })?;
+    local
+        .and_local_timezone(Local)
+        .earliest()
+        .map(|t| t.to_utc())
+        .ok_or(format!("'{value}' does not exist in the local timezone"))
+}
+
+fn value_to_string(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+fn insert(connection: &Connection, entry: &HistoryEntry) -> anyhow::Result<()> {
+    connection.execute(
+        "INSERT INTO history (timestamp, device_url, kind, name, value, exec_id, status)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        params![
+            entry.timestamp,
+            entry.device_url,
+            entry.kind.as_str(),
+            entry.name,
+            entry.value,
+            entry.exec_id,
+            entry.status
+        ],
+    )?;
+    Ok(())
+}
+
+/// Local SQLite database of state changes and executions
+pub struct HistoryStore {
+    connection: Mutex<Connection>,
+}
+
+impl HistoryStore {
+    pub(crate) fn location() -> PathBuf {
+        get_config_folder().join(HISTORY_FILENAME)
+    }
+
+    pub(crate) fn open(path: &Path) -> anyhow::Result<Self> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let connection =
+            Connection::open(path).context(format!("Failed to open history database {path:?}"))?;
+        connection
+            .execute_batch(SCHEMA)
+            .context(format!("Failed to create history tables in {path:?}"))?;
+        Ok(Self {
+            connection: Mutex::new(connection),
+        })
+    }
+
+    /// Opens the history at its default location and removes entries older than the retention period
+    pub(crate) fn from_settings(settings: &HistorySettings) -> anyhow::Result<Self> {
+        let store = HistoryStore::open(&HistoryStore::location())?;
+        if settings.retention_days > 0 {
+            store.prune(Utc::now() - TimeDelta::days(settings.retention_days.into()))?;
+        }
+        Ok(store)
+    }
+
+    /// The history enabled in the config file. Failing to open it only disables recording.
+    pub(crate) fn from_config(config: Option<&CliApiClientConfig>) -> Option<Self> {
+        let settings = config?.history.as_ref()?;
+        HistoryStore::from_settings(settings)
+            .inspect_err(|e| warn!("History disabled: {e:#}"))
+            .ok()
+    }
+
+    /// Removes all entries before `before` and returns their number
+    pub(crate) fn prune(&self, before: DateTime<Utc>) -> anyhow::Result<usize> {
+        let connection = self.connection.lock().unwrap();
+        Ok(connection.execute("DELETE FROM history WHERE timestamp < ?1", params![before])?)
+    }
+
+    /// Stores every state of `DeviceStateChangedEvent`s and the new state of executions sent by the CLI.
+    /// States are recorded at the time of their event, at `received` if the event has no timestamp.
+    pub(crate) fn record_events(
+        &self,
+        events: &[DeviceEvent],
+        received: DateTime<Utc>,
+    ) -> anyhow::Result<()> {
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction()?;
+        for event in events {
+            match (event.name.as_str(), event.device_url.as_ref()) {
+                (DEVICE_STATE_CHANGED_EVENT, Some(device_url)) => {
+                    let timestamp = event.timestamp().unwrap_or(received);
+                    for state in &event.device_states {
+                        insert(
+                            &transaction,
+                            &HistoryEntry {
+                                timestamp,
+                                device_url: device_url.clone(),
+                                kind: HistoryKind::State,
+                                name: state.name.clone(),
+                                value: Some(value_to_string(&state.value)),
+                                exec_id: None,
+                                status: None,
+                            },
+                        )?;
+                    }
+                }
+                (EXECUTION_STATE_CHANGED_EVENT, _) => {
+                    let exec_id = event.other.get("execId").map(value_to_string);
+                    let new_state = event.other.get("newState").map(value_to_string);
+                    if let (Some(exec_id), Some(new_state)) = (exec_id, new_state) {
+                        transaction.execute(
+                            "UPDATE history SET status = ?1 WHERE exec_id = ?2",
+                            params![new_state, exec_id],
+                        )?;
+                    }
+                }
+                _ => {}
+            }
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    pub(crate) fn record_execution(
+        &self,
+        device_urls: &[String],
+        command: &str,
+        parameters: &[String],
+        exec_id: &str,
+    ) -> anyhow::Result<()> {
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction()?;
+        let timestamp = Utc::now();
+        for device_url in device_urls {
+            insert(
+                &transaction,
+                &HistoryEntry {
+                    timestamp,
+                    device_url: device_url.clone(),
+                    kind: HistoryKind::Execution,
+                    name: command.to_string(),
+                    value: (!parameters.is_empty()).then(|| parameters.join(" ")),
+                    exec_id: Some(exec_id.to_string()),
+                    status: None,
+                },
+            )?;
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    pub(crate) fn query(&self, query: &HistoryQuery) -> anyhow::Result<HistoryEntries> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare(
+            "SELECT timestamp, device_url, kind, name, value, exec_id, status FROM history
+             WHERE (?1 IS NULL OR device_url = ?1)
+               AND (?2 IS NULL OR timestamp >= ?2)
+               AND (?3 IS NULL OR name = ?3)
+               AND (?4 IS NULL OR kind = ?4)
+             ORDER BY timestamp DESC, id DESC
+             LIMIT ?5",
+        )?;
+        let rows = statement.query_map(
+            params![
+                query.device_url,
+                query.since,
+                query.name,
+                query.kind.map(HistoryKind::as_str),
+                query.limit as i64
+            ],
+            |row| {
+                let kind: String = row.get(2)?;
+                Ok(HistoryEntry {
+                    timestamp: row.get(0)?,
+                    device_url: row.get(1)?,
+                    kind: match kind.as_str() {
+                        "execution" => HistoryKind::Execution,
+                        _ => HistoryKind::State,
+                    },
+                    name: row.get(3)?,
+                    value: row.get(4)?,
+                    exec_id: row.get(5)?,
+                    status: row.get(6)?,
+                })
+            },
+        )?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use tempfile::TempDir;
+
+    fn create_test_store() -> (HistoryStore, TempDir) {
+        let temp_dir = TempDir::new().unwrap();
+        let store = HistoryStore::open(&temp_dir.path().join("history.sqlite")).unwrap();
+        (store, temp_dir)
+    }
+
+    fn event(value: Value) -> DeviceEvent {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn test_states_are_recorded_at_the_time_of_their_event() {
+        let (store, _temp_dir) = create_test_store();
+        let emitted = DateTime::from_timestamp_millis(1_700_000_000_000).unwrap();
+        store
+            .record_events(
+                &[
+                    event(json!({
+                        "name": "DeviceStateChangedEvent", "deviceURL": "io://1/1",
+                        "timestamp": 1_700_000_000_000_i64,
+                        "deviceStates": [{"name": "core:ClosureState", "type": 1, "value": "40"}]
+                    })),
+                    event(json!({
+                        "name": "DeviceStateChangedEvent", "deviceURL": "io://1/2",
+                        "deviceStates": [{"name": "core:ClosureState", "type": 1, "value": "60"}]
+                    })),
+                ],
+                emitted + TimeDelta::seconds(30),
+            )
+            .unwrap();
+
+        let entries = store
+            .query(&HistoryQuery {
+                limit: 10,
+                ..Default::default()
+            })
+            .unwrap();
+
+        assert_eq!(entries[0].device_url, "io://1/2");
+        assert_eq!(entries[0].timestamp, emitted + TimeDelta::seconds(30));
+        assert_eq!(entries[1].timestamp, emitted);
+    }
+
+    #[test]
+    fn test_state_changes_and_executions_are_queried_newest_first() {
+        let (store, _temp_dir) = create_test_store();
+        let earlier = Utc::now() - TimeDelta::minutes(5);
+        store
+            .record_events(
+                &[event(json!({
+                    "name": "DeviceStateChangedEvent", "deviceURL": "io://1/1",
+                    "deviceStates": [{"name": "core:ClosureState", "type": 1, "value": "100"}]
+                }))],
+                earlier,
+            )
+            .unwrap();
+        store
+            .record_execution(&["io://1/1".to_string()], "open", &[], "exec-1")
+            .unwrap();
+        store
+            .record_events(
+                &[event(json!({
+                    "name": "ExecutionStateChangedEvent", "execId": "exec-1", "newState": "COMPLETED"
+                }))],
+                Utc::now(),
+            )
+            .unwrap();
+
+        let entries = store
+            .query(&HistoryQuery {
+                device_url: Some("io://1/1".to_string()),
+                limit: 10,
+                ..Default::default()
+            })
+            .unwrap();
+
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].kind, HistoryKind::Execution);
+        assert_eq!(entries[0].status.as_deref(), Some("COMPLETED"));
+        assert_eq!(entries[1].name, "core:ClosureState");
+        assert_eq!(entries[1].value.as_deref(), Some("100"));
+    }
+
+    #[test]
+    fn test_query_filters() {
+        let (store, _temp_dir) = create_test_store();
+        store
+            .record_execution(
+                &["io://1/1".to_string(), "io://1/2".to_string()],
+                "setClosure",
+                &["40".to_string()],
+                "exec-1",
+            )
+            .unwrap();
+
+        let query =
+            |query: HistoryQuery| store.query(&HistoryQuery { limit: 10, ..query }).unwrap();
+
+        assert_eq!(query(HistoryQuery::default()).len(), 2);
+        assert_eq!(
+            query(HistoryQuery {
+                device_url: Some("io://1/2".to_string()),
+                ..Default::default()
+            })[0]
+                .value
+                .as_deref(),
+            Some("40")
+        );
+        assert!(query(HistoryQuery {
+            kind: Some(HistoryKind::State),
+            ..Default::default()
+        })
+        .is_empty());
+        assert!(query(HistoryQuery {
+            since: Some(Utc::now() + TimeDelta::minutes(1)),
+            ..Default::default()
+        })
+        .is_empty());
+    }
+
+    #[test]
+    fn test_prune_removes_old_entries() {
+        let (store, _temp_dir) = create_test_store();
+        store
+            .record_execution(&["io://1/1".to_string()], "close", &[], "exec-1")
+            .unwrap();
+
+        assert_eq!(store.prune(Utc::now() - TimeDelta::days(1)).unwrap(), 0);
+        assert_eq!(store.prune(Utc::now() + TimeDelta::days(1)).unwrap(), 1);
+        let remaining = store
+            .query(&HistoryQuery {
+                limit: 10,
+                ..Default::default()
+            })
+            .unwrap();
+        assert!(remaining.is_empty());
+    }
+
+    #[test]
+    fn test_parse_since() {
+        let two_hours_ago = parse_since("2h").unwrap();
+        assert!(
+            (Utc::now() - two_hours_ago - TimeDelta::hours(2))
+                .num_seconds()
+                .abs()
+                <= 1
+        );
+
+        assert_eq!(
+            parse_since("2024-05-01T10:00:00Z").unwrap(),
+            "2024-05-01T10:00:00Z".parse::<DateTime<Utc>>().unwrap()
+        );
+        assert!(parse_since("2024-05-01").is_ok());
+        assert!(parse_since("2024-05-01 18:30").is_ok());
+        assert!(parse_since("yesterday").is_err());
+    }
+}
@@ -1,44 +1,140 @@
 pub(crate) mod commands {
+    pub(crate) mod batch;
+    pub(crate) mod capabilities;
     pub(crate) mod cli;
+    pub(crate) mod completions;
     pub(crate) mod dispatcher;
+    pub(crate) mod error;
+    pub(crate) mod execution_state;
     pub(crate) mod executor;
+    pub(crate) mod gateway_client;
+    pub(crate) mod shell;
 }
 pub(crate) mod utils {
+    pub(crate) mod duration;
     pub(crate) mod poller;
 }
+pub(crate) mod automation {
+    pub(crate) mod rule_engine;
+    pub(crate) mod scheduler;
+    pub(crate) mod sun;
+}
 pub(crate) mod output {
+    pub(crate) mod categories;
     pub(crate) mod formatter;
+    pub(crate) mod template;
 }
 
 pub(crate) mod config {
     pub(crate) mod alias;
+    pub(crate) mod certificate;
     pub(crate) mod common;
+    pub(crate) mod device_cache;
     pub(crate) mod dotenv;
     pub(crate) mod loader;
+    pub(crate) mod rules;
+    pub(crate) mod schedule;
+    pub(crate) mod secrets;
+    pub(crate) mod toml_file;
+}
+pub(crate) mod cloud {
+    pub(crate) mod client;
+}
+pub(crate) mod mock {
+    pub(crate) mod cloud;
+    pub(crate) mod gateway;
+    pub(crate) mod server;
+}
+pub(crate) mod events {
+    pub(crate) mod fetch_device_events;
+    pub(crate) mod recording;
+    pub(crate) mod stream;
+    pub(crate) mod watch;
+}
+
+pub(crate) mod history {
+    pub(crate) mod store;
+}
+
+pub(crate) mod tui {
+    pub(crate) mod app;
+    pub(crate) mod dashboard;
+    pub(crate) mod view;
 }
 
 use crate::commands::cli::Cli;
+use crate::commands::completions::{BIN_NAME, COMPLETE_VAR};
 use crate::commands::dispatcher::CommandDispatcher;
-use crate::config::dotenv::load_config_file;
-use crate::config::loader::merge_config_sources;
-use clap::Parser;
-use somfy_sdk::api_client::ApiClient;
+use crate::commands::error::{report, ErrorKind, WithErrorKind};
+use crate::commands::gateway_client::GatewayClient;
+use crate::config::alias::migrate_json_aliases;
+use crate::config::common::{migrate_legacy_config_folder, set_config_folder};
+use crate::config::device_cache::set_cache_profile;
+use crate::config::dotenv::{load_config_file, selected_profile};
+use crate::config::loader::{gateway_address, merge_config_sources};
+use crate::history::store::HistoryStore;
+use crate::output::formatter::OutputFormat;
+use crate::utils::poller::PollerConfig;
+use clap::{CommandFactory, Parser};
+use clap_complete::CompleteEnv;
+use log::warn;
+use std::process::ExitCode;
 
 #[tokio::main]
-async fn main() -> anyhow::Result<()> {
+async fn main() -> ExitCode {
+    // Answers completion requests of the registered shell script and exits
+    CompleteEnv::with_factory(Cli::command)
+        .var(COMPLETE_VAR)
+        .bin(BIN_NAME)
+        .complete();
+
     env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
 
     let cli_args = Cli::parse();
-    let config_file = load_config_file()?;
+    if let Some(config_dir) = &cli_args.config_dir {
+        set_config_folder(config_dir.clone());
+    }
+    if let Err(e) = migrate_legacy_config_folder() {
+        warn!("Failed to move the legacy config folder: {e:#}");
+    }
+    migrate_json_aliases();
+    set_cache_profile(selected_profile(cli_args.profile.as_deref()));
+    let format = match cli_args.output_format() {
+        Ok(format) => format,
+        Err(e) => return report(&e, &OutputFormat::Style(cli_args.output_style)),
+    };
 
-    let config = merge_config_sources(&cli_args, &config_file)?;
-
-    let api_client = ApiClient::new(config).await?;
-    let cmd_dispatcher = CommandDispatcher::from(api_client);
+    match run(cli_args, &format).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => report(&e, &format),
+    }
+}
 
-    cmd_dispatcher
-        .dispatch(cli_args.command, cli_args.output_style)
-        .await?;
+async fn run(cli_args: Cli, format: &OutputFormat) -> anyhow::Result<()> {
+    let profile = cli_args.profile.as_deref();
+    let cmd_dispatcher = if cli_args.command.requires_gateway() {
+        let config_file = load_config_file(profile).error_kind(ErrorKind::Config)?;
+        let config = merge_config_sources(&cli_args, &config_file).error_kind(ErrorKind::Config)?;
+        let pinned = config_file
+            .as_ref()
+            .and_then(|cfg| cfg.cert_fingerprint.as_deref());
+        let api_client = GatewayClient::new(config, pinned).await?;
+        let poller =
+            PollerConfig::from_settings(config_file.as_ref().and_then(|c| c.poller.as_ref()));
+        CommandDispatcher::from(api_client, HistoryStore::from_config(config_file.as_ref()))
+            .with_poller(poller)
+    } else {
+        CommandDispatcher::offline()
+    };
+    let gateway_address = if cli_args.command.requires_gateway_address() {
+        let config_file = load_config_file(profile).error_kind(ErrorKind::Config)?;
+        Some(gateway_address(&cli_args, &config_file).error_kind(ErrorKind::Config)?)
+    } else {
+        None
+    };
+    let cmd_dispatcher = cmd_dispatcher
+        .with_profile(cli_args.profile.clone())
+        .with_gateway_address(gateway_address);
 
-    Ok(())
+    cmd_dispatcher.dispatch(cli_args.command, format).await
 }
@@ -0,0 +1,93 @@
+use crate::cloud::client::{LocalToken, DEVMODE_SCOPE};
+
+const MOCK_SESSION: &str = "mock-session";
+
+/// Token management of the Somfy cloud. Logins with any user and a non-empty password succeed,
+/// activated tokens are accepted by the mock gateway.
+#[derive(Default)]
+pub(crate) struct MockCloud {
+    generated: Vec<String>,
+    active: Vec<(String, LocalToken)>,
+    next_id: u64,
+}
+
+impl MockCloud {
+    fn generate_id(&mut self) -> u64 {
+        self.next_id += 1;
+        self.next_id
+    }
+
+    /// The session cookie value, None if the credentials are rejected
+    pub(crate) fn login(&self, password: &str) -> Option<&'static str> {
+        (!password.is_empty()).then_some(MOCK_SESSION)
+    }
+
+    pub(crate) fn is_session(&self, session: &str) -> bool {
+        session == MOCK_SESSION
+    }
+
+    pub(crate) fn generate_token(&mut self) -> String {
+        let token = format!("{:020x}", self.generate_id());
+        self.generated.push(token.clone());
+        token
+    }
+
+    /// Activates a previously generated token and returns the request id
+    pub(crate) fn activate_token(
+        &mut self,
+        token: &str,
+        label: &str,
+        now_ms: i64,
+    ) -> Option<String> {
+        let index = self.generated.iter().position(|t| t == token)?;
+        let token = self.generated.remove(index);
+        let id = self.generate_id();
+        self.active.push((
+            token,
+            LocalToken {
+                label: label.to_string(),
+                uuid: format!("00000000-0000-4000-9000-{id:012x}"),
+                scope: DEVMODE_SCOPE.to_string(),
+                gateway_creation_time: Some(now_ms),
+                expiration_time: None,
+            },
+        ));
+        Some(format!("{id}"))
+    }
+
+    pub(crate) fn tokens(&self) -> Vec<LocalToken> {
+        self.active.iter().map(|(_, t)| t.clone()).collect()
+    }
+
+    /// Returns false if no token with this uuid is active
+    pub(crate) fn revoke_token(&mut self, uuid: &str) -> bool {
+        let before = self.active.len();
+        self.active.retain(|(_, t)| t.uuid != uuid);
+        self.active.len() != before
+    }
+
+    pub(crate) fn is_active(&self, token: &str) -> bool {
+        self.active.iter().any(|(t, _)| t == token)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_lifecycle() {
+        let mut cloud = MockCloud::default();
+        let token = cloud.generate_token();
+        assert!(!cloud.is_active(&token));
+
+        assert!(cloud.activate_token(&token, "laptop", 0).is_some());
+        assert!(cloud.activate_token(&token, "again", 0).is_none());
+        assert!(cloud.is_active(&token));
+
+        let uuid = cloud.tokens()[0].uuid.clone();
+        assert!(cloud.revoke_token(&uuid));
+        assert!(!cloud.is_active(&token));
+        assert!(!cloud.revoke_token(&uuid));
+    }
+}
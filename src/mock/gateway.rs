@@ -0,0 +1,718 @@
+use serde_json::{json, Value};
+use somfy_sdk::commands::types::{ActionGroup, Command};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::Duration;
+
+pub(crate) const GATEWAY_ID: &str = "0000-1111-2222";
+const DEFAULT_MY_POSITION: u8 = 50;
+/// How many finished executions are remembered, older ones are reported as unknown
+const FINISHED_EXECUTIONS_KEPT: usize = 1000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum MockError {
+    BadRequest(String),
+}
+
+#[derive(Debug, Clone)]
+pub(crate) enum MockDeviceKind {
+    /// Roller shutters, awnings and blinds. Venetian blinds additionally have an orientation.
+    Shutter {
+        closure: f64,
+        target: Option<f64>,
+        orientation: Option<u8>,
+    },
+    Light {
+        on: bool,
+    },
+    /// Static states, sensors do not accept any command
+    Sensor {
+        states: Vec<(String, Value)>,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct MockDevice {
+    pub url: String,
+    pub label: String,
+    pub controllable_name: String,
+    pub ui_class: String,
+    pub kind: MockDeviceKind,
+    /// Unavailable devices advertise their commands, but the gateway rejects executions for them
+    pub available: bool,
+}
+
+#[derive(Debug, Clone)]
+struct MockExecution {
+    id: String,
+    label: String,
+    start_time: i64,
+    action_group: ActionGroup,
+    pending_devices: HashSet<String>,
+}
+
+/// In-memory emulation of the parts of the TaHoma local API used by the CLI.
+/// Shutters move with `speed` percent per second, advanced by calling `tick`.
+pub(crate) struct MockGateway {
+    devices: Vec<MockDevice>,
+    executions: HashMap<String, MockExecution>,
+    /// Ids of finished executions, oldest first
+    finished_executions: VecDeque<String>,
+    listeners: HashMap<String, Vec<Value>>,
+    speed: f64,
+    next_id: u64,
+}
+
+fn shutter(url: &str, label: &str, controllable_name: &str, closure: f64) -> MockDevice {
+    MockDevice {
+        url: url.to_string(),
+        label: label.to_string(),
+        controllable_name: controllable_name.to_string(),
+        ui_class: "RollerShutter".to_string(),
+        kind: MockDeviceKind::Shutter {
+            closure,
+            target: None,
+            orientation: None,
+        },
+        available: true,
+    }
+}
+
+pub(crate) fn default_devices() -> Vec<MockDevice> {
+    let base = format!("io://{GATEWAY_ID}");
+    let mut venetian = shutter(
+        &format!("{base}/3"),
+        "Office Blind",
+        "io:ExteriorVenetianBlindIOComponent",
+        100.0,
+    );
+    venetian.ui_class = "ExteriorVenetianBlind".to_string();
+    venetian.kind = MockDeviceKind::Shutter {
+        closure: 100.0,
+        target: None,
+        orientation: Some(50),
+    };
+    let mut awning = shutter(
+        &format!("{base}/4"),
+        "Terrace Awning",
+        "io:HorizontalAwningIOComponent",
+        100.0,
+    );
+    awning.ui_class = "Awning".to_string();
+    let mut garage = shutter(
+        &format!("{base}/7"),
+        "Garage Door",
+        "io:GarageOpenerIOComponent",
+        100.0,
+    );
+    garage.ui_class = "GarageDoor".to_string();
+    garage.available = false;
+
+    vec![
+        shutter(
+            &format!("{base}/1"),
+            "Living Room",
+            "io:RollerShutterGenericIOComponent",
+            0.0,
+        ),
+        shutter(
+            &format!("{base}/2"),
+            "Kitchen",
+            "io:RollerShutterGenericIOComponent",
+            100.0,
+        ),
+        venetian,
+        awning,
+        MockDevice {
+            url: format!("{base}/5"),
+            label: "Hallway Light".to_string(),
+            controllable_name: "io:OnOffLightIOComponent".to_string(),
+            ui_class: "Light".to_string(),
+            kind: MockDeviceKind::Light { on: false },
+            available: true,
+        },
+        MockDevice {
+            url: format!("{base}/6"),
+            label: "Terrace Door".to_string(),
+            controllable_name: "io:SomfyContactIOSystemSensor".to_string(),
+            ui_class: "ContactSensor".to_string(),
+            kind: MockDeviceKind::Sensor {
+                states: vec![("core:ContactState".to_string(), json!("closed"))],
+            },
+            available: true,
+        },
+        garage,
+    ]
+}
+
+impl MockDevice {
+    fn supported_commands(&self) -> Vec<(&'static str, i64)> {
+        match &self.kind {
+            MockDeviceKind::Shutter { orientation, .. } => {
+                let mut commands = vec![
+                    ("open", 0),
+                    ("close", 0),
+                    ("stop", 0),
+                    ("my", 0),
+                    ("setClosure", 1),
+                    ("identify", 0),
+                ];
+                if orientation.is_some() {
+                    commands.push(("setOrientation", 1));
+                }
+                commands
+            }
+            MockDeviceKind::Light { .. } => vec![("on", 0), ("off", 0)],
+            MockDeviceKind::Sensor { .. } => vec![],
+        }
+    }
+
+    /// States as returned by `/setup/devices`, with typed values
+    fn states(&self) -> Vec<(String, i64, Value)> {
+        let status = if self.available {
+            "available"
+        } else {
+            "unavailable"
+        };
+        let mut states = vec![("core:StatusState".to_string(), 3, json!(status))];
+        match &self.kind {
+            MockDeviceKind::Shutter {
+                closure,
+                target,
+                orientation,
+            } => {
+                let closure = closure.round() as i64;
+                let open_closed = if closure == 100 { "closed" } else { "open" };
+                states.push(("core:ClosureState".to_string(), 1, json!(closure)));
+                states.push(("core:OpenClosedState".to_string(), 3, json!(open_closed)));
+                states.push(("core:MovingState".to_string(), 6, json!(target.is_some())));
+                states.push((
+                    "core:Memorized1PositionState".to_string(),
+                    1,
+                    json!(DEFAULT_MY_POSITION),
+                ));
+                if let Some(o) = orientation {
+                    states.push(("core:SlateOrientationState".to_string(), 1, json!(o)));
+                }
+            }
+            MockDeviceKind::Light { on } => {
+                let on_off = if *on { "on" } else { "off" };
+                states.push(("core:OnOffState".to_string(), 3, json!(on_off)));
+            }
+            MockDeviceKind::Sensor { states: s } => {
+                states.extend(s.iter().map(|(n, v)| (n.clone(), 3, v.clone())));
+            }
+        }
+        states
+    }
+
+    fn states_json(&self) -> Value {
+        Value::Array(
+            self.states()
+                .into_iter()
+                .map(|(name, t, value)| json!({"name": name, "type": t, "value": value}))
+                .collect(),
+        )
+    }
+
+    fn to_json(&self) -> Value {
+        let commands: Vec<Value> = self
+            .supported_commands()
+            .into_iter()
+            .map(|(name, nparams)| json!({"commandName": name, "nparams": nparams}))
+            .collect();
+        let state_names: Vec<Value> = self
+            .states()
+            .into_iter()
+            .map(|(name, _, _)| json!({"name": name}))
+            .collect();
+
+        json!({
+            "deviceURL": self.url,
+            "label": self.label,
+            "controllableName": self.controllable_name,
+            "subsystemId": 0,
+            "type": 1,
+            "available": self.available,
+            "synced": true,
+            "enabled": true,
+            "states": self.states_json(),
+            "attributes": [],
+            "definition": {
+                "uiClass": self.ui_class,
+                "widgetName": self.ui_class,
+                "type": 1,
+                "attributes": [],
+                "states": state_names,
+                "commands": commands,
+            }
+        })
+    }
+
+    /// States as transmitted in events, values are always strings there
+    fn state_changed_event(&self, names: &[&str]) -> Value {
+        let states: Vec<Value> = self
+            .states()
+            .into_iter()
+            .filter(|(name, _, _)| names.contains(&name.as_str()))
+            .map(|(name, t, value)| {
+                let value = match value {
+                    Value::String(s) => s,
+                    v => v.to_string(),
+                };
+                json!({"name": name, "type": t, "value": value})
+            })
+            .collect();
+
+        json!({
+            "name": "DeviceStateChangedEvent",
+            "deviceURL": self.url,
+            "deviceStates": states,
+        })
+    }
+}
+
+impl MockGateway {
+    pub(crate) fn new(devices: Vec<MockDevice>, speed: f64) -> Self {
+        Self {
+            devices,
+            executions: HashMap::new(),
+            finished_executions: VecDeque::new(),
+            listeners: HashMap::new(),
+            speed,
+            next_id: 1,
+        }
+    }
+
+    fn generate_id(&mut self) -> String {
+        let id = self.next_id;
+        self.next_id += 1;
+        format!("00000000-0000-4000-8000-{id:012x}")
+    }
+
+    fn emit(&mut self, event: Value, now_ms: i64) {
+        let mut event = event;
+        event["timestamp"] = json!(now_ms);
+        for queue in self.listeners.values_mut() {
+            queue.push(event.clone());
+        }
+    }
+
+    pub(crate) fn devices(&self) -> Value {
+        Value::Array(self.devices.iter().map(MockDevice::to_json).collect())
+    }
+
+    pub(crate) fn device(&self, url: &str) -> Option<Value> {
+        self.devices
+            .iter()
+            .find(|d| d.url == url)
+            .map(MockDevice::to_json)
+    }
+
+    pub(crate) fn device_states(&self, url: &str) -> Option<Value> {
+        self.devices
+            .iter()
+            .find(|d| d.url == url)
+            .map(MockDevice::states_json)
+    }
+
+    pub(crate) fn gateways(&self) -> Value {
+        json!([{
+            "gatewayId": GATEWAY_ID,
+            "connectivity": {"status": "OK", "protocolVersion": "mock"}
+        }])
+    }
+
+    fn validate(&self, action_group: &ActionGroup) -> Result<(), MockError> {
+        if action_group.actions.is_empty() {
+            return Err(MockError::BadRequest(
+                "No action in action group".to_string(),
+            ));
+        }
+        for action in &action_group.actions {
+            let device = self
+                .devices
+                .iter()
+                .find(|d| d.url == action.device_url)
+                .ok_or(MockError::BadRequest(format!(
+                    "Unknown device {}",
+                    action.device_url
+                )))?;
+            if !device.available {
+                return Err(MockError::BadRequest(format!(
+                    "Device {} is not available",
+                    device.url
+                )));
+            }
+            for command in &action.commands {
+                let supported = device.supported_commands();
+                let (_, nparams) = supported
+                    .iter()
+                    .find(|(name, _)| *name == command.name)
+                    .ok_or(MockError::BadRequest(format!(
+                        "Command {} is not supported by {}",
+                        command.name, device.url
+                    )))?;
+                if command.parameters.len() as i64 != *nparams {
+                    return Err(MockError::BadRequest(format!(
+                        "Command {} expects {nparams} parameter(s)",
+                        command.name
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Starts an execution, returns its id
+    pub(crate) fn apply(
+        &mut self,
+        action_group: ActionGroup,
+        now_ms: i64,
+    ) -> Result<String, MockError> {
+        self.validate(&action_group)?;
+
+        let id = self.generate_id();
+        let mut pending_devices = HashSet::new();
+        let mut changed = Vec::new();
+        for action in &action_group.actions {
+            let Some(device) = self.devices.iter_mut().find(|d| d.url == action.device_url) else {
+                continue;
+            };
+            for command in &action.commands {
+                if apply_command(device, command)? {
+                    pending_devices.insert(device.url.clone());
+                }
+            }
+            changed.push(device.clone());
+        }
+
+        let label = action_group.label.clone().unwrap_or_default();
+        self.emit(
+            json!({"name": "ExecutionRegisteredEvent", "execId": id, "label": label}),
+            now_ms,
+        );
+        self.emit(
+            json!({"name": "ExecutionStateChangedEvent", "execId": id, "oldState": "INITIALIZED", "newState": "IN_PROGRESS"}),
+            now_ms,
+        );
+        for device in changed {
+            let event = device.state_changed_event(&[
+                "core:MovingState",
+                "core:OnOffState",
+                "core:SlateOrientationState",
+            ]);
+            self.emit(event, now_ms);
+        }
+
+        self.executions.insert(
+            id.clone(),
+            MockExecution {
+                id: id.clone(),
+                label,
+                start_time: now_ms,
+                action_group,
+                pending_devices,
+            },
+        );
+
+        Ok(id)
+    }
+
+    fn execution_json(execution: &MockExecution) -> Value {
+        json!({
+            "owner": "mock",
+            "id": execution.id,
+            "executionType": "Immediate execution",
+            "executionSubType": "MANUAL_CONTROL",
+            "description": format!("Execution : {}", execution.label),
+            "startTime": execution.start_time,
+            "actionGroup": execution.action_group,
+            "state": "IN_PROGRESS",
+        })
+    }
+
+    pub(crate) fn current_executions(&self) -> Value {
+        Value::Array(self.executions.values().map(Self::execution_json).collect())
+    }
+
+    /// Mirrors the gateway: `null` for finished executions, `[]` for unknown ones
+    pub(crate) fn execution(&self, id: &str) -> Value {
+        match self.executions.get(id) {
+            Some(execution) => Self::execution_json(execution),
+            None if self.finished_executions.iter().any(|f| f == id) => Value::Null,
+            None => json!([]),
+        }
+    }
+
+    pub(crate) fn register_listener(&mut self) -> String {
+        let id = self.generate_id();
+        self.listeners.insert(id.clone(), Vec::new());
+        id
+    }
+
+    pub(crate) fn fetch_events(&mut self, listener_id: &str) -> Result<Value, MockError> {
+        let queue = self
+            .listeners
+            .get_mut(listener_id)
+            .ok_or(MockError::BadRequest(
+                "No registered event listener".to_string(),
+            ))?;
+        Ok(Value::Array(std::mem::take(queue)))
+    }
+
+    pub(crate) fn unregister_listener(&mut self, listener_id: &str) {
+        self.listeners.remove(listener_id);
+    }
+
+    /// Moves all shutters by the time elapsed and completes executions whose devices arrived
+    pub(crate) fn tick(&mut self, elapsed: Duration, now_ms: i64) {
+        let step = self.speed * elapsed.as_secs_f64();
+        let mut arrived = Vec::new();
+        for device in self.devices.iter_mut() {
+            if let MockDeviceKind::Shutter {
+                closure, target, ..
+            } = &mut device.kind
+            {
+                let Some(t) = *target else { continue };
+                if (t - *closure).abs() <= step {
+                    *closure = t;
+                    *target = None;
+                    arrived.push(device.clone());
+                } else {
+                    *closure += step.copysign(t - *closure);
+                }
+            }
+        }
+
+        for device in &arrived {
+            let event = device.state_changed_event(&[
+                "core:ClosureState",
+                "core:OpenClosedState",
+                "core:MovingState",
+            ]);
+            self.emit(event, now_ms);
+        }
+
+        let moving: HashSet<String> = self
+            .devices
+            .iter()
+            .filter(|d| {
+                matches!(
+                    d.kind,
+                    MockDeviceKind::Shutter {
+                        target: Some(_),
+                        ..
+                    }
+                )
+            })
+            .map(|d| d.url.clone())
+            .collect();
+        let completed: Vec<String> = self
+            .executions
+            .values()
+            .filter(|e| e.pending_devices.is_disjoint(&moving))
+            .map(|e| e.id.clone())
+            .collect();
+        for id in completed {
+            self.executions.remove(&id);
+            if self.finished_executions.len() == FINISHED_EXECUTIONS_KEPT {
+                self.finished_executions.pop_front();
+            }
+            self.finished_executions.push_back(id.clone());
+            self.emit(
+                json!({"name": "ExecutionStateChangedEvent", "execId": id, "oldState": "IN_PROGRESS", "newState": "COMPLETED"}),
+                now_ms,
+            );
+        }
+    }
+}
+
+/// Applies a single command, returns whether the device started moving
+fn apply_command(device: &mut MockDevice, command: &Command) -> Result<bool, MockError> {
+    let param = |idx: usize| -> Result<f64, MockError> {
+        command
+            .parameters
+            .get(idx)
+            .and_then(|p| p.parse::<f64>().ok())
+            .filter(|p| (0.0..=100.0).contains(p))
+            .ok_or(MockError::BadRequest(format!(
+                "Invalid parameter for command {}",
+                command.name
+            )))
+    };
+
+    match (&mut device.kind, command.name.as_str()) {
+        (MockDeviceKind::Shutter { orientation, .. }, "setOrientation") => {
+            *orientation = Some(param(0)? as u8);
+            Ok(false)
+        }
+        (
+            MockDeviceKind::Shutter {
+                closure, target, ..
+            },
+            name,
+        ) => {
+            let new_target = match name {
+                "open" => Some(0.0),
+                "close" => Some(100.0),
+                "my" => Some(DEFAULT_MY_POSITION as f64),
+                "setClosure" => Some(param(0)?),
+                "stop" => None,
+                _ => *target,
+            };
+            *target = new_target.filter(|t| (t - *closure).abs() > f64::EPSILON);
+            Ok(target.is_some())
+        }
+        (MockDeviceKind::Light { on }, "on") => {
+            *on = true;
+            Ok(false)
+        }
+        (MockDeviceKind::Light { on }, "off") => {
+            *on = false;
+            Ok(false)
+        }
+        _ => Ok(false),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use somfy_sdk::commands::types::Action;
+
+    fn action_group(device_url: &str, name: &str, parameters: Vec<&str>) -> ActionGroup {
+        ActionGroup {
+            label: Some("test".to_string()),
+            actions: vec![Action {
+                device_url: device_url.to_string(),
+                commands: vec![Command {
+                    name: name.to_string(),
+                    parameters: parameters.into_iter().map(String::from).collect(),
+                }],
+            }],
+        }
+    }
+
+    fn closure(gateway: &MockGateway, url: &str) -> Value {
+        gateway.device_states(url).unwrap()[1]["value"].clone()
+    }
+
+    #[test]
+    fn test_shutter_moves_over_time_and_execution_completes() {
+        let mut gateway = MockGateway::new(default_devices(), 10.0);
+        let url = "io://0000-1111-2222/2";
+
+        let id = gateway
+            .apply(action_group(url, "setClosure", vec!["50"]), 0)
+            .unwrap();
+        assert_eq!(gateway.execution(&id)["state"], "IN_PROGRESS");
+
+        gateway.tick(Duration::from_secs(2), 2000);
+        assert_eq!(closure(&gateway, url), json!(80));
+        assert_eq!(gateway.current_executions().as_array().unwrap().len(), 1);
+
+        gateway.tick(Duration::from_secs(5), 7000);
+        assert_eq!(closure(&gateway, url), json!(50));
+        assert_eq!(gateway.execution(&id), Value::Null);
+        assert!(gateway.current_executions().as_array().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_unknown_execution_returns_empty_array() {
+        let gateway = MockGateway::new(default_devices(), 10.0);
+        assert_eq!(gateway.execution("unknown"), json!([]));
+    }
+
+    #[test]
+    fn test_finished_executions_are_bounded() {
+        let mut gateway = MockGateway::new(default_devices(), 1000.0);
+        let url = "io://0000-1111-2222/1";
+        let mut ids = vec![];
+        for i in 0..=FINISHED_EXECUTIONS_KEPT {
+            let id = gateway
+                .apply(
+                    action_group(url, "setClosure", vec![&(i % 2 * 100).to_string()]),
+                    0,
+                )
+                .unwrap();
+            gateway.tick(Duration::from_secs(1), 0);
+            ids.push(id);
+        }
+
+        assert_eq!(gateway.finished_executions.len(), FINISHED_EXECUTIONS_KEPT);
+        assert_eq!(gateway.execution(&ids[0]), json!([]));
+        assert_eq!(gateway.execution(ids.last().unwrap()), Value::Null);
+    }
+
+    #[test]
+    fn test_stop_halts_movement() {
+        let mut gateway = MockGateway::new(default_devices(), 10.0);
+        let url = "io://0000-1111-2222/1";
+
+        gateway
+            .apply(action_group(url, "close", vec![]), 0)
+            .unwrap();
+        gateway.tick(Duration::from_secs(3), 3000);
+        gateway
+            .apply(action_group(url, "stop", vec![]), 3000)
+            .unwrap();
+        gateway.tick(Duration::from_secs(3), 6000);
+
+        assert_eq!(closure(&gateway, url), json!(30));
+    }
+
+    #[test]
+    fn test_unsupported_command_is_rejected() {
+        let mut gateway = MockGateway::new(default_devices(), 10.0);
+
+        let err = gateway
+            .apply(
+                action_group("io://0000-1111-2222/6", "setClosure", vec!["50"]),
+                0,
+            )
+            .unwrap_err();
+        assert!(matches!(err, MockError::BadRequest(_)));
+        assert!(gateway
+            .apply(action_group("io://unknown", "open", vec![]), 0)
+            .is_err());
+        assert!(gateway
+            .apply(action_group("io://0000-1111-2222/7", "open", vec![]), 0)
+            .is_err());
+    }
+
+    #[test]
+    fn test_listener_receives_state_changes() {
+        let mut gateway = MockGateway::new(default_devices(), 100.0);
+        let url = "io://0000-1111-2222/1";
+        let listener = gateway.register_listener();
+
+        gateway
+            .apply(action_group(url, "close", vec![]), 0)
+            .unwrap();
+        gateway.tick(Duration::from_secs(1), 1000);
+
+        let events = gateway.fetch_events(&listener).unwrap();
+        let names: Vec<&str> = events
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|e| e["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                "ExecutionRegisteredEvent",
+                "ExecutionStateChangedEvent",
+                "DeviceStateChangedEvent",
+                "DeviceStateChangedEvent",
+                "ExecutionStateChangedEvent"
+            ]
+        );
+        assert!(gateway
+            .fetch_events(&listener)
+            .unwrap()
+            .as_array()
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -0,0 +1,339 @@
+use crate::cloud::client::SESSION_COOKIE;
+use crate::mock::cloud::MockCloud;
+use crate::mock::gateway::{default_devices, MockError, MockGateway, GATEWAY_ID};
+use axum::extract::{Path, Request, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{delete, get, post};
+use axum::{Form, Json, Router};
+use chrono::Utc;
+use log::info;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use somfy_sdk::commands::types::ActionGroup;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::net::TcpListener;
+
+const API_PREFIX: &str = "/enduser-mobile-web/1/enduserAPI";
+const CLOUD_PREFIX: &str = "/enduser-mobile-web/enduserAPI";
+const TICK_INTERVAL: Duration = Duration::from_millis(200);
+
+type SharedGateway = Arc<Mutex<MockGateway>>;
+type SharedCloud = Arc<Mutex<MockCloud>>;
+
+/// Tokens accepted by the local API
+#[derive(Clone)]
+struct Authorization {
+    token: Option<String>,
+    cloud: SharedCloud,
+}
+
+pub(crate) struct MockServerConfig {
+    pub bind: String,
+    pub port: u16,
+    /// Bearer token requests have to present, any token is accepted if unset
+    pub token: Option<String>,
+    /// Shutter movement in percent per second
+    pub speed: f64,
+}
+
+/// Shutter speeds have to be positive, durations are derived from them
+pub(crate) fn parse_speed(value: &str) -> Result<f64, String> {
+    value
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|speed| speed.is_finite() && *speed > 0.0)
+        .ok_or(format!("'{value}' is not a positive speed, e.g. 10"))
+}
+
+impl IntoResponse for MockError {
+    fn into_response(self) -> Response {
+        match self {
+            MockError::BadRequest(error) => (
+                StatusCode::BAD_REQUEST,
+                Json(json!({"errorCode": "UNSPECIFIED_ERROR", "error": error})),
+            )
+                .into_response(),
+        }
+    }
+}
+
+fn not_found() -> Response {
+    (
+        StatusCode::NOT_FOUND,
+        Json(json!({"errorCode": "RESOURCE_ACCESS_DENIED", "error": "Unknown object"})),
+    )
+        .into_response()
+}
+
+fn now_ms() -> i64 {
+    Utc::now().timestamp_millis()
+}
+
+async fn devices(State(gw): State<SharedGateway>) -> Json<Value> {
+    Json(gw.lock().unwrap().devices())
+}
+
+async fn device(State(gw): State<SharedGateway>, Path(url): Path<String>) -> Response {
+    match gw.lock().unwrap().device(&url) {
+        Some(device) => Json(device).into_response(),
+        None => not_found(),
+    }
+}
+
+async fn device_states(State(gw): State<SharedGateway>, Path(url): Path<String>) -> Response {
+    match gw.lock().unwrap().device_states(&url) {
+        Some(states) => Json(states).into_response(),
+        None => not_found(),
+    }
+}
+
+async fn gateways(State(gw): State<SharedGateway>) -> Json<Value> {
+    Json(gw.lock().unwrap().gateways())
+}
+
+async fn api_version() -> Json<Value> {
+    Json(json!({"protocolVersion": "mock"}))
+}
+
+async fn apply(
+    State(gw): State<SharedGateway>,
+    Json(action_group): Json<ActionGroup>,
+) -> Result<Json<Value>, MockError> {
+    let exec_id = gw.lock().unwrap().apply(action_group, now_ms())?;
+    Ok(Json(json!({"execId": exec_id})))
+}
+
+async fn current_executions(State(gw): State<SharedGateway>) -> Json<Value> {
+    Json(gw.lock().unwrap().current_executions())
+}
+
+async fn execution(State(gw): State<SharedGateway>, Path(id): Path<String>) -> Json<Value> {
+    Json(gw.lock().unwrap().execution(&id))
+}
+
+async fn register_listener(State(gw): State<SharedGateway>) -> Json<Value> {
+    Json(json!({"id": gw.lock().unwrap().register_listener()}))
+}
+
+async fn fetch_events(
+    State(gw): State<SharedGateway>,
+    Path(id): Path<String>,
+) -> Result<Json<Value>, MockError> {
+    Ok(Json(gw.lock().unwrap().fetch_events(&id)?))
+}
+
+async fn unregister_listener(State(gw): State<SharedGateway>, Path(id): Path<String>) {
+    gw.lock().unwrap().unregister_listener(&id);
+}
+
+fn not_authenticated() -> Response {
+    (
+        StatusCode::UNAUTHORIZED,
+        Json(json!({"errorCode": "RESOURCE_ACCESS_DENIED", "error": "Not authenticated"})),
+    )
+        .into_response()
+}
+
+/// Without a configured token any token is accepted, otherwise also the ones activated in the cloud
+async fn require_token(
+    State(auth): State<Authorization>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let Some(token) = auth.token else {
+        return next.run(request).await;
+    };
+
+    let authorized = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Bearer "))
+        .is_some_and(|t| t == token || auth.cloud.lock().unwrap().is_active(t));
+
+    if authorized {
+        next.run(request).await
+    } else {
+        not_authenticated()
+    }
+}
+
+async fn require_session(
+    State(cloud): State<SharedCloud>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let session = request
+        .headers()
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|h| h.to_str().ok())
+        .flat_map(|h| h.split(';'))
+        .filter_map(|cookie| cookie.trim().strip_prefix(SESSION_COOKIE))
+        .filter_map(|cookie| cookie.strip_prefix('='))
+        .any(|session| cloud.lock().unwrap().is_session(session));
+
+    if session {
+        next.run(request).await
+    } else {
+        not_authenticated()
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Credentials {
+    user_password: String,
+}
+
+async fn login(State(cloud): State<SharedCloud>, Form(credentials): Form<Credentials>) -> Response {
+    let Some(session) = cloud.lock().unwrap().login(&credentials.user_password) else {
+        return (
+            StatusCode::UNAUTHORIZED,
+            Json(json!({"errorCode": "AUTHENTICATION_ERROR", "error": "Bad credentials"})),
+        )
+            .into_response();
+    };
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        header::SET_COOKIE,
+        format!("{SESSION_COOKIE}={session}; Path=/; HttpOnly")
+            .parse()
+            .unwrap(),
+    );
+    (headers, Json(json!({"success": true, "roles": []}))).into_response()
+}
+
+async fn generate_token(State(cloud): State<SharedCloud>, Path(gateway): Path<String>) -> Response {
+    if gateway != GATEWAY_ID {
+        return not_found();
+    }
+    Json(json!({"token": cloud.lock().unwrap().generate_token()})).into_response()
+}
+
+#[derive(Deserialize)]
+struct Activation {
+    label: String,
+    token: String,
+}
+
+async fn activate_token(
+    State(cloud): State<SharedCloud>,
+    Path(gateway): Path<String>,
+    Json(activation): Json<Activation>,
+) -> Response {
+    if gateway != GATEWAY_ID {
+        return not_found();
+    }
+    let request_id =
+        cloud
+            .lock()
+            .unwrap()
+            .activate_token(&activation.token, &activation.label, now_ms());
+    match request_id {
+        Some(request_id) => Json(json!({"requestId": request_id})).into_response(),
+        None => MockError::BadRequest("Unknown token".to_string()).into_response(),
+    }
+}
+
+async fn list_tokens(State(cloud): State<SharedCloud>, Path(gateway): Path<String>) -> Response {
+    if gateway != GATEWAY_ID {
+        return not_found();
+    }
+    Json(cloud.lock().unwrap().tokens()).into_response()
+}
+
+async fn revoke_token(
+    State(cloud): State<SharedCloud>,
+    Path((gateway, uuid)): Path<(String, String)>,
+) -> Response {
+    if gateway != GATEWAY_ID || !cloud.lock().unwrap().revoke_token(&uuid) {
+        return not_found();
+    }
+    StatusCode::OK.into_response()
+}
+
+/// Token management of the Somfy cloud, for testing `somfy token`
+fn cloud_router(cloud: SharedCloud) -> Router {
+    let tokens = Router::new()
+        .route("/config/{gateway}/local/tokens", post(activate_token))
+        .route(
+            "/config/{gateway}/local/tokens/generate",
+            get(generate_token),
+        )
+        .route("/config/{gateway}/local/tokens/devmode", get(list_tokens))
+        .route(
+            "/config/{gateway}/local/tokens/{uuid}",
+            delete(revoke_token),
+        )
+        .route_layer(middleware::from_fn_with_state(
+            cloud.clone(),
+            require_session,
+        ));
+
+    Router::new()
+        .route("/login", post(login))
+        .merge(tokens)
+        .with_state(cloud)
+}
+
+pub(crate) fn router(gateway: SharedGateway, token: Option<String>) -> Router {
+    let api = Router::new()
+        .route("/apiVersion", get(api_version))
+        .route("/setup/gateways", get(gateways))
+        .route("/setup/devices", get(devices))
+        .route("/setup/devices/{url}", get(device))
+        .route("/setup/devices/{url}/states", get(device_states))
+        .route("/exec/apply", post(apply))
+        .route("/exec/current", get(current_executions))
+        .route("/exec/current/{id}", get(execution))
+        .route("/events/register", post(register_listener))
+        .route("/events/{id}/fetch", post(fetch_events))
+        .route("/events/{id}/unregister", post(unregister_listener))
+        .with_state(gateway);
+    let cloud = SharedCloud::default();
+    let auth = Authorization {
+        token,
+        cloud: cloud.clone(),
+    };
+
+    Router::new()
+        .nest(
+            API_PREFIX,
+            api.layer(middleware::from_fn_with_state(auth, require_token)),
+        )
+        .nest(CLOUD_PREFIX, cloud_router(cloud))
+}
+
+/// Serves the mock gateway over plain HTTP until the process is terminated
+pub(crate) async fn serve(config: MockServerConfig) -> anyhow::Result<()> {
+    let gateway = Arc::new(Mutex::new(MockGateway::new(
+        default_devices(),
+        config.speed,
+    )));
+
+    let ticker = gateway.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(TICK_INTERVAL);
+        let mut last = tokio::time::Instant::now();
+        loop {
+            let now = interval.tick().await;
+            ticker.lock().unwrap().tick(now - last, now_ms());
+            last = now;
+        }
+    });
+
+    let listener = TcpListener::bind((config.bind.as_str(), config.port)).await?;
+    let addr: SocketAddr = listener.local_addr()?;
+    println!("Mock gateway listening on http://{addr}");
+    // The address is the only line on stdout, tests read the port from it
+    info!("Mock cloud API for 'somfy token' at http://{addr}{CLOUD_PREFIX}");
+
+    axum::serve(listener, router(gateway, config.token)).await?;
+    Ok(())
+}
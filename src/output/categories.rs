@@ -0,0 +1,318 @@
+use crate::output::formatter::{
+    build_highlighted_table, build_table, Records, Searchable, TableRows,
+};
+use somfy_sdk::commands::types::Device;
+use std::collections::BTreeMap;
+use tabled::settings::object::Columns;
+use tabled::settings::Alignment;
+use tabled::Table;
+
+const MOVING_STATE: &str = "core:MovingState";
+
+/// State shown as a column of a category, numeric columns are aligned right
+struct StateColumn {
+    header: &'static str,
+    state: &'static str,
+    numeric: bool,
+}
+
+const fn column(header: &'static str, state: &'static str, numeric: bool) -> StateColumn {
+    StateColumn {
+        header,
+        state,
+        numeric,
+    }
+}
+
+const STATUS: StateColumn = column("Status", "core:StatusState", false);
+const OPEN_CLOSED: StateColumn = column("Open/Close", "core:OpenClosedState", false);
+const CLOSURE: StateColumn = column("Closure (%)", "core:ClosureState", true);
+const MY_POSITION: StateColumn = column("'My' position (%)", "core:Memorized1PositionState", true);
+const MOVING: StateColumn = column("Is Moving?", MOVING_STATE, false);
+const ON_OFF: StateColumn = column("On/Off", "core:OnOffState", false);
+const TEMPERATURE: StateColumn = column("Temperature (°C)", "core:TemperatureState", true);
+
+const ROLLER_SHUTTER_COLUMNS: &[StateColumn] = &[STATUS, OPEN_CLOSED, CLOSURE, MY_POSITION, MOVING];
+const VENETIAN_BLIND_COLUMNS: &[StateColumn] = &[
+    STATUS,
+    OPEN_CLOSED,
+    CLOSURE,
+    column("Tilt (%)", "core:SlateOrientationState", true),
+    MY_POSITION,
+    column("'My' tilt (%)", "core:Memorized1OrientationState", true),
+    MOVING,
+];
+const AWNING_COLUMNS: &[StateColumn] = &[
+    STATUS,
+    OPEN_CLOSED,
+    column("Deployment (%)", "core:DeploymentState", true),
+    CLOSURE,
+    MY_POSITION,
+    MOVING,
+];
+const LIGHT_COLUMNS: &[StateColumn] = &[
+    STATUS,
+    ON_OFF,
+    column("Intensity (%)", "core:LightIntensityState", true),
+];
+const SENSOR_COLUMNS: &[StateColumn] = &[
+    STATUS,
+    column("Contact", "core:ContactState", false),
+    column("Occupancy", "core:OccupancyState", false),
+    column("Smoke", "core:SmokeState", false),
+    TEMPERATURE,
+    column("Humidity (%)", "core:RelativeHumidityState", true),
+    column("Luminance (lx)", "core:LuminanceState", true),
+    column("Wind speed", "core:WindSpeedState", true),
+    column("Battery", "core:BatteryState", false),
+];
+const HEATING_COLUMNS: &[StateColumn] = &[
+    STATUS,
+    ON_OFF,
+    column("Mode", "core:OperatingModeState", false),
+    TEMPERATURE,
+    column("Target (°C)", "core:TargetTemperatureState", true),
+];
+const OTHER_COLUMNS: &[StateColumn] = &[STATUS, OPEN_CLOSED, ON_OFF];
+
+/// Kind of device, decides the section and the state columns of a device in `ls`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum DeviceCategory {
+    RollerShutter,
+    VenetianBlind,
+    Awning,
+    Light,
+    Sensor,
+    Heating,
+    Other,
+}
+
+impl DeviceCategory {
+    /// By the controllable name, e.g. `io:RollerShutterGenericIOComponent`, or else by the states the device has
+    pub(crate) fn of(device: &Device) -> Self {
+        let name = device.controllable_name.to_lowercase();
+        let is = |parts: &[&str]| parts.iter().any(|part| name.contains(part));
+        let has = |state: &str| device.states.find_by_name(state).is_some();
+        if is(&["sensor"]) {
+            DeviceCategory::Sensor
+        } else if is(&["heat", "thermostat", "valve", "radiator"]) {
+            DeviceCategory::Heating
+        } else if is(&["light"]) {
+            DeviceCategory::Light
+        } else if is(&["venetian"]) || has("core:SlateOrientationState") {
+            DeviceCategory::VenetianBlind
+        } else if is(&["awning", "pergola"]) || has("core:DeploymentState") {
+            DeviceCategory::Awning
+        } else if is(&["shutter", "screen", "window"]) || has(CLOSURE.state) {
+            DeviceCategory::RollerShutter
+        } else if has("core:TargetTemperatureState") {
+            DeviceCategory::Heating
+        } else {
+            DeviceCategory::Other
+        }
+    }
+
+    pub(crate) fn title(self) -> &'static str {
+        match self {
+            DeviceCategory::RollerShutter => "Roller shutters",
+            DeviceCategory::VenetianBlind => "Venetian blinds",
+            DeviceCategory::Awning => "Awnings",
+            DeviceCategory::Light => "Lights",
+            DeviceCategory::Sensor => "Sensors",
+            DeviceCategory::Heating => "Heating",
+            DeviceCategory::Other => "Other devices",
+        }
+    }
+
+    fn columns(self) -> &'static [StateColumn] {
+        match self {
+            DeviceCategory::RollerShutter => ROLLER_SHUTTER_COLUMNS,
+            DeviceCategory::VenetianBlind => VENETIAN_BLIND_COLUMNS,
+            DeviceCategory::Awning => AWNING_COLUMNS,
+            DeviceCategory::Light => LIGHT_COLUMNS,
+            DeviceCategory::Sensor => SENSOR_COLUMNS,
+            DeviceCategory::Heating => HEATING_COLUMNS,
+            DeviceCategory::Other => OTHER_COLUMNS,
+        }
+    }
+}
+
+/// Devices of one category, rendered as a table of its own
+pub(crate) struct DeviceSection {
+    pub category: DeviceCategory,
+    pub devices: Vec<Device>,
+}
+
+impl DeviceSection {
+    /// Columns of the category that at least one of the devices reports
+    fn columns(&self) -> Vec<&'static StateColumn> {
+        self.category
+            .columns()
+            .iter()
+            .filter(|column| {
+                self.devices
+                    .iter()
+                    .any(|device| device.states.find_by_name(column.state).is_some())
+            })
+            .collect()
+    }
+}
+
+/// Sections in the order of the categories, devices keep their order within a section
+pub(crate) fn device_sections(devices: &[Device]) -> Vec<DeviceSection> {
+    let mut by_category: BTreeMap<DeviceCategory, Vec<Device>> = BTreeMap::new();
+    for device in devices {
+        by_category
+            .entry(DeviceCategory::of(device))
+            .or_default()
+            .push(device.clone());
+    }
+    by_category
+        .into_iter()
+        .map(|(category, devices)| DeviceSection { category, devices })
+        .collect()
+}
+
+impl TableRows for DeviceSection {
+    fn table_rows(&self) -> Records {
+        let columns = self.columns();
+        let mut header = vec![
+            "Label".to_string(),
+            "Device URL".to_string(),
+            "Device Type".to_string(),
+        ];
+        header.extend(columns.iter().map(|column| column.header.to_string()));
+        let mut rows = vec![header];
+
+        for device in &self.devices {
+            let mut row = vec![
+                device.label.clone(),
+                device.device_url.clone(),
+                device.controllable_name.clone(),
+            ];
+            row.extend(
+                columns
+                    .iter()
+                    .map(|column| device.states.value_from_name(column.state)),
+            );
+            rows.push(row);
+        }
+        rows
+    }
+
+    fn key_column(&self) -> usize {
+        1
+    }
+
+    fn format_table(&self, table: &mut Table) {
+        for (i, column) in self.columns().iter().enumerate() {
+            if column.numeric {
+                table.modify(Columns::one(i + 3), Alignment::right());
+            }
+        }
+    }
+
+    fn active_rows(&self) -> Vec<usize> {
+        self.devices
+            .iter()
+            .enumerate()
+            .filter(|(_, device)| device.states.value_from_name(MOVING_STATE) == "true")
+            .map(|(i, _)| i)
+            .collect()
+    }
+}
+
+fn render_sections(devices: &[Device], render: impl Fn(&DeviceSection) -> Table) -> String {
+    let sections = device_sections(devices);
+    if sections.is_empty() {
+        return "No devices".to_string();
+    }
+    sections
+        .iter()
+        .map(|section| format!("{}\n{}", section.category.title(), render(section)))
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+/// One titled table per category
+pub(crate) fn device_tables(devices: &[Device]) -> String {
+    render_sections(devices, build_table)
+}
+
+/// Like `device_tables`, cells that changed compared to the same section of `previous` are highlighted
+pub(crate) fn highlighted_device_tables(devices: &[Device], previous: Option<&[Device]>) -> String {
+    let previous_sections = previous.map(device_sections).unwrap_or_default();
+    render_sections(devices, |section| {
+        let before = previous_sections
+            .iter()
+            .find(|p| p.category == section.category);
+        build_highlighted_table(section, before)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn device(url: &str, controllable_name: &str, states: serde_json::Value) -> Device {
+        serde_json::from_value(json!({
+            "deviceURL": url,
+            "label": url,
+            "controllableName": controllable_name,
+            "subsystemId": 0,
+            "type": 1,
+            "available": true,
+            "synced": true,
+            "enabled": true,
+            "states": states,
+            "attributes": []
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_devices_are_grouped_with_their_columns() {
+        let devices = vec![
+            device(
+                "io://1/1",
+                "io:RollerShutterGenericIOComponent",
+                json!([{"name": "core:ClosureState", "type": 1, "value": 40}]),
+            ),
+            device(
+                "io://1/2",
+                "io:SomfyContactIOSystemSensor",
+                json!([{"name": "core:ContactState", "type": 3, "value": "open"}]),
+            ),
+            device("io://1/3", "io:RollerShutterGenericIOComponent", json!([])),
+            device(
+                "io://1/4",
+                "io:SomeUnknownComponent",
+                json!([{"name": "core:SlateOrientationState", "type": 1, "value": 10}]),
+            ),
+        ];
+
+        let sections = device_sections(&devices);
+
+        let categories: Vec<_> = sections.iter().map(|s| s.category).collect();
+        assert_eq!(
+            categories,
+            vec![
+                DeviceCategory::RollerShutter,
+                DeviceCategory::VenetianBlind,
+                DeviceCategory::Sensor
+            ]
+        );
+        assert_eq!(sections[0].devices.len(), 2);
+        let shutter_rows = sections[0].table_rows();
+        assert_eq!(
+            shutter_rows[0],
+            vec!["Label", "Device URL", "Device Type", "Closure (%)"]
+        );
+        assert_eq!(shutter_rows[1][3], " 40");
+        assert_eq!(
+            sections[2].table_rows()[0],
+            vec!["Label", "Device URL", "Device Type", "Contact"]
+        );
+    }
+}
@@ -1,34 +1,174 @@
-use chrono::DateTime;
+use crate::automation::rule_engine::RuleMatches;
+use crate::automation::scheduler::ScheduleOverview;
+use crate::cloud::client::{ActivatedToken, GeneratedToken, LocalTokens};
+use crate::commands::batch::BatchReport;
+use crate::commands::capabilities::DeviceCapabilities;
+use crate::config::alias::{AliasChanges, Aliases};
+use crate::config::certificate::CertificateInfos;
+use crate::config::common::ConfigPaths;
+use crate::events::recording::RecordedEvent;
+use crate::history::store::HistoryEntries;
+use crate::output::categories::device_tables;
+use crate::output::template::TemplateRenderer;
+use chrono::{DateTime, Local};
 use clap::ValueEnum;
 use log::debug;
 use serde::Serialize;
+use serde_json::{json, Value};
 use somfy_sdk::commands::execute_action_group::ExecuteActionGroupResponse;
 use somfy_sdk::commands::get_current_executions::GetCurrentExecutionsResponse;
+use somfy_sdk::commands::get_device_states::GetDeviceStatesResponse;
 use somfy_sdk::commands::get_devices::GetDevicesResponse;
 use somfy_sdk::commands::get_execution::GetExecutionResponse;
 use somfy_sdk::commands::types::{DeviceState, DeviceStateValue};
 use std::collections::HashMap;
 use tabled::builder::Builder;
-use tabled::settings::object::{Columns, Rows};
-use tabled::settings::{Alignment, Panel, Style};
+use tabled::settings::object::{Cell, Columns, Rows};
+use tabled::settings::{Alignment, Color, Panel, Style};
+use tabled::Table;
 
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
 pub enum OutputStyle {
+    /// Pretty-printed JSON
     Json,
+    /// Single-line JSON
+    JsonCompact,
     Table,
+    Yaml,
+    Csv,
+    Tsv,
+    /// key=value lines, records separated by an empty line
+    Plain,
 }
 
+/// How responses are printed, either a predefined style or a `--format` template
+pub(crate) enum OutputFormat {
+    Style(OutputStyle),
+    Template(Box<TemplateRenderer>),
+}
+
+/// Header followed by one row per record, used for the CSV, TSV and plain styles
+pub type Records = Vec<Vec<String>>;
+
 pub trait CliOutput: Serialize {
+    fn to_output(&self, format: &OutputFormat) -> anyhow::Result<String> {
+        match format {
+            OutputFormat::Style(style) => self.to_cli_output(*style),
+            OutputFormat::Template(renderer) => renderer.render(&self.to_template_items()?),
+        }
+    }
     fn to_cli_output(&self, style: OutputStyle) -> anyhow::Result<String> {
         match style {
             OutputStyle::Json => self.to_json(),
+            OutputStyle::JsonCompact => self.to_json_compact(),
             OutputStyle::Table => self.to_table(),
+            OutputStyle::Yaml => self.to_yaml(),
+            OutputStyle::Csv => self.to_delimited(b','),
+            OutputStyle::Tsv => self.to_delimited(b'\t'),
+            OutputStyle::Plain => self.to_plain(),
         }
     }
     fn to_json(&self) -> anyhow::Result<String> {
         Ok(serde_json::to_string_pretty(&self)?)
     }
+    fn to_json_compact(&self) -> anyhow::Result<String> {
+        Ok(serde_json::to_string(&self)?)
+    }
+    fn to_yaml(&self) -> anyhow::Result<String> {
+        Ok(serde_yaml_ng::to_string(&self)?.trim_end().to_string())
+    }
     fn to_table(&self) -> anyhow::Result<String>;
+    /// Defaults to one record per list element, with nested fields flattened into dotted columns
+    fn to_records(&self) -> anyhow::Result<Records> {
+        Ok(records_from_value(&serde_json::to_value(self)?))
+    }
+    /// The values a `--format` template is rendered for, one per list element by default
+    fn to_template_items(&self) -> anyhow::Result<Vec<Value>> {
+        Ok(match serde_json::to_value(self)? {
+            Value::Array(items) => items,
+            v => vec![v],
+        })
+    }
+    fn to_delimited(&self, delimiter: u8) -> anyhow::Result<String> {
+        write_delimited(&self.to_records()?, delimiter)
+    }
+    fn to_plain(&self) -> anyhow::Result<String> {
+        let records = self.to_records()?;
+        let Some((header, rows)) = records.split_first() else {
+            return Ok(String::new());
+        };
+        let str = rows
+            .iter()
+            .map(|row| {
+                header
+                    .iter()
+                    .zip(row)
+                    .map(|(key, value)| format!("{key}={value}"))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            })
+            .collect::<Vec<_>>()
+            .join("\n\n");
+        Ok(str)
+    }
+}
+
+fn flatten_value(prefix: &str, value: &Value, out: &mut Vec<(String, String)>) {
+    let key = |k: &str| {
+        if prefix.is_empty() {
+            k.to_string()
+        } else {
+            format!("{prefix}.{k}")
+        }
+    };
+    let scalar_key = if prefix.is_empty() { "value" } else { prefix };
+    match value {
+        Value::Object(map) => map.iter().for_each(|(k, v)| flatten_value(&key(k), v, out)),
+        Value::Array(items) => items
+            .iter()
+            .enumerate()
+            .for_each(|(i, v)| flatten_value(&key(&i.to_string()), v, out)),
+        Value::Null => out.push((scalar_key.to_string(), String::new())),
+        Value::String(s) => out.push((scalar_key.to_string(), s.clone())),
+        v => out.push((scalar_key.to_string(), v.to_string())),
+    }
+}
+
+fn records_from_value(value: &Value) -> Records {
+    let items = match value {
+        Value::Array(items) => items.clone(),
+        v => vec![v.clone()],
+    };
+    let rows: Vec<Vec<(String, String)>> = items
+        .iter()
+        .map(|item| {
+            let mut fields = vec![];
+            flatten_value("", item, &mut fields);
+            fields
+        })
+        .collect();
+
+    let mut header: Vec<String> = vec![];
+    for (key, _) in rows.iter().flatten() {
+        if !header.contains(key) {
+            header.push(key.clone());
+        }
+    }
+
+    let mut records = vec![header.clone()];
+    records.extend(rows.iter().map(|fields| {
+        header
+            .iter()
+            .map(|h| {
+                fields
+                    .iter()
+                    .find(|(k, _)| k == h)
+                    .map(|(_, v)| v.clone())
+                    .unwrap_or_default()
+            })
+            .collect()
+    }));
+    records
 }
 
 impl CliOutput for ExecuteActionGroupResponse {
@@ -107,12 +247,308 @@ impl HumanFriendly for DeviceStateValue {
     }
 }
 
+/// Rows of the table style, header first. Allows redrawing tables with highlighted changes, e.g. for `--watch`
+pub(crate) trait TableRows {
+    fn table_rows(&self) -> Records;
+    /// Column identifying a row across redraws
+    fn key_column(&self) -> usize {
+        0
+    }
+    fn format_table(&self, _table: &mut Table) {}
+    /// Data rows to emphasize when watching, e.g. moving devices
+    fn active_rows(&self) -> Vec<usize> {
+        vec![]
+    }
+}
+
+pub(crate) fn build_table<T: TableRows>(value: &T) -> Table {
+    let mut table = Builder::from(value.table_rows()).build();
+    value.format_table(&mut table);
+    table.with(Style::sharp());
+    table
+}
+
+/// Table for terminals, cells that changed compared to `previous` are highlighted.
+/// Rows are matched by their key column, new rows count as changed.
+pub(crate) fn build_highlighted_table<T: TableRows>(current: &T, previous: Option<&T>) -> Table {
+    let rows = current.table_rows();
+    let previous_rows: HashMap<String, Vec<String>> = previous
+        .map(|p| p.table_rows().into_iter().skip(1))
+        .into_iter()
+        .flatten()
+        .map(|row| (row[current.key_column()].clone(), row))
+        .collect();
+
+    let mut table = build_table(current);
+    for row in current.active_rows() {
+        table.modify(Cell::new(row + 1, 0), Color::FG_CYAN);
+    }
+    if previous.is_none() {
+        return table;
+    }
+
+    for (r, row) in rows.iter().enumerate().skip(1) {
+        let before = previous_rows.get(&row[current.key_column()]);
+        for (c, cell) in row.iter().enumerate() {
+            if before.and_then(|b| b.get(c)) != Some(cell) {
+                table.modify(Cell::new(r, c), Color::BOLD | Color::FG_YELLOW);
+            }
+        }
+    }
+    table
+}
+
+impl TableRows for GetCurrentExecutionsResponse {
+    fn table_rows(&self) -> Records {
+        let mut rows = vec![vec!["ExecId".to_string(), "Owner".to_string()]];
+        rows.extend(
+            self.iter()
+                .map(|exec| vec![exec.id.clone(), exec.owner.clone()]),
+        );
+        rows
+    }
+}
+
 impl CliOutput for GetCurrentExecutionsResponse {
+    fn to_table(&self) -> anyhow::Result<String> {
+        Ok(build_table(self).to_string())
+    }
+}
+
+impl TableRows for GetDeviceStatesResponse {
+    fn table_rows(&self) -> Records {
+        let mut rows = vec![vec!["State".to_string(), "Value".to_string()]];
+        rows.extend(
+            self.iter()
+                .map(|state| vec![state.name.clone(), state.value.to_human_friendly_string()]),
+        );
+        rows
+    }
+}
+
+impl CliOutput for GetDeviceStatesResponse {
+    fn to_table(&self) -> anyhow::Result<String> {
+        Ok(build_table(self).to_string())
+    }
+}
+
+impl TableRows for DeviceCapabilities {
+    fn table_rows(&self) -> Records {
+        let mut rows = vec![vec!["Command".to_string(), "Parameters".to_string()]];
+        rows.extend(
+            self.definition
+                .commands
+                .iter()
+                .map(|command| vec![command.command_name.clone(), command.nparams.to_string()]),
+        );
+        rows
+    }
+
+    fn format_table(&self, table: &mut Table) {
+        table.with(Panel::header(format!(
+            "{} ({})",
+            self.label, self.definition.ui_class
+        )));
+    }
+}
+
+impl CliOutput for DeviceCapabilities {
+    fn to_table(&self) -> anyhow::Result<String> {
+        Ok(build_table(self).to_string())
+    }
+
+    fn to_records(&self) -> anyhow::Result<Records> {
+        Ok(self.table_rows())
+    }
+}
+
+impl CliOutput for GetDevicesResponse {
+    /// One table per device category, each with the states relevant for it
+    fn to_table(&self) -> anyhow::Result<String> {
+        Ok(device_tables(self))
+    }
+
+    /// One column per state, so that inventories can be loaded into spreadsheets
+    fn to_records(&self) -> anyhow::Result<Records> {
+        let mut state_names: Vec<&str> = vec![];
+        for state in self.iter().flat_map(|d| &d.states) {
+            if !state_names.contains(&state.name.as_str()) {
+                state_names.push(state.name.as_str());
+            }
+        }
+
+        let mut header: Vec<String> = ["label", "device_url", "controllable_name", "available"]
+            .into_iter()
+            .map(String::from)
+            .collect();
+        header.extend(state_names.iter().map(|n| n.to_string()));
+
+        let mut records = vec![header];
+        for device in self {
+            let mut row = vec![
+                device.label.clone(),
+                device.device_url.clone(),
+                device.controllable_name.clone(),
+                device.available.to_string(),
+            ];
+            row.extend(state_names.iter().map(|name| {
+                device
+                    .states
+                    .find_by_name(name)
+                    .map(|s| s.value.to_human_friendly_string())
+                    .unwrap_or_default()
+            }));
+            records.push(row);
+        }
+        Ok(records)
+    }
+}
+
+/// Metadata columns are only added if an alias has metadata
+fn has_alias_metadata(aliases: &Aliases) -> bool {
+    aliases.values().any(|entry| !entry.metadata.is_empty())
+}
+
+impl CliOutput for Aliases {
+    fn to_table(&self) -> anyhow::Result<String> {
+        let mut builder = Builder::new();
+        let with_metadata = has_alias_metadata(self);
+        if with_metadata {
+            builder.push_record([
+                "Alias",
+                "Device URL",
+                "Room",
+                "Tags",
+                "Default position",
+                "Description",
+            ]);
+        }
+        for (alias, entry) in self {
+            let mut row = vec![alias.clone(), entry.device_url.clone()];
+            if with_metadata {
+                let metadata = &entry.metadata;
+                row.extend([
+                    metadata.room.clone().unwrap_or_default(),
+                    metadata.tags.join(", "),
+                    metadata
+                        .default_position
+                        .map(|p| format!("{p}%"))
+                        .unwrap_or_default(),
+                    metadata.description.clone().unwrap_or_default(),
+                ]);
+            }
+            builder.push_record(row);
+        }
+        let mut table = builder.build();
+        let str = table.with(Style::modern_rounded()).to_string();
+        Ok(str)
+    }
+
+    /// The columns `alias import` reads, tags are separated by commas
+    fn to_records(&self) -> anyhow::Result<Records> {
+        let with_metadata = has_alias_metadata(self);
+        let mut header = vec!["alias".to_string(), "device_url".to_string()];
+        if with_metadata {
+            header.extend(["description", "room", "tags", "default_position"].map(str::to_string));
+        }
+        let mut records = vec![header];
+        records.extend(self.iter().map(|(alias, entry)| {
+            let mut row = vec![alias.clone(), entry.device_url.clone()];
+            if with_metadata {
+                let metadata = &entry.metadata;
+                row.extend([
+                    metadata.description.clone().unwrap_or_default(),
+                    metadata.room.clone().unwrap_or_default(),
+                    metadata.tags.join(","),
+                    metadata
+                        .default_position
+                        .map(|p| p.to_string())
+                        .unwrap_or_default(),
+                ]);
+            }
+            row
+        }));
+        Ok(records)
+    }
+
+    fn to_template_items(&self) -> anyhow::Result<Vec<Value>> {
+        self.iter()
+            .map(|(alias, entry)| {
+                let mut item = json!({"alias": alias, "device_url": entry.device_url});
+                if let (Value::Object(item), Value::Object(metadata)) =
+                    (&mut item, serde_json::to_value(&entry.metadata)?)
+                {
+                    item.extend(metadata);
+                }
+                Ok(item)
+            })
+            .collect()
+    }
+}
+
+impl CliOutput for AliasChanges {
+    fn to_table(&self) -> anyhow::Result<String> {
+        let mut builder = Builder::new();
+        builder.push_record(["Alias", "Change", "Device URL", "Previous"]);
+        for change in self {
+            builder.push_record([
+                change.alias.clone(),
+                serde_json::to_value(change.change)?
+                    .as_str()
+                    .unwrap_or_default()
+                    .to_string(),
+                change.device_url.clone().unwrap_or_default(),
+                change.previous.clone().unwrap_or_default(),
+            ]);
+        }
+        Ok(builder.build().with(Style::sharp()).to_string())
+    }
+}
+
+impl CliOutput for BatchReport {
+    fn to_table(&self) -> anyhow::Result<String> {
+        let mut table = Builder::from(self.to_records()?).build();
+        Ok(table.with(Style::sharp()).to_string())
+    }
+
+    /// The outputs of the commands differ per line, only their errors are part of the records
+    fn to_records(&self) -> anyhow::Result<Records> {
+        let mut records = vec![vec![
+            "Line".to_string(),
+            "Command".to_string(),
+            "Status".to_string(),
+            "Error".to_string(),
+        ]];
+        records.extend(self.iter().map(|step| {
+            vec![
+                step.line.to_string(),
+                step.command.clone(),
+                step.status.to_string(),
+                step.error.clone().unwrap_or_default(),
+            ]
+        }));
+        Ok(records)
+    }
+}
+
+impl CliOutput for HistoryEntries {
     fn to_table(&self) -> anyhow::Result<String> {
         let mut builder = Builder::new();
-        builder.push_record(["ExecId", "Owner"]);
-        for exec in self {
-            builder.push_record([&exec.id, &exec.owner])
+        builder.push_record(["Time", "Device", "Kind", "Name", "Value", "Status"]);
+        for entry in self {
+            builder.push_record([
+                entry
+                    .timestamp
+                    .with_timezone(&Local)
+                    .format("%Y-%m-%d %H:%M:%S")
+                    .to_string()
+                    .as_str(),
+                entry.device_url.as_str(),
+                entry.kind.to_string().as_str(),
+                entry.name.as_str(),
+                entry.value.as_deref().unwrap_or_default(),
+                entry.status.as_deref().unwrap_or_default(),
+            ]);
         }
 
         let mut table = builder.build();
@@ -121,78 +557,327 @@ impl CliOutput for GetCurrentExecutionsResponse {
     }
 }
 
-impl CliOutput for GetDevicesResponse {
+impl CliOutput for GeneratedToken {
     fn to_table(&self) -> anyhow::Result<String> {
         let mut builder = Builder::new();
-        builder.push_record([
-            "Label",
-            "Device URL",
-            "Device Type",
-            "Open/Close",
-            "Status",
-            "Closure (%)",
-            "Tilt (%)",
-            "'My' position (%)",
-            "'My' tilt (%)",
-            "Is Moving?",
-        ]);
+        builder.push_record(["Token", self.token.as_str()]);
+        let mut table = builder.build();
+        table.with(Panel::header("Generated Token"));
+        Ok(table.with(Style::modern_rounded()).to_string())
+    }
+}
 
-        for device in self {
+impl CliOutput for ActivatedToken {
+    fn to_table(&self) -> anyhow::Result<String> {
+        let mut builder = Builder::new();
+        builder.push_record(["Label", self.label.as_str()]);
+        builder.push_record(["Request Id", self.request_id.as_str()]);
+        let mut table = builder.build();
+        table.with(Panel::header("Activated Token"));
+        Ok(table.with(Style::modern_rounded()).to_string())
+    }
+}
+
+impl CliOutput for LocalTokens {
+    fn to_table(&self) -> anyhow::Result<String> {
+        let format_time = |ms: Option<i64>| {
+            ms.and_then(DateTime::from_timestamp_millis)
+                .map(|t| {
+                    t.with_timezone(&Local)
+                        .format("%Y-%m-%d %H:%M:%S")
+                        .to_string()
+                })
+                .unwrap_or("-".to_string())
+        };
+        let mut builder = Builder::new();
+        builder.push_record(["Label", "UUID", "Scope", "Created", "Expires"]);
+        for token in self {
             builder.push_record([
-                &device.label,
-                &device.device_url,
-                &device.controllable_name,
-                device
-                    .states
-                    .value_from_name("core:OpenClosedState")
-                    .as_str(),
-                device.states.value_from_name("core:StatusState").as_str(),
-                device.states.value_from_name("core:ClosureState").as_str(),
-                device
-                    .states
-                    .value_from_name("core:SlateOrientationState")
-                    .as_str(),
-                device
-                    .states
-                    .value_from_name("core:Memorized1PositionState")
-                    .as_str(),
-                device
-                    .states
-                    .value_from_name("core:Memorized1OrientationState")
-                    .as_str(),
-                device.states.value_from_name("core:MovingState").as_str(),
+                token.label.clone(),
+                token.uuid.clone(),
+                token.scope.clone(),
+                format_time(token.gateway_creation_time),
+                format_time(token.expiration_time),
+            ]);
+        }
+        Ok(builder.build().with(Style::sharp()).to_string())
+    }
+}
+
+impl CliOutput for ConfigPaths {
+    fn to_table(&self) -> anyhow::Result<String> {
+        let mut builder = Builder::new();
+        builder.push_record(["File", "Path", "Exists"]);
+        for file in &self.files {
+            builder.push_record([
+                file.name.clone(),
+                file.path.display().to_string(),
+                file.exists.to_string(),
+            ]);
+        }
+        let mut table = builder.build();
+        table.with(Panel::header(format!(
+            "{} (from {})",
+            self.config_dir.display(),
+            serde_json::to_value(self.source)?
+                .as_str()
+                .unwrap_or_default()
+        )));
+        Ok(table.with(Style::sharp()).to_string())
+    }
+
+    /// One record per file
+    fn to_records(&self) -> anyhow::Result<Records> {
+        Ok(records_from_value(&serde_json::to_value(&self.files)?))
+    }
+}
+
+impl CliOutput for CertificateInfos {
+    fn to_table(&self) -> anyhow::Result<String> {
+        let mut builder = Builder::new();
+        builder.push_record(["Subject", "Issuer", "SHA-256 Fingerprint", "Valid until"]);
+        for certificate in self {
+            builder.push_record([
+                certificate.subject.clone(),
+                certificate.issuer.clone(),
+                certificate.fingerprint.clone(),
+                certificate
+                    .not_after
+                    .with_timezone(&Local)
+                    .format("%Y-%m-%d %H:%M:%S")
+                    .to_string(),
+            ]);
+        }
+        Ok(builder.build().with(Style::sharp()).to_string())
+    }
+}
+
+impl CliOutput for ScheduleOverview {
+    fn to_table(&self) -> anyhow::Result<String> {
+        let mut builder = Builder::new();
+        builder.push_record(["Name", "Trigger", "Action", "Devices", "Next fire"]);
+        for entry in self {
+            let next_fire = entry
+                .next_fire
+                .map(|t| t.format("%Y-%m-%d %H:%M:%S %Z").to_string())
+                .unwrap_or("-".to_string());
+            builder.push_record([
+                entry.name.as_str(),
+                entry.trigger.as_str(),
+                entry.action.as_str(),
+                entry.devices.join(", ").as_str(),
+                next_fire.as_str(),
             ]);
         }
 
         let mut table = builder.build();
-        table.modify(Columns::new(5..=8), Alignment::right());
+        let str = table.with(Style::sharp()).to_string();
+        Ok(str)
+    }
+}
+
+impl CliOutput for RuleMatches {
+    fn to_table(&self) -> anyhow::Result<String> {
+        let mut builder = Builder::new();
+        builder.push_record([
+            "Time",
+            "Rule",
+            "Event",
+            "Source device",
+            "Action",
+            "Devices",
+        ]);
+        for m in self {
+            builder.push_record([
+                m.timestamp.format("%Y-%m-%d %H:%M:%S").to_string().as_str(),
+                m.rule.as_str(),
+                m.event.as_str(),
+                m.device.as_deref().unwrap_or("-"),
+                m.action.to_string().as_str(),
+                m.devices.join(", ").as_str(),
+            ]);
+        }
 
+        let mut table = builder.build();
         let str = table.with(Style::sharp()).to_string();
         Ok(str)
     }
 }
 
-impl CliOutput for HashMap<String, String> {
+impl CliOutput for RecordedEvent {
+    /// Single line per event, so that the output of `listen` is itself a valid recording
+    fn to_json(&self) -> anyhow::Result<String> {
+        Ok(serde_json::to_string(&self)?)
+    }
+
     fn to_table(&self) -> anyhow::Result<String> {
-        let builder = Builder::from(self.clone());
+        let mut builder = Builder::new();
+        builder.push_record([
+            self.timestamp
+                .format("%Y-%m-%d %H:%M:%S")
+                .to_string()
+                .as_str(),
+            self.event.name.as_str(),
+            self.event.device_url.as_deref().unwrap_or("-"),
+            self.states_summary().as_str(),
+        ]);
+
         let mut table = builder.build();
-        let str = table.with(Style::modern_rounded()).to_string();
+        let str = table.with(Style::blank()).to_string();
         Ok(str)
     }
+
+    fn to_records(&self) -> anyhow::Result<Records> {
+        Ok(vec![
+            vec![
+                "timestamp".to_string(),
+                "name".to_string(),
+                "device_url".to_string(),
+                "states".to_string(),
+            ],
+            vec![
+                self.timestamp.to_rfc3339(),
+                self.event.name.clone(),
+                self.event.device_url.clone().unwrap_or_default(),
+                self.states_summary(),
+            ],
+        ])
+    }
+}
+
+impl RecordedEvent {
+    pub(crate) fn states_summary(&self) -> String {
+        self.event
+            .device_states
+            .iter()
+            .map(|s| match &s.value {
+                Value::String(v) => format!("{}={v}", s.name),
+                v => format!("{}={v}", s.name),
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// Object safe counterpart of `CliOutput`, lets commands hand back their response instead of printing it
+pub(crate) trait AnyOutput {
+    fn output(&self, format: &OutputFormat) -> anyhow::Result<String>;
+    fn value(&self) -> anyhow::Result<Value>;
+}
+
+impl<T: CliOutput> AnyOutput for T {
+    fn output(&self, format: &OutputFormat) -> anyhow::Result<String> {
+        self.to_output(format)
+    }
+
+    fn value(&self) -> anyhow::Result<Value> {
+        Ok(serde_json::to_value(self)?)
+    }
 }
 
-pub(crate) fn print_to_console<T>(response: T, style: OutputStyle)
+pub(crate) fn print_to_console<T>(response: T, format: &OutputFormat) -> anyhow::Result<()>
 where
     T: CliOutput,
 {
-    match response.to_cli_output(style) {
-        Ok(str) => {
-            debug!("Command succeeded");
-            println!("{str}")
-        }
-        Err(e) => {
-            debug!("Error: {e}");
-            eprintln!("Error: {e}")
-        }
+    let str = response.to_output(format)?;
+    debug!("Command succeeded");
+    println!("{str}");
+    Ok(())
+}
+
+fn write_delimited(records: &[Vec<String>], delimiter: u8) -> anyhow::Result<String> {
+    let mut writer = csv::WriterBuilder::new()
+        .delimiter(delimiter)
+        .from_writer(vec![]);
+    for record in records {
+        writer.write_record(record)?;
+    }
+    let str = String::from_utf8(writer.into_inner()?)?;
+    Ok(str.trim_end().to_string())
+}
+
+/// Rows of a stream element after the first, without the header
+fn delimited_rows<T: CliOutput>(item: &T, delimiter: u8) -> anyhow::Result<String> {
+    let records = item.to_records()?;
+    write_delimited(records.get(1..).unwrap_or_default(), delimiter)
+}
+
+/// Formats one element of a stream, e.g. events of `listen`. Keeps the stream parseable as a whole:
+/// CSV/TSV headers are only part of the first element, YAML elements become separate documents.
+pub(crate) fn format_stream_item<T>(
+    item: T,
+    format: &OutputFormat,
+    first: bool,
+) -> anyhow::Result<String>
+where
+    T: CliOutput,
+{
+    let OutputFormat::Style(style) = format else {
+        return item.to_output(format);
+    };
+    match *style {
+        OutputStyle::Csv if first => item.to_delimited(b','),
+        OutputStyle::Tsv if first => item.to_delimited(b'\t'),
+        OutputStyle::Csv => delimited_rows(&item, b','),
+        OutputStyle::Tsv => delimited_rows(&item, b'\t'),
+        OutputStyle::Yaml => Ok(format!("---\n{}", item.to_yaml()?)),
+        OutputStyle::Plain if !first => Ok(format!("\n{}", item.to_plain()?)),
+        _ => item.to_cli_output(*style),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_records_flatten_nested_fields() {
+        let value = json!([
+            {"id": "1", "actionGroup": {"label": "a"}, "tags": ["x", "y"]},
+            {"id": "2", "state": null}
+        ]);
+
+        let records = records_from_value(&value);
+
+        assert_eq!(
+            records,
+            vec![
+                vec!["id", "actionGroup.label", "tags.0", "tags.1", "state"],
+                vec!["1", "a", "x", "y", ""],
+                vec!["2", "", "", "", ""],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_alias_map_delimited_and_plain() {
+        let aliases = Aliases::from([
+            ("kitchen".to_string(), "io://1/2".to_string().into()),
+            ("living, room".to_string(), "io://1/1".to_string().into()),
+        ]);
+
+        assert_eq!(
+            aliases.to_delimited(b',').unwrap(),
+            "alias,device_url\nkitchen,io://1/2\n\"living, room\",io://1/1"
+        );
+        assert_eq!(
+            delimited_rows(&aliases, b'\t').unwrap(),
+            "kitchen\tio://1/2\nliving, room\tio://1/1"
+        );
+        assert_eq!(
+            aliases.to_plain().unwrap(),
+            "alias=kitchen\ndevice_url=io://1/2\n\nalias=living, room\ndevice_url=io://1/1"
+        );
+    }
+
+    #[test]
+    fn test_yaml_and_compact_json() {
+        let aliases = Aliases::from([("kitchen".to_string(), "io://1/2".to_string().into())]);
+
+        assert_eq!(aliases.to_yaml().unwrap(), "kitchen: io://1/2");
+        assert_eq!(
+            aliases.to_json_compact().unwrap(),
+            r#"{"kitchen":"io://1/2"}"#
+        );
     }
 }
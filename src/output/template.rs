@@ -0,0 +1,180 @@
+use crate::output::formatter::HumanFriendly;
+use anyhow::Context as _;
+use chrono::{DateTime, Local};
+use handlebars::{
+    no_escape, Context, Handlebars, Helper, HelperResult, Output, RenderContext, RenderErrorReason,
+};
+use serde_json::Value;
+use somfy_sdk::commands::types::DeviceStateValue;
+
+const TEMPLATE_NAME: &str = "format";
+const DEFAULT_TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// Renders a user provided handlebars template once per element of a response, similar to `docker --format`
+pub(crate) struct TemplateRenderer {
+    registry: Handlebars<'static>,
+}
+
+/// `{{state "core:ClosureState"}}` looks up a state of the current element, `{{state "name" obj}}` of `obj`.
+/// Works for devices (`states`), events (`deviceStates`) and recorded events as printed by `listen` (`event.deviceStates`).
+fn state_helper(
+    h: &Helper<'_>,
+    _: &Handlebars<'_>,
+    ctx: &Context,
+    _: &mut RenderContext<'_, '_>,
+    out: &mut dyn Output,
+) -> HelperResult {
+    let name = h
+        .param(0)
+        .and_then(|p| p.value().as_str())
+        .ok_or(RenderErrorReason::ParamNotFoundForIndex("state", 0))?;
+    let holder = h.param(1).map(|p| p.value()).unwrap_or(ctx.data());
+
+    let states = holder
+        .get("states")
+        .or(holder.get("deviceStates"))
+        .or(holder.get("event").and_then(|e| e.get("deviceStates")))
+        .and_then(Value::as_array);
+    let value = states
+        .into_iter()
+        .flatten()
+        .find(|state| state.get("name").and_then(Value::as_str) == Some(name))
+        .and_then(|state| state.get("value"));
+
+    if let Some(value) = value {
+        // Event values are not always valid device state values, e.g. decimal temperatures
+        let value = match serde_json::from_value::<DeviceStateValue>(value.clone()) {
+            Ok(value) => value.to_human_friendly_string(),
+            Err(_) => value.to_string(),
+        };
+        out.write(&value)?;
+    }
+    Ok(())
+}
+
+/// `{{time startTime}}` or `{{time timestamp "%H:%M"}}` formats epoch milliseconds or RFC 3339 timestamps in local time
+fn time_helper(
+    h: &Helper<'_>,
+    _: &Handlebars<'_>,
+    _: &Context,
+    _: &mut RenderContext<'_, '_>,
+    out: &mut dyn Output,
+) -> HelperResult {
+    let value = h
+        .param(0)
+        .map(|p| p.value())
+        .ok_or(RenderErrorReason::ParamNotFoundForIndex("time", 0))?;
+    let format = h
+        .param(1)
+        .and_then(|p| p.value().as_str())
+        .unwrap_or(DEFAULT_TIME_FORMAT);
+
+    let timestamp = match value {
+        Value::Number(n) => n.as_i64().and_then(DateTime::from_timestamp_millis),
+        Value::String(s) => DateTime::parse_from_rfc3339(s).ok().map(|t| t.to_utc()),
+        _ => None,
+    }
+    .ok_or(RenderErrorReason::Other(format!(
+        "Cannot format {value} as time"
+    )))?;
+
+    out.write(&timestamp.with_timezone(&Local).format(format).to_string())?;
+    Ok(())
+}
+
+impl TemplateRenderer {
+    pub(crate) fn new(template: &str) -> anyhow::Result<Self> {
+        let mut registry = Handlebars::new();
+        registry.register_escape_fn(no_escape);
+        registry.register_helper("state", Box::new(state_helper));
+        registry.register_helper("time", Box::new(time_helper));
+        registry
+            .register_template_string(TEMPLATE_NAME, template)
+            .context("Invalid --format template")?;
+
+        Ok(Self { registry })
+    }
+
+    /// Renders every item on its own line
+    pub(crate) fn render(&self, items: &[Value]) -> anyhow::Result<String> {
+        let lines = items
+            .iter()
+            .map(|item| self.registry.render(TEMPLATE_NAME, item))
+            .collect::<Result<Vec<_>, _>>()
+            .context("Failed to render --format template")?;
+        Ok(lines.join("\n"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_render_with_state_helper() {
+        let renderer =
+            TemplateRenderer::new(r#"{{label}}: {{state "core:ClosureState"}}%"#).unwrap();
+        let devices = vec![
+            json!({"label": "Kitchen", "states": [{"name": "core:ClosureState", "type": 1, "value": 40}]}),
+            json!({"label": "Light & Hall", "states": []}),
+        ];
+
+        assert_eq!(
+            renderer.render(&devices).unwrap(),
+            "Kitchen: 40%\nLight & Hall: %"
+        );
+    }
+
+    #[test]
+    fn test_state_helper_with_explicit_object() {
+        let renderer = TemplateRenderer::new(
+            r#"{{#each events}}{{state "core:OpenClosedState" this}} {{/each}}"#,
+        )
+        .unwrap();
+        let data = json!({"events": [
+            {"deviceStates": [{"name": "core:OpenClosedState", "type": 3, "value": "open"}]},
+            {"deviceStates": [{"name": "core:OpenClosedState", "type": 3, "value": "closed"}]}
+        ]});
+
+        assert_eq!(renderer.render(&[data]).unwrap(), "open closed ");
+    }
+
+    #[test]
+    fn test_render_listened_event() {
+        let renderer = TemplateRenderer::new(
+            r#"{{event.deviceURL}} {{state "core:ClosureState"}} {{state "core:TemperatureState"}}"#,
+        )
+        .unwrap();
+        let recorded = json!({
+            "timestamp": "2024-06-21T18:00:00Z",
+            "event": {
+                "name": "DeviceStateChangedEvent",
+                "deviceURL": "io://1234-5678-9012/1",
+                "deviceStates": [
+                    {"name": "core:ClosureState", "type": 1, "value": "40"},
+                    {"name": "core:TemperatureState", "value": 21.5}
+                ]
+            }
+        });
+
+        assert_eq!(
+            renderer.render(&[recorded]).unwrap(),
+            "io://1234-5678-9012/1 40 21.5"
+        );
+    }
+
+    #[test]
+    fn test_time_helper() {
+        let renderer =
+            TemplateRenderer::new(r#"{{time startTime "%Y"}}|{{time ts "%Y"}}"#).unwrap();
+        let data = json!({"startTime": 1718992800000_i64, "ts": "2024-06-21T18:00:00Z"});
+
+        assert_eq!(renderer.render(&[data]).unwrap(), "2024|2024");
+    }
+
+    #[test]
+    fn test_invalid_template_is_rejected() {
+        assert!(TemplateRenderer::new("{{#each}}").is_err());
+    }
+}
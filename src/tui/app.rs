@@ -0,0 +1,294 @@
+use crate::commands::executor::OpenClose;
+use crate::config::alias::Aliases;
+use crate::events::fetch_device_events::DEVICE_STATE_CHANGED_EVENT;
+use crate::events::recording::RecordedEvent;
+use crate::events::watch::apply_states;
+use crate::output::formatter::Searchable;
+use chrono::Local;
+use ratatui::crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use somfy_sdk::commands::get_current_executions::GetCurrentExecutionsResponse;
+use somfy_sdk::commands::get_devices::GetDevicesResponse;
+use somfy_sdk::commands::types::Device;
+use std::collections::{BTreeSet, HashMap, VecDeque};
+
+const MAX_EVENTS: usize = 200;
+const MAX_POSITION_DIGITS: usize = 3;
+
+/// What a key press asks the dashboard loop to do
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Action {
+    Quit,
+    Refresh,
+    Execute {
+        device_urls: Vec<String>,
+        command: OpenClose,
+    },
+}
+
+/// State of the dashboard, updated by key presses, gateway events and refreshes
+pub(crate) struct App {
+    pub devices: GetDevicesResponse,
+    /// Aliases per device URL
+    pub aliases: HashMap<String, Vec<String>>,
+    pub executions: GetCurrentExecutionsResponse,
+    /// Newest event last
+    pub events: VecDeque<String>,
+    pub selected: usize,
+    /// Device URLs of the group commands are sent to instead of the selected device
+    pub marked: BTreeSet<String>,
+    /// Digits typed after pressing `p`, None outside of position input
+    pub position_input: Option<String>,
+    pub status: String,
+}
+
+impl App {
+    pub(crate) fn new(devices: GetDevicesResponse, aliases: &Aliases) -> Self {
+        let mut by_url: HashMap<String, Vec<String>> = HashMap::new();
+        for (alias, entry) in aliases {
+            by_url
+                .entry(entry.device_url.clone())
+                .or_default()
+                .push(alias.clone());
+        }
+        by_url.values_mut().for_each(|a| a.sort());
+
+        Self {
+            devices,
+            aliases: by_url,
+            executions: vec![],
+            events: VecDeque::new(),
+            selected: 0,
+            marked: BTreeSet::new(),
+            position_input: None,
+            status: String::new(),
+        }
+    }
+
+    pub(crate) fn alias_of(&self, device_url: &str) -> String {
+        self.aliases
+            .get(device_url)
+            .map(|a| a.join(", "))
+            .unwrap_or_default()
+    }
+
+    pub(crate) fn label_of(&self, device_url: &str) -> String {
+        self.devices
+            .iter()
+            .find(|d| d.device_url == device_url)
+            .map(|d| d.label.clone())
+            .unwrap_or(device_url.to_string())
+    }
+
+    pub(crate) fn is_moving(device: &Device) -> bool {
+        device.states.value_from_name("core:MovingState") == "true"
+    }
+
+    pub(crate) fn set_devices(&mut self, devices: GetDevicesResponse) {
+        self.marked
+            .retain(|url| devices.iter().any(|d| &d.device_url == url));
+        self.selected = self.selected.min(devices.len().saturating_sub(1));
+        self.devices = devices;
+    }
+
+    /// The marked devices, or the selected one if none are marked
+    fn targets(&self) -> Vec<String> {
+        if !self.marked.is_empty() {
+            return self.marked.iter().cloned().collect();
+        }
+        self.devices
+            .get(self.selected)
+            .map(|d| vec![d.device_url.clone()])
+            .unwrap_or_default()
+    }
+
+    fn execute(&self, command: OpenClose) -> Option<Action> {
+        let device_urls = self.targets();
+        (!device_urls.is_empty()).then_some(Action::Execute {
+            device_urls,
+            command,
+        })
+    }
+
+    pub(crate) fn handle_key(&mut self, key: KeyEvent) -> Option<Action> {
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+            return Some(Action::Quit);
+        }
+        if let Some(input) = self.position_input.as_mut() {
+            match key.code {
+                KeyCode::Char(c) if c.is_ascii_digit() && input.len() < MAX_POSITION_DIGITS => {
+                    input.push(c)
+                }
+                KeyCode::Backspace => {
+                    input.pop();
+                }
+                KeyCode::Esc => self.position_input = None,
+                KeyCode::Enter => {
+                    let input = self.position_input.take().unwrap_or_default();
+                    return match input.parse::<u8>() {
+                        Ok(p) if p <= 100 => self.execute(OpenClose::Closure(p)),
+                        _ => {
+                            self.status = "Position must be between 0 and 100".to_string();
+                            None
+                        }
+                    };
+                }
+                _ => {}
+            }
+            return None;
+        }
+
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return Some(Action::Quit),
+            KeyCode::Up | KeyCode::Char('k') => self.selected = self.selected.saturating_sub(1),
+            KeyCode::Down | KeyCode::Char('j') => {
+                self.selected = (self.selected + 1).min(self.devices.len().saturating_sub(1))
+            }
+            KeyCode::Char(' ') => {
+                if let Some(device) = self.devices.get(self.selected) {
+                    if !self.marked.remove(&device.device_url) {
+                        self.marked.insert(device.device_url.clone());
+                    }
+                }
+            }
+            KeyCode::Char('o') => return self.execute(OpenClose::Open),
+            KeyCode::Char('c') => return self.execute(OpenClose::Close),
+            KeyCode::Char('s') => return self.execute(OpenClose::Stop),
+            KeyCode::Char('p') => self.position_input = Some(String::new()),
+            KeyCode::Char('r') => return Some(Action::Refresh),
+            _ => {}
+        }
+        None
+    }
+
+    /// Applies state changes to the device list and adds the event to the event pane
+    pub(crate) fn apply_event(&mut self, recorded: RecordedEvent) {
+        let event = &recorded.event;
+        let source = event.device_url.as_deref().map(|url| self.label_of(url));
+        if event.name == DEVICE_STATE_CHANGED_EVENT {
+            if let Some(device) = self
+                .devices
+                .iter_mut()
+                .find(|d| Some(&d.device_url) == event.device_url.as_ref())
+            {
+                apply_states(&mut device.states, &event.device_states);
+            }
+        }
+
+        let line = [
+            recorded
+                .timestamp
+                .with_timezone(&Local)
+                .format("%H:%M:%S")
+                .to_string(),
+            event.name.clone(),
+            source.unwrap_or_default(),
+            recorded.states_summary(),
+        ]
+        .into_iter()
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ");
+
+        self.events.push_back(line);
+        if self.events.len() > MAX_EVENTS {
+            self.events.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn app() -> App {
+        let devices = serde_json::from_value(json!([
+            {"deviceURL": "io://1/1", "label": "Living Room", "controllableName": "io:Shutter",
+             "subsystemId": 0, "type": 1, "available": true, "synced": true, "enabled": true,
+             "states": [{"name": "core:ClosureState", "type": 1, "value": 0}], "attributes": []},
+            {"deviceURL": "io://1/2", "label": "Kitchen", "controllableName": "io:Shutter",
+             "subsystemId": 0, "type": 1, "available": true, "synced": true, "enabled": true,
+             "states": [], "attributes": []}
+        ]))
+        .unwrap();
+        let aliases = Aliases::from([("kitchen".to_string(), "io://1/2".to_string().into())]);
+        App::new(devices, &aliases)
+    }
+
+    fn press(app: &mut App, code: KeyCode) -> Option<Action> {
+        app.handle_key(KeyEvent::from(code))
+    }
+
+    #[test]
+    fn test_commands_target_selected_device() {
+        let mut app = app();
+        press(&mut app, KeyCode::Down);
+
+        assert_eq!(app.alias_of("io://1/2"), "kitchen");
+        assert_eq!(
+            press(&mut app, KeyCode::Char('c')),
+            Some(Action::Execute {
+                device_urls: vec!["io://1/2".to_string()],
+                command: OpenClose::Close
+            })
+        );
+    }
+
+    #[test]
+    fn test_commands_target_marked_group() {
+        let mut app = app();
+        press(&mut app, KeyCode::Char(' '));
+        press(&mut app, KeyCode::Down);
+        press(&mut app, KeyCode::Char(' '));
+
+        assert_eq!(
+            press(&mut app, KeyCode::Char('s')),
+            Some(Action::Execute {
+                device_urls: vec!["io://1/1".to_string(), "io://1/2".to_string()],
+                command: OpenClose::Stop
+            })
+        );
+    }
+
+    #[test]
+    fn test_position_input() {
+        let mut app = app();
+        press(&mut app, KeyCode::Char('p'));
+        for c in ['1', '5', '0'] {
+            press(&mut app, KeyCode::Char(c));
+        }
+        assert_eq!(press(&mut app, KeyCode::Enter), None);
+        assert!(app.status.contains("between 0 and 100"));
+
+        press(&mut app, KeyCode::Char('p'));
+        press(&mut app, KeyCode::Char('4'));
+        press(&mut app, KeyCode::Char('5'));
+        assert_eq!(
+            press(&mut app, KeyCode::Enter),
+            Some(Action::Execute {
+                device_urls: vec!["io://1/1".to_string()],
+                command: OpenClose::Closure(45)
+            })
+        );
+        assert_eq!(app.position_input, None);
+    }
+
+    #[test]
+    fn test_events_update_devices() {
+        let mut app = app();
+        app.apply_event(
+            serde_json::from_value(json!({
+                "timestamp": "2024-01-01T00:00:00Z",
+                "event": {"name": "DeviceStateChangedEvent", "deviceURL": "io://1/1",
+                          "deviceStates": [{"name": "core:ClosureState", "type": 1, "value": "60"}]}
+            }))
+            .unwrap(),
+        );
+
+        assert_eq!(
+            app.devices[0].states.value_from_name("core:ClosureState"),
+            " 60"
+        );
+        assert!(app.events[0].contains("DeviceStateChangedEvent Living Room"));
+    }
+}
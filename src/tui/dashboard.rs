@@ -0,0 +1,108 @@
+use crate::commands::executor::CommandExecutor;
+use crate::config::alias::AliasManager;
+use crate::events::recording::RecordedEvent;
+use crate::tui::app::{Action, App};
+use crate::tui::view;
+use crate::utils::poller::PollerConfig;
+use chrono::Utc;
+use ratatui::crossterm::event::{self, Event, KeyEventKind};
+use ratatui::DefaultTerminal;
+use tokio::sync::mpsc;
+
+/// Terminal events are read on a separate thread, crossterm only offers blocking reads without extra features
+fn read_terminal_events(sender: mpsc::UnboundedSender<Event>) {
+    while let Ok(event) = event::read() {
+        let relevant = match &event {
+            Event::Key(key) => key.kind == KeyEventKind::Press,
+            Event::Resize(..) => true,
+            _ => false,
+        };
+        if relevant && sender.send(event).is_err() {
+            break;
+        }
+    }
+}
+
+async fn refresh(executor: &CommandExecutor, app: &mut App) -> anyhow::Result<()> {
+    app.set_devices(executor.list_devices().await?);
+    app.executions = executor.get_current_executions().await?;
+    Ok(())
+}
+
+/// Pulls new events and the running executions, errors are shown in the status line
+async fn poll_gateway(executor: &CommandExecutor, app: &mut App, listener_id: &mut String) {
+    match executor.fetch_device_events(listener_id).await {
+        Ok(events) => {
+            let timestamp = Utc::now();
+            for event in events {
+                app.apply_event(RecordedEvent { timestamp, event });
+            }
+        }
+        Err(e) => {
+            app.status = format!("Fetching events failed: {e:#}");
+            match executor.register_event_listener().await {
+                Ok(id) => *listener_id = id,
+                Err(e) => app.status = format!("Registering event listener failed: {e:#}"),
+            }
+        }
+    }
+
+    match executor.get_current_executions().await {
+        Ok(executions) => app.executions = executions,
+        Err(e) => app.status = format!("Fetching executions failed: {e:#}"),
+    }
+}
+
+async fn event_loop(
+    terminal: &mut DefaultTerminal,
+    executor: &CommandExecutor,
+    mut app: App,
+    mut listener_id: String,
+) -> anyhow::Result<()> {
+    let (sender, mut terminal_events) = mpsc::unbounded_channel();
+    std::thread::spawn(move || read_terminal_events(sender));
+    let mut ticker = tokio::time::interval(PollerConfig::EVENT_LISTENER.refresh_interval);
+
+    loop {
+        terminal.draw(|frame| view::draw(frame, &app))?;
+
+        tokio::select! {
+            Some(event) = terminal_events.recv() => {
+                let Event::Key(key) = event else { continue };
+                match app.handle_key(key) {
+                    Some(Action::Quit) => return Ok(()),
+                    Some(Action::Refresh) => {
+                        if let Err(e) = refresh(executor, &mut app).await {
+                            app.status = format!("Refresh failed: {e:#}");
+                        }
+                    }
+                    Some(Action::Execute { device_urls, command }) => {
+                        let count = device_urls.len();
+                        app.status = match executor.apply(device_urls, command).await {
+                            Ok(resp) => format!("Sent {command} to {count} device(s), execution {}", resp.exec_id),
+                            Err(e) => format!("Error: {e:#}"),
+                        };
+                    }
+                    None => {}
+                }
+            }
+            _ = ticker.tick() => poll_gateway(executor, &mut app, &mut listener_id).await,
+        }
+    }
+}
+
+/// Runs the dashboard until the user quits. The terminal is restored on errors and panics.
+pub(crate) async fn run(
+    executor: &CommandExecutor,
+    alias_manager: &AliasManager,
+) -> anyhow::Result<()> {
+    let aliases = alias_manager.load_aliases()?;
+    let mut app = App::new(executor.list_devices().await?, &aliases);
+    app.executions = executor.get_current_executions().await?;
+    let listener_id = executor.register_event_listener().await?;
+
+    let mut terminal = ratatui::init();
+    let result = event_loop(&mut terminal, executor, app, listener_id).await;
+    ratatui::restore();
+    result
+}
@@ -0,0 +1,145 @@
+use crate::output::formatter::Searchable;
+use crate::tui::app::App;
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, List, ListItem, Paragraph, Row, Table, TableState};
+use ratatui::Frame;
+
+const HELP: &str = "↑/↓ select  space mark  o open  c close  s stop  p position  r refresh  q quit";
+
+fn device_rows(app: &App) -> Vec<Row<'_>> {
+    app.devices
+        .iter()
+        .map(|device| {
+            let marker = if app.marked.contains(&device.device_url) {
+                "●"
+            } else {
+                " "
+            };
+            let status = if App::is_moving(device) {
+                "moving".to_string()
+            } else {
+                device.states.value_from_name("core:StatusState")
+            };
+            let row = Row::new(vec![
+                marker.to_string(),
+                device.label.clone(),
+                app.alias_of(&device.device_url),
+                device.states.value_from_name("core:ClosureState"),
+                device.states.value_from_name("core:SlateOrientationState"),
+                status,
+            ]);
+            if App::is_moving(device) {
+                row.style(Style::default().fg(Color::Cyan))
+            } else {
+                row
+            }
+        })
+        .collect()
+}
+
+/// Device table on top, events and running executions below, status line at the bottom
+pub(crate) fn draw(frame: &mut Frame, app: &App) {
+    let [devices_area, bottom_area, status_area] = Layout::vertical([
+        Constraint::Min(6),
+        Constraint::Length(12),
+        Constraint::Length(1),
+    ])
+    .areas(frame.area());
+    let [events_area, executions_area] =
+        Layout::horizontal([Constraint::Percentage(60), Constraint::Percentage(40)])
+            .areas(bottom_area);
+
+    let header = Row::new(["", "Label", "Alias", "Closure (%)", "Tilt (%)", "Status"])
+        .style(Style::default().add_modifier(Modifier::BOLD));
+    let devices = Table::new(
+        device_rows(app),
+        [
+            Constraint::Length(1),
+            Constraint::Fill(2),
+            Constraint::Fill(1),
+            Constraint::Length(11),
+            Constraint::Length(8),
+            Constraint::Length(11),
+        ],
+    )
+    .header(header)
+    .block(Block::bordered().title(" Devices "))
+    .row_highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    let mut state = TableState::default().with_selected(Some(app.selected));
+    frame.render_stateful_widget(devices, devices_area, &mut state);
+
+    let visible_events = events_area.height.saturating_sub(2) as usize;
+    let events: Vec<ListItem> = app
+        .events
+        .iter()
+        .skip(app.events.len().saturating_sub(visible_events))
+        .map(|e| ListItem::new(e.as_str()))
+        .collect();
+    frame.render_widget(
+        List::new(events).block(Block::bordered().title(" Events ")),
+        events_area,
+    );
+
+    let executions: Vec<ListItem> = app
+        .executions
+        .iter()
+        .map(|exec| {
+            let label = exec
+                .action_group
+                .label
+                .clone()
+                .unwrap_or(exec.description.clone());
+            ListItem::new(format!("{} {label}", exec.state))
+        })
+        .collect();
+    frame.render_widget(
+        List::new(executions).block(Block::bordered().title(" Running executions ")),
+        executions_area,
+    );
+
+    let status = match &app.position_input {
+        Some(input) => Line::from(format!(
+            "Position (0-100): {input}_  Enter apply  Esc cancel"
+        )),
+        None if app.status.is_empty() => Line::from(HELP),
+        None => Line::from(format!("{}  |  {HELP}", app.status)),
+    };
+    frame.render_widget(Paragraph::new(status), status_area);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::alias::Aliases;
+    use ratatui::backend::TestBackend;
+    use ratatui::Terminal;
+    use serde_json::json;
+
+    #[test]
+    fn test_draws_devices_with_alias_and_state() {
+        let devices = serde_json::from_value(json!([
+            {"deviceURL": "io://1/1", "label": "Living Room", "controllableName": "io:Shutter",
+             "subsystemId": 0, "type": 1, "available": true, "synced": true, "enabled": true,
+             "states": [{"name": "core:ClosureState", "type": 1, "value": 40}], "attributes": []}
+        ]))
+        .unwrap();
+        let aliases = Aliases::from([("living".to_string(), "io://1/1".to_string().into())]);
+        let app = App::new(devices, &aliases);
+
+        let mut terminal = Terminal::new(TestBackend::new(100, 24)).unwrap();
+        terminal.draw(|frame| draw(frame, &app)).unwrap();
+
+        let screen: String = terminal
+            .backend()
+            .buffer()
+            .content()
+            .iter()
+            .map(|cell| cell.symbol())
+            .collect();
+        assert!(screen.contains("Living Room"));
+        assert!(screen.contains("living"));
+        assert!(screen.contains("40"));
+    }
+}
@@ -0,0 +1,44 @@
+use std::time::Duration;
+
+/// Accepts `500ms`, `2s`, `1m`, `3h`, `7d` and plain seconds like `1.5`
+pub(crate) fn parse_duration(value: &str) -> Result<Duration, String> {
+    let (number, unit_secs) = if let Some(ms) = value.strip_suffix("ms") {
+        (ms, 0.001)
+    } else if let Some(s) = value.strip_suffix('s') {
+        (s, 1.0)
+    } else if let Some(m) = value.strip_suffix('m') {
+        (m, 60.0)
+    } else if let Some(h) = value.strip_suffix('h') {
+        (h, 3600.0)
+    } else if let Some(d) = value.strip_suffix('d') {
+        (d, 86400.0)
+    } else {
+        (value, 1.0)
+    };
+
+    number
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|n| n.is_finite() && *n >= 0.0)
+        .map(|n| Duration::from_secs_f64(n * unit_secs))
+        .ok_or(format!(
+            "'{value}' is not a valid duration, e.g. 500ms, 2s or 1m"
+        ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("500ms"), Ok(Duration::from_millis(500)));
+        assert_eq!(parse_duration("2s"), Ok(Duration::from_secs(2)));
+        assert_eq!(parse_duration("1.5"), Ok(Duration::from_millis(1500)));
+        assert_eq!(parse_duration("1m"), Ok(Duration::from_secs(60)));
+        assert_eq!(parse_duration("2d"), Ok(Duration::from_secs(172_800)));
+        assert!(parse_duration("soon").is_err());
+        assert!(parse_duration("-1").is_err());
+    }
+}
@@ -1,7 +1,9 @@
+use crate::config::dotenv::PollerSettings;
 use std::time::Duration;
 
 const MAX_LISTENER_LIFETIME: u64 = 60 * 10;
 
+#[derive(Debug, Clone, Copy)]
 pub(crate) struct PollerConfig {
     pub(crate) max_wait: Duration,
     pub(crate) refresh_interval: Duration,
@@ -13,6 +15,20 @@ impl PollerConfig {
         max_wait: Duration::from_secs(MAX_LISTENER_LIFETIME),
         refresh_interval: Duration::from_millis(1000),
     };
+
+    /// Execution polling with the values configured in `poller`, the defaults otherwise
+    pub(crate) fn from_settings(settings: Option<&PollerSettings>) -> Self {
+        let mut config = Self::default();
+        if let Some(settings) = settings {
+            if let Some(max_wait) = settings.max_wait_ms {
+                config.max_wait = Duration::from_millis(max_wait);
+            }
+            if let Some(refresh_interval) = settings.refresh_interval_ms {
+                config.refresh_interval = Duration::from_millis(refresh_interval);
+            }
+        }
+        config
+    }
 }
 
 impl Default for PollerConfig {
@@ -0,0 +1,509 @@
+use serde_json::Value;
+use std::io::{BufRead, BufReader};
+use std::process::{Child, Command, Output, Stdio};
+use std::time::{Duration, Instant};
+use tempfile::TempDir;
+
+const LIVING_ROOM: &str = "io://0000-1111-2222/1";
+/// Unavailable, the mock gateway rejects its executions although it advertises the commands
+const GARAGE_DOOR: &str = "io://0000-1111-2222/7";
+
+/// Mock gateway running as child process, killed on drop
+struct MockGateway {
+    child: Child,
+    port: u16,
+    home: TempDir,
+}
+
+impl MockGateway {
+    fn start(extra_args: &[&str]) -> Self {
+        let home = TempDir::new().unwrap();
+        let mut child = Command::new(env!("CARGO_BIN_EXE_somfy"))
+            .args(["mock-gateway", "--port", "0", "--speed", "50"])
+            .args(extra_args)
+            .env("HOME", home.path())
+            .env_remove("XDG_CONFIG_HOME")
+            .env_remove("SOMFY_CONFIG_DIR")
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+
+        let mut line = String::new();
+        BufReader::new(child.stdout.take().unwrap())
+            .read_line(&mut line)
+            .unwrap();
+        let port = line
+            .trim()
+            .rsplit(':')
+            .next()
+            .and_then(|p| p.parse().ok())
+            .unwrap_or_else(|| panic!("Unexpected mock gateway output: {line}"));
+
+        Self { child, port, home }
+    }
+
+    /// The CLI pointed at the mock gateway, the API key is taken from env.json
+    fn command(&self) -> Command {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_somfy"));
+        command
+            .args(["--gateway-url", "127.0.0.1", "--gateway-protocol", "http"])
+            .args(["--gateway-port", &self.port.to_string()])
+            .env("HOME", self.home.path())
+            .env_remove("SOMFY_API_KEY")
+            .env_remove("SOMFY_GATEWAY_HOSTNAME")
+            .env_remove("SOMFY_GATEWAY_PORT")
+            .env_remove("SOMFY_GATEWAY_PROTOCOL")
+            .env_remove("SOMFY_PROFILE")
+            .env_remove("XDG_CONFIG_HOME")
+            .env_remove("SOMFY_CONFIG_DIR");
+        command
+    }
+
+    fn cli(&self, api_key: &str, args: &[&str]) -> Output {
+        self.command()
+            .args(["--api-key", api_key])
+            .args(args)
+            .output()
+            .unwrap()
+    }
+
+    fn json(&self, args: &[&str]) -> Value {
+        let output = self.cli("secret", args);
+        assert!(
+            output.status.success(),
+            "{args:?} failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        serde_json::from_slice(&output.stdout).unwrap()
+    }
+}
+
+impl Drop for MockGateway {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+fn closure_of(devices: &Value, device_url: &str) -> Value {
+    let device = devices
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|d| d["deviceURL"] == device_url)
+        .unwrap();
+    device["states"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|s| s["name"] == "core:ClosureState")
+        .unwrap()["value"]
+        .clone()
+}
+
+#[test]
+fn test_ls_lists_simulated_devices() {
+    let gateway = MockGateway::start(&["--token", "secret"]);
+
+    let devices = gateway.json(&["ls"]);
+
+    assert_eq!(devices.as_array().unwrap().len(), 7);
+    assert_eq!(closure_of(&devices, LIVING_ROOM), 0);
+}
+
+#[test]
+fn test_cached_devices_and_labels_after_ls() {
+    let gateway = MockGateway::start(&[]);
+    let devices = gateway.json(&["ls"]);
+
+    let cached = gateway.json(&["ls", "--cached"]);
+    assert_eq!(cached, devices);
+
+    let execution = gateway.json(&["close", "living room", "-N"]);
+    assert!(execution["execId"].is_string());
+    let running = gateway.json(&["current-execs"]);
+    assert_eq!(
+        running[0]["actionGroup"]["actions"][0]["deviceURL"],
+        LIVING_ROOM
+    );
+}
+
+#[test]
+fn test_history_records_executions() {
+    let gateway = MockGateway::start(&[]);
+    let config_dir = gateway.home.path().join(".config").join("somfy-cli");
+    std::fs::create_dir_all(&config_dir).unwrap();
+    let env_file = config_dir.join("env.json");
+    std::fs::write(&env_file, r#"{"history": {}}"#).unwrap();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&env_file, std::fs::Permissions::from_mode(0o600)).unwrap();
+    }
+
+    let execution = gateway.json(&["close", LIVING_ROOM, "-N"]);
+    let history = gateway.json(&["history", LIVING_ROOM, "--kind", "execution"]);
+
+    assert_eq!(history.as_array().unwrap().len(), 1);
+    assert_eq!(history[0]["name"], "close");
+    assert_eq!(history[0]["exec_id"], execution["execId"]);
+}
+
+#[test]
+fn test_config_toml_provides_settings_and_aliases() {
+    let gateway = MockGateway::start(&["--token", "secret"]);
+    let config_dir = gateway.home.path().join(".config").join("somfy-cli");
+    std::fs::create_dir_all(&config_dir).unwrap();
+    let config_file = config_dir.join("config.toml");
+    let write_config = |poller: &str| {
+        std::fs::write(
+            &config_file,
+            format!(
+                "# Local API key\napi_key = \"secret\"\n\n[poller]\n{poller}\n\n[aliases]\nliving = \"{LIVING_ROOM}\"\n"
+            ),
+        )
+        .unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&config_file, std::fs::Permissions::from_mode(0o600)).unwrap();
+        }
+    };
+
+    write_config("max_wait_ms = 5000\nrefresh_interval_ms = 100");
+    std::fs::write(config_dir.join("alias.json"), r#"{"kitchen": "io://1/2"}"#).unwrap();
+    let output = gateway
+        .command()
+        .args(["-S", "json", "close", "living"])
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let execution: Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(execution["state"], "IN_PROGRESS");
+    assert!(config_dir.join("alias.json.bak").exists());
+    assert!(std::fs::read_to_string(&config_file)
+        .unwrap()
+        .contains("kitchen = \"io://1/2\""));
+
+    write_config("max_wait_ms = 5000\nrefresh_interval_ms = 0");
+    let output = gateway
+        .command()
+        .args(["close", "living"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(3));
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("poller.refresh_interval_ms of the default profile has to be greater than 0"));
+}
+
+#[test]
+fn test_legacy_config_folder_is_migrated() {
+    let gateway = MockGateway::start(&[]);
+    let legacy_dir = gateway.home.path().join(".somfy_cli");
+    std::fs::create_dir_all(&legacy_dir).unwrap();
+    std::fs::write(legacy_dir.join("alias.json"), r#"{"living": "io://1"}"#).unwrap();
+
+    let paths = gateway.json(&["config", "path"]);
+    let config_dir = gateway.home.path().join(".config").join("somfy-cli");
+    assert_eq!(paths["source"], "xdg");
+    let aliases = paths["files"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|file| file["name"] == "aliases")
+        .unwrap();
+
+    assert_eq!(aliases["exists"], true);
+    assert_eq!(
+        aliases["path"],
+        config_dir.join("alias.json").to_str().unwrap()
+    );
+    assert!(!legacy_dir.exists());
+
+    let custom_dir = gateway.home.path().join("custom");
+    let output = gateway
+        .command()
+        .args(["--config-dir", custom_dir.to_str().unwrap()])
+        .args(["-S", "json", "config", "path"])
+        .output()
+        .unwrap();
+    let paths: Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(paths["source"], "flag");
+    assert_eq!(
+        paths["files"][0]["path"],
+        custom_dir.join("env.json").to_str().unwrap()
+    );
+    assert_eq!(paths["files"][0]["exists"], false);
+}
+
+#[test]
+fn test_activated_token_is_stored_and_accepted() {
+    let gateway = MockGateway::start(&["--token", "secret"]);
+    let cloud_url = format!(
+        "http://127.0.0.1:{}/enduser-mobile-web/enduserAPI",
+        gateway.port
+    );
+    let token = |args: &[&str]| {
+        gateway
+            .command()
+            .args(["--profile", "home", "token"])
+            .args(args)
+            .env("SOMFY_CLOUD_URL", &cloud_url)
+            .env("SOMFY_CLOUD_PASSWORD", "password")
+            .output()
+            .unwrap()
+    };
+
+    let activation = token(&[
+        "activate",
+        "--user",
+        "me@example.com",
+        "--gateway-id",
+        "0000-1111-2222",
+    ]);
+    assert!(
+        activation.status.success(),
+        "{}",
+        String::from_utf8_lossy(&activation.stderr)
+    );
+    let env_file = gateway
+        .home
+        .path()
+        .join(".config")
+        .join("somfy-cli")
+        .join("env.json");
+    let stored: Value = serde_json::from_slice(&std::fs::read(env_file).unwrap()).unwrap();
+    assert_eq!(stored["profiles"]["home"]["cloud_user"], "me@example.com");
+    assert!(stored["profiles"]["home"].get("cloud_url").is_none());
+
+    let listed = token(&["ls"]);
+    let tokens: Value = serde_json::from_slice(&listed.stdout).unwrap();
+    assert_eq!(tokens[0]["label"], "somfy-cli");
+
+    let ls = gateway
+        .command()
+        .args(["--profile", "home", "ls"])
+        .output()
+        .unwrap();
+    assert!(ls.status.success());
+
+    let revoke = token(&["revoke", tokens[0]["uuid"].as_str().unwrap()]);
+    assert!(revoke.status.success());
+    let rejected = gateway
+        .command()
+        .args(["--profile", "home", "ls"])
+        .output()
+        .unwrap();
+    assert_eq!(rejected.status.code(), Some(4));
+}
+
+#[test]
+fn test_close_moves_device_over_time() {
+    let gateway = MockGateway::start(&[]);
+
+    let execution = gateway.json(&["close", LIVING_ROOM]);
+    assert_eq!(
+        execution["actionGroup"]["actions"][0]["deviceURL"],
+        LIVING_ROOM
+    );
+
+    let deadline = Instant::now() + Duration::from_secs(10);
+    loop {
+        let closure = closure_of(&gateway.json(&["ls"]), LIVING_ROOM);
+        if closure == 100 {
+            break;
+        }
+        assert!(
+            Instant::now() < deadline,
+            "device did not arrive, at {closure}"
+        );
+        std::thread::sleep(Duration::from_millis(200));
+    }
+    assert!(gateway
+        .json(&["current-execs"])
+        .as_array()
+        .unwrap()
+        .is_empty());
+}
+
+#[test]
+fn test_running_execution_is_listed() {
+    let gateway = MockGateway::start(&[]);
+
+    let started = gateway.json(&["position", LIVING_ROOM, "80", "-N"]);
+    let executions = gateway.json(&["current-execs"]);
+
+    assert_eq!(executions[0]["id"], started["execId"]);
+}
+
+#[test]
+fn test_relative_position_starts_from_current_closure() {
+    let gateway = MockGateway::start(&[]);
+
+    let execution = gateway.json(&["position", "io://0000-1111-2222/2", "-30", "-N"]);
+    let executions = gateway.json(&["current-execs"]);
+
+    assert!(execution["execId"].is_string());
+    assert_eq!(
+        executions[0]["actionGroup"]["actions"][0]["commands"][0]["parameters"][0],
+        "70"
+    );
+}
+
+#[test]
+fn test_tagged_aliases_address_several_devices() {
+    let gateway = MockGateway::start(&[]);
+    let terrace = "io://0000-1111-2222/2";
+    gateway.json(&["alias", "add", "living", LIVING_ROOM, "--tag", "south"]);
+    let aliases = gateway.json(&[
+        "alias",
+        "add",
+        "terrace",
+        terrace,
+        "--tag",
+        "south",
+        "--default-position",
+        "40",
+    ]);
+    assert_eq!(aliases["terrace"]["tags"][0], "south");
+
+    let tagged = gateway.json(&["ls", "--tag", "south"]);
+    assert_eq!(tagged.as_array().unwrap().len(), 2);
+
+    gateway.json(&["close", "tag:south", "-N"]);
+    let executions = gateway.json(&["current-execs"]);
+    assert_eq!(
+        executions[0]["actionGroup"]["actions"]
+            .as_array()
+            .unwrap()
+            .len(),
+        2
+    );
+
+    let execution = gateway.json(&["position", "terrace"]);
+    assert_eq!(
+        execution["actionGroup"]["actions"][0]["commands"][0]["parameters"][0],
+        "40"
+    );
+}
+
+#[test]
+fn test_out_of_range_position_is_rejected() {
+    let gateway = MockGateway::start(&[]);
+
+    let output = gateway.cli("secret", &["position", LIVING_ROOM, "150"]);
+
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn test_batch_reports_every_line() {
+    let gateway = MockGateway::start(&[]);
+    let script = gateway.home.path().join("script.txt");
+    std::fs::write(
+        &script,
+        format!("# Evening\nclose {LIVING_ROOM} -N\nposition {GARAGE_DOOR} 50\nwait 100ms\n"),
+    )
+    .unwrap();
+
+    let output = gateway.cli(
+        "secret",
+        &["batch", "--continue-on-error", script.to_str().unwrap()],
+    );
+    let report: Value = serde_json::from_slice(&output.stdout).unwrap();
+
+    assert_eq!(output.status.code(), Some(7));
+    assert_eq!(report[0]["line"], 2);
+    assert_eq!(report[0]["status"], "ok");
+    assert_eq!(report[1]["status"], "failed");
+    assert_eq!(report[2]["status"], "ok");
+}
+
+fn json_error(output: &Output) -> Value {
+    serde_json::from_slice(&output.stderr).unwrap()
+}
+
+#[test]
+fn test_wrong_api_key_is_rejected() {
+    let gateway = MockGateway::start(&["--token", "secret"]);
+
+    let output = gateway.cli("wrong", &["ls"]);
+
+    assert_eq!(output.status.code(), Some(4));
+    assert_eq!(json_error(&output)["error"]["kind"], "auth");
+}
+
+#[test]
+fn test_unknown_device_exit_code() {
+    let gateway = MockGateway::start(&[]);
+
+    let output = gateway.cli("secret", &["open", "no-such-alias"]);
+
+    assert_eq!(output.status.code(), Some(6));
+    assert_eq!(json_error(&output)["error"]["exit_code"], 6);
+}
+
+#[test]
+fn test_rejected_execution_exit_code() {
+    let gateway = MockGateway::start(&[]);
+
+    let output = gateway.cli("secret", &["position", GARAGE_DOOR, "50"]);
+
+    assert_eq!(output.status.code(), Some(7));
+    assert_eq!(json_error(&output)["error"]["kind"], "execution_failed");
+    assert!(gateway
+        .json(&["current-execs"])
+        .as_array()
+        .unwrap()
+        .is_empty());
+}
+
+#[test]
+fn test_commands_lists_supported_commands() {
+    let gateway = MockGateway::start(&[]);
+
+    let capabilities = gateway.json(&["commands", "io://0000-1111-2222/5"]);
+
+    assert_eq!(capabilities["definition"]["uiClass"], "Light");
+    assert_eq!(
+        capabilities["definition"]["commands"][0]["commandName"],
+        "on"
+    );
+    let output = gateway.cli("secret", &["close", "io://0000-1111-2222/5"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(json_error(&output)["error"]["message"]
+        .as_str()
+        .unwrap()
+        .contains("supported commands: on, off"));
+    let output = gateway.cli("secret", &["position", "io://0000-1111-2222/6", "50"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(json_error(&output)["error"]["kind"], "usage");
+    assert!(json_error(&output)["error"]["message"]
+        .as_str()
+        .unwrap()
+        .contains("does not support setClosure"));
+}
+
+#[test]
+fn test_unreachable_gateway_exit_code() {
+    let port = {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.local_addr().unwrap().port()
+    };
+    let home = TempDir::new().unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_somfy"))
+        .args(["--gateway-url", "127.0.0.1", "--gateway-protocol", "http"])
+        .args(["--gateway-port", &port.to_string(), "--api-key", "secret"])
+        .args(["-S", "table", "ls"])
+        .env("HOME", home.path())
+        .output()
+        .unwrap();
+
+    assert_eq!(output.status.code(), Some(5));
+    assert!(String::from_utf8_lossy(&output.stderr).starts_with("Error: "));
+}